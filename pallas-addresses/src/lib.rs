@@ -320,9 +320,9 @@ macro_rules! parse_shelley_fn {
     ($name:tt, $payment:tt, pointer) => {
         fn $name(header: u8, payload: &[u8]) -> Result<Address, Error> {
             let net = parse_network(header);
-            let h1 = slice_to_hash(&payload[0..=27])?;
+            let h1 = slice_to_hash(payload.get(0..28).unwrap_or(payload))?;
             let p1 = ShelleyPaymentPart::$payment(h1);
-            let p2 = ShelleyDelegationPart::from_pointer(&payload[28..])?;
+            let p2 = ShelleyDelegationPart::from_pointer(payload.get(28..).unwrap_or_default())?;
             let addr = ShelleyAddress(net, p1, p2);
 
             Ok(addr.into())
@@ -331,9 +331,9 @@ macro_rules! parse_shelley_fn {
     ($name:tt, $payment:tt, $delegation:tt) => {
         fn $name(header: u8, payload: &[u8]) -> Result<Address, Error> {
             let net = parse_network(header);
-            let h1 = slice_to_hash(&payload[0..=27])?;
+            let h1 = slice_to_hash(payload.get(0..28).unwrap_or(payload))?;
             let p1 = ShelleyPaymentPart::$payment(h1);
-            let h2 = slice_to_hash(&payload[28..=55])?;
+            let h2 = slice_to_hash(payload.get(28..56).unwrap_or_default())?;
             let p2 = ShelleyDelegationPart::$delegation(h2);
             let addr = ShelleyAddress(net, p1, p2);
 
@@ -343,7 +343,7 @@ macro_rules! parse_shelley_fn {
     ($name:tt, $payment:tt) => {
         fn $name(header: u8, payload: &[u8]) -> Result<Address, Error> {
             let net = parse_network(header);
-            let h1 = slice_to_hash(&payload[0..=27])?;
+            let h1 = slice_to_hash(payload.get(0..28).unwrap_or(payload))?;
             let p1 = ShelleyPaymentPart::$payment(h1);
             let addr = ShelleyAddress(net, p1, ShelleyDelegationPart::Null);
 
@@ -356,7 +356,7 @@ macro_rules! parse_stake_fn {
     ($name:tt, $type:tt) => {
         fn $name(header: u8, payload: &[u8]) -> Result<Address, Error> {
             let net = parse_network(header);
-            let p1 = StakePayload::$type(&payload[0..=27])?;
+            let p1 = StakePayload::$type(payload.get(0..28).unwrap_or(payload))?;
             let addr = StakeAddress(net, p1);
 
             Ok(addr.into())
@@ -882,6 +882,16 @@ mod tests {
         assert!(matches!(addr, Ok(Address::Shelley(_))));
     }
 
+    #[test]
+    fn test_truncated_payload() {
+        for hex in ["6100", "01aabb", "4000", "e1"] {
+            assert!(matches!(
+                Address::from_hex(hex),
+                Err(Error::InvalidHashSize(_))
+            ));
+        }
+    }
+
     #[test]
     fn test_shelley_into_stake() {
         let addr = Address::from_bech32("addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x").unwrap();
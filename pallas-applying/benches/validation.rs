@@ -0,0 +1,209 @@
+//! Throughput of Byron tx validation, over a single tx and over a synthetic
+//! block validated against a preloaded UTxO set
+//!
+//! Run with `cargo bench -p pallas-applying`. Everything is built in memory,
+//! so no chain data is needed. Each scenario is measured both in txs and in
+//! bytes per second.
+
+use std::{borrow::Cow, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use cryptoxide::hashing::sha3_256;
+use pallas_applying::{
+    byron::validate_byron_tx,
+    environment::{ByronProtParams, Strictness},
+    utxo_key,
+    witnesses::Ed25519Verifier,
+    UTxOs,
+};
+use pallas_codec::{
+    minicbor,
+    utils::{CborWrap, EmptyMap, MaybeIndefArray, TagWrap},
+};
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::SecretKey,
+};
+use pallas_primitives::byron::{Address, MintedTxPayload, Twit, Tx, TxIn, TxOut, TxPayload};
+use pallas_traverse::MultiEraOutput;
+
+const PROT_PPS: ByronProtParams = ByronProtParams {
+    summand: 155381,
+    multiplier: 44,
+    max_tx_size: 4096,
+    max_tx_outputs: None,
+    protocol_magic: 764824073,
+};
+
+const BLOCK_TXS: usize = 500;
+
+/// The extended public key of a secret key, with an all-zeros chain code
+fn xpub(key: &SecretKey) -> Vec<u8> {
+    [key.public_key().as_ref(), &[0; 32]].concat()
+}
+
+/// The pubkey address of a key, with no attributes
+fn address(key: &SecretKey) -> Address {
+    // the root hashes [addrtype, [addrtype, xpub], attributes]
+    let mut spending = minicbor::Encoder::new(Vec::new());
+    spending.array(3).unwrap();
+    spending.u8(0).unwrap();
+    spending
+        .array(2)
+        .unwrap()
+        .u8(0)
+        .unwrap()
+        .bytes(&xpub(key))
+        .unwrap();
+    spending.map(0).unwrap();
+
+    let root = Hasher::<224>::hash(&sha3_256(spending.writer()));
+
+    let mut payload = minicbor::Encoder::new(Vec::new());
+    payload.array(3).unwrap();
+    payload.bytes(root.as_ref()).unwrap();
+    payload.map(0).unwrap();
+    payload.u64(0).unwrap();
+
+    let payload = payload.into_writer();
+    let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(&payload);
+
+    Address {
+        payload: TagWrap(payload.into()),
+        crc,
+    }
+}
+
+/// A two-input, two-output tx spending the outputs `seed#0` and `seed#1`,
+/// both locked by the same key, which signs it once per input
+fn tx(seed: u32) -> (Vec<u8>, Vec<(TxIn, TxOut)>) {
+    let key = SecretKey::from([1; SecretKey::SIZE]);
+
+    let mut id = [0; 32];
+    id[..4].copy_from_slice(&seed.to_be_bytes());
+    let id = Hash::new(id);
+
+    let inputs: Vec<_> = (0..2)
+        .map(|index| TxIn::Variant0(CborWrap((id, index))))
+        .collect();
+
+    let resolved = inputs
+        .iter()
+        .map(|x| {
+            let output = TxOut {
+                address: address(&key),
+                amount: 2_000_000,
+            };
+
+            (x.clone(), output)
+        })
+        .collect();
+
+    let outputs = vec![
+        TxOut {
+            address: address(&key),
+            amount: 3_000_000,
+        },
+        TxOut {
+            address: address(&key),
+            amount: 800_000,
+        },
+    ];
+
+    let transaction = Tx {
+        inputs: MaybeIndefArray::Def(inputs),
+        outputs: MaybeIndefArray::Def(outputs),
+        attributes: EmptyMap,
+    };
+
+    // tag ‖ cbor(protocol_magic) ‖ cbor(tx id)
+    let message = [
+        vec![0x01],
+        minicbor::to_vec(PROT_PPS.protocol_magic).unwrap(),
+        minicbor::to_vec(Hasher::<256>::hash_cbor(&transaction)).unwrap(),
+    ]
+    .concat();
+
+    let witness = Twit::PkWitness(CborWrap((
+        xpub(&key).into(),
+        key.sign(message).as_ref().to_vec().into(),
+    )));
+
+    let payload = TxPayload {
+        transaction,
+        witness: MaybeIndefArray::Def(vec![witness.clone(), witness]),
+    };
+
+    (minicbor::to_vec(payload).unwrap(), resolved)
+}
+
+fn utxos(entries: impl Iterator<Item = (TxIn, TxOut)>) -> UTxOs<'static> {
+    entries
+        .map(|(input, output)| {
+            (
+                utxo_key(&input).unwrap(),
+                MultiEraOutput::Byron(Box::new(Cow::Owned(output))),
+            )
+        })
+        .collect()
+}
+
+fn validate(mtxp: &MintedTxPayload, utxos: &UTxOs) {
+    validate_byron_tx(
+        black_box(mtxp),
+        utxos,
+        &PROT_PPS,
+        Strictness::Lenient,
+        &Ed25519Verifier,
+    )
+    .unwrap();
+}
+
+fn single_tx(c: &mut Criterion) {
+    let (bytes, resolved) = tx(0);
+    let utxos = utxos(resolved.into_iter());
+    let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+    let mut group = c.benchmark_group("single_tx");
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("txs", |b| b.iter(|| validate(&mtxp, &utxos)));
+
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("bytes", |b| b.iter(|| validate(&mtxp, &utxos)));
+
+    group.finish();
+}
+
+fn block(c: &mut Criterion) {
+    let txs: Vec<_> = (0..BLOCK_TXS as u32).map(tx).collect();
+    let utxos = utxos(txs.iter().flat_map(|(_, x)| x.iter().cloned()));
+
+    let payloads: Vec<MintedTxPayload> = txs
+        .iter()
+        .map(|(x, _)| minicbor::decode(x).unwrap())
+        .collect();
+
+    let size: usize = txs.iter().map(|(x, _)| x.len()).sum();
+
+    let validate_all = || {
+        for mtxp in payloads.iter() {
+            validate(mtxp, &utxos);
+        }
+    };
+
+    let mut group = c.benchmark_group(format!("block_of_{BLOCK_TXS}_txs"));
+
+    group.throughput(Throughput::Elements(BLOCK_TXS as u64));
+    group.bench_function("txs", |b| b.iter(validate_all));
+
+    group.throughput(Throughput::Bytes(size as u64));
+    group.bench_function("bytes", |b| b.iter(validate_all));
+
+    group.finish();
+}
+
+criterion_group!(benches, single_tx, block);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Arbitrary bytes decoded and validated as a tx, which must never panic
+//!
+//! Run with `cargo fuzz run validate_tx_bytes` from `pallas-applying`. The
+//! first byte picks the era the rest is decoded as, so both the Byron and the
+//! Shelley decode paths get covered.
+
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use libfuzzer_sys::fuzz_target;
+use pallas_applying::{
+    environment::{
+        ByronProtParams, Environment, GenesisDelegates, ProtocolParams, ShelleyProtParams,
+    },
+    validator::validate_tx_bytes,
+    UTxOs,
+};
+use pallas_traverse::Era;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((selector, bytes)) = data.split_first() else {
+        return;
+    };
+
+    let (era, prot_params, protocol_version) = match selector % 2 {
+        0 => (
+            Era::Byron,
+            ProtocolParams::Byron(ByronProtParams {
+                summand: 155381,
+                multiplier: 44,
+                max_tx_size: 4096,
+                max_tx_outputs: None,
+                protocol_magic: 764824073,
+            }),
+            (1, 0),
+        ),
+        _ => (
+            Era::Shelley,
+            ProtocolParams::Shelley(ShelleyProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+            }),
+            (2, 0),
+        ),
+    };
+
+    let env = Environment {
+        prot_params,
+        protocol_version,
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+    };
+
+    let _ = validate_tx_bytes(era, bytes, &UTxOs::new(), &env);
+});
@@ -0,0 +1,823 @@
+//! Validation of transactions from the Alonzo era onwards
+
+use std::collections::HashSet;
+
+use pallas_addresses::{Address, ShelleyPaymentPart};
+use pallas_crypto::hash::{Hash, Hasher};
+use pallas_primitives::{
+    alonzo::{Certificate, RedeemerTag, StakeCredential},
+    babbage::{PseudoDatumOption, Script},
+};
+use pallas_traverse::{ComputeHash, MultiEraOutput, MultiEraTx};
+
+use crate::{
+    certs::{
+        check_certificate_scripts, check_contradictory_certificates, check_pool_registrations,
+    },
+    environment::{AlonzoProtParams, Strictness},
+    fee::{check_fee, check_tx_size},
+    metadata::check_aux_data,
+    mint::check_mint,
+    native_script::check_native_scripts,
+    script_data::check_script_data_hash,
+    shelley_ma::check_preservation_of_value,
+    structure::{
+        check_current_slot, check_inputs_not_empty, check_inputs_unique, check_validity_interval,
+    },
+    value::{check_output_values, check_outputs_within_inputs},
+    witnesses::{
+        check_input_witness_eras, check_vkey_witnesses, check_withdrawals, verify_tx_witnesses,
+    },
+    UtxoStore, ValidationError, ValidationResult, ValidationWarning,
+};
+
+/// Validates a transaction of the Alonzo era
+///
+/// Its validity interval is only checked against the current `slot` when
+/// given.
+pub fn validate_alonzo_tx<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &AlonzoProtParams,
+    slot: Option<u64>,
+) -> ValidationResult {
+    check_inputs_not_empty(metx)?;
+    check_inputs_unique(metx)?;
+    check_validity_interval(metx)?;
+    check_current_slot(metx, slot)?;
+    check_output_values(metx.outputs(), |_| 0)?;
+    check_aux_data(metx)?;
+    check_mint(metx)?;
+    check_native_scripts(metx, utxos)?;
+    check_tx_size(metx, prot_pps.max_tx_size)?;
+    check_fee(metx, prot_pps)?;
+    check_outputs_within_inputs(metx, utxos, prot_pps.key_deposit)?;
+    check_preservation_of_value(
+        metx,
+        utxos,
+        prot_pps.key_deposit,
+        prot_pps.pool_deposit,
+        |_| false,
+    )?;
+    check_failed_tx_collateral(metx)?;
+    check_collateral_inputs(metx, prot_pps.max_collateral_inputs)?;
+    check_input_witness_eras(metx, utxos)?;
+    verify_tx_witnesses(metx)?;
+    check_vkey_witnesses(metx, utxos)?;
+    check_withdrawals(metx)?;
+    check_certificate_scripts(metx, utxos)?;
+    check_pool_registrations(metx)?;
+    check_contradictory_certificates(metx)?;
+    check_collateral_addresses(metx, utxos)?;
+    check_collateral_amount(metx, utxos, prot_pps.collateral_percentage)?;
+    check_datums_supplied(metx, utxos)?;
+    check_redeemer_purposes(metx, utxos)?;
+    check_script_data_hash(metx, utxos, &prot_pps.cost_models)
+}
+
+/// Checks the number of collateral inputs against `maxCollateralInputs`
+///
+/// Takes the bare limit so Babbage, which keeps the same parameter, can share
+/// the check.
+pub fn check_collateral_inputs(metx: &MultiEraTx, max: u32) -> ValidationResult {
+    let count = metx.collateral().len() as u32;
+
+    match count <= max {
+        true => Ok(()),
+        false => Err(ValidationError::TooManyCollateralInputs { count, max }),
+    }
+}
+
+/// Checks that no collateral input is locked by a script
+///
+/// Collateral has to be spendable with just a signature, so it must sit at a
+/// key (or bootstrap) address.
+pub fn check_collateral_addresses<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> ValidationResult {
+    for input in metx.collateral() {
+        let output_ref = input.output_ref();
+
+        let output = utxos
+            .resolve(&output_ref)
+            .ok_or_else(|| ValidationError::InputNotInUTxO(output_ref.clone()))?;
+
+        if let Ok(Address::Shelley(address)) = output.address() {
+            if let ShelleyPaymentPart::Script(_) = address.payment() {
+                return Err(ValidationError::CollateralIsScriptLocked(output_ref));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the collateral a tx running scripts has to provide for its fee
+///
+/// The ledger requires `collateral * 100 >= fee * collateralPercentage`, which
+/// over the integers is `ceil(fee * collateralPercentage / 100)`, computed by
+/// integer ceil division of the 128-bit product, with no floats involved.
+/// Saturates at `u64::MAX`.
+pub fn required_collateral(fee: u64, collateral_percentage: u64) -> u64 {
+    let required = (fee as u128 * collateral_percentage as u128).div_ceil(100);
+
+    required.try_into().unwrap_or(u64::MAX)
+}
+
+/// Checks that the lovelace put up as collateral, net of the collateral
+/// return, covers [required_collateral] for the fee of a tx running scripts
+///
+/// Txs without redeemers run no scripts and need no collateral.
+pub fn check_collateral_amount<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    collateral_percentage: u64,
+) -> ValidationResult {
+    if metx.redeemers().is_empty() {
+        return Ok(());
+    }
+
+    let mut total: u64 = 0;
+
+    for input in metx.collateral() {
+        let output = utxos
+            .resolve(&input.output_ref())
+            .ok_or_else(|| ValidationError::InputNotInUTxO(input.output_ref()))?;
+
+        total = total
+            .checked_add(output.lovelace_amount())
+            .ok_or(ValidationError::ValueOverflow)?;
+    }
+
+    let returned = metx.collateral_return().map(|x| x.lovelace_amount());
+    let provided = total.saturating_sub(returned.unwrap_or_default());
+    let required = required_collateral(metx.fee().unwrap_or_default(), collateral_percentage);
+
+    match provided >= required {
+        true => Ok(()),
+        false => Err(ValidationError::CollateralInsufficient { provided, required }),
+    }
+}
+
+/// Checks that a tx failing phase-2 validation has collateral to consume
+///
+/// Such a tx consumes its collateral inputs instead of its regular ones, so
+/// without them it would consume nothing at all.
+pub fn check_failed_tx_collateral(metx: &MultiEraTx) -> ValidationResult {
+    match !metx.is_valid() && metx.collateral().is_empty() {
+        true => Err(ValidationError::NoCollateralForFailedTx),
+        false => Ok(()),
+    }
+}
+
+/// Checks that outputs locked by a key don't carry a datum hash
+///
+/// The ledger accepts these outputs, so the check only applies in
+/// [Strictness::Strict] mode: the datum is never used to spend the output and
+/// is most likely the result of picking the wrong address.
+pub fn check_datums_on_key_addresses(
+    metx: &MultiEraTx,
+    strictness: Strictness,
+) -> ValidationResult {
+    if strictness == Strictness::Lenient {
+        return Ok(());
+    }
+
+    for (index, output) in metx.outputs().iter().enumerate() {
+        if !matches!(output.datum(), Some(PseudoDatumOption::Hash(_))) {
+            continue;
+        }
+
+        if !is_locked_by_script(output) {
+            return Err(ValidationError::DatumOnKeyAddress(index));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the witness set carries the datum of every spent output locked
+/// by a script through a datum hash
+///
+/// Inputs missing from the UTxO set are left for the checks reporting them.
+pub fn check_datums_supplied<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> ValidationResult {
+    let supplied = supplied_datums(metx);
+
+    for input in metx.inputs() {
+        let output = match utxos.resolve(&input.output_ref()) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        if let Some(hash) = datum_hash(&output).filter(|_| is_locked_by_script(&output)) {
+            if !supplied.contains(&hash) {
+                return Err(ValidationError::DatumMissing(hash));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the datums of the witness set referenced neither by a spent or
+/// referenced output nor by an output of the tx
+///
+/// The ledger accepts these extra datums, but they usually come from a
+/// mistake in the construction of the transaction.
+pub fn orphan_datums<'b>(metx: &MultiEraTx, utxos: &impl UtxoStore<'b>) -> Vec<Hash<32>> {
+    let (inputs, reference_inputs) = (metx.inputs(), metx.reference_inputs());
+
+    let resolved = inputs
+        .iter()
+        .chain(reference_inputs.iter())
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .filter_map(|x| datum_hash(&x))
+        .collect::<Vec<_>>();
+
+    let produced = metx.outputs().iter().filter_map(datum_hash).collect();
+
+    let referenced: HashSet<_> = [resolved, produced].concat().into_iter().collect();
+
+    metx.plutus_data()
+        .iter()
+        .map(|x| Hasher::<256>::hash(x.raw_cbor()))
+        .filter(|x| !referenced.contains(x))
+        .collect()
+}
+
+/// Fails with [ValidationError::OrphanDatum] on the first orphan datum, only
+/// in [Strictness::Strict] mode
+pub fn check_orphan_datums<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    strictness: Strictness,
+) -> ValidationResult {
+    if strictness == Strictness::Lenient {
+        return Ok(());
+    }
+
+    match orphan_datums(metx, utxos).first() {
+        Some(hash) => Err(ValidationError::OrphanDatum(*hash)),
+        None => Ok(()),
+    }
+}
+
+/// Reports every orphan datum as a [ValidationWarning::OrphanDatum]
+pub fn orphan_datum_warnings<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> Vec<ValidationWarning> {
+    orphan_datums(metx, utxos)
+        .into_iter()
+        .map(ValidationWarning::OrphanDatum)
+        .collect()
+}
+
+/// Checks that the redeemers of a tx are exactly the ones its scripts need,
+/// one per purpose
+///
+/// Spending an input locked by a script, minting under a policy, a
+/// certificate with a script credential and withdrawing from a script reward
+/// account each call for a redeemer, pointing at the input, policy,
+/// certificate or account by its index among the sorted inputs, the sorted
+/// policies, the certificates and the sorted reward accounts respectively.
+/// Reward accounts sort by network and then by credential, script ones first.
+/// Native scripts, be they in the witness set or referenced, run without one.
+/// Two redeemers for the same purpose fail with
+/// [ValidationError::DuplicateRedeemer]; a redeemer with no such purpose, or a
+/// purpose left without its redeemer, with
+/// [ValidationError::RedeemerPurposeMismatch].
+pub fn check_redeemer_purposes<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> ValidationResult {
+    let native = native_script_hashes(metx, utxos);
+
+    let mut inputs: Vec<_> = metx.inputs().iter().map(|x| x.output_ref()).collect();
+    inputs.sort_by_key(|x| (*x.hash(), x.index()));
+    let spend = inputs
+        .iter()
+        .map(|x| utxos.resolve(x).and_then(|x| payment_script_hash(&x)))
+        .collect();
+
+    let mut policies: Vec<_> = metx.mints().iter().map(|x| Some(*x.policy())).collect();
+    policies.sort();
+
+    let cert = metx
+        .certs()
+        .iter()
+        .map(|x| match x.as_alonzo() {
+            Some(Certificate::StakeDeregistration(StakeCredential::Scripthash(x)))
+            | Some(Certificate::StakeDelegation(StakeCredential::Scripthash(x), _)) => Some(*x),
+            _ => None,
+        })
+        .collect();
+
+    let withdrawals = metx.withdrawals();
+    let mut accounts: Vec<&[u8]> = withdrawals
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|(x, _)| x)
+        .collect();
+    accounts.sort_by_key(|x| reward_account_order(x));
+    let reward = accounts.into_iter().map(reward_script_hash).collect();
+
+    let mut required = vec![];
+
+    for (tag, hashes) in [
+        (RedeemerTag::Spend, spend),
+        (RedeemerTag::Mint, policies),
+        (RedeemerTag::Cert, cert),
+        (RedeemerTag::Reward, reward),
+    ] {
+        for (index, hash) in hashes.into_iter().enumerate() {
+            if hash.is_some_and(|x: Hash<28>| !native.contains(&x)) {
+                required.push((tag.clone(), index as u32));
+            }
+        }
+    }
+
+    let present: Vec<_> = metx
+        .redeemers()
+        .iter()
+        .map(|x| (x.tag.clone(), x.index))
+        .collect();
+
+    let repeated = present
+        .iter()
+        .enumerate()
+        .find(|(index, x)| present[..*index].contains(x));
+
+    if let Some((_, (tag, index))) = repeated {
+        return Err(ValidationError::DuplicateRedeemer {
+            tag: tag.clone(),
+            index: *index,
+        });
+    }
+
+    let unmatched = present
+        .iter()
+        .find(|x| !required.contains(x))
+        .or_else(|| required.iter().find(|x| !present.contains(x)));
+
+    match unmatched {
+        Some((tag, index)) => Err(ValidationError::RedeemerPurposeMismatch {
+            tag: tag.clone(),
+            index: *index,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Hashes of the native scripts of the witness set and of the reference
+/// scripts of the spent and referenced outputs
+fn native_script_hashes<'b>(metx: &MultiEraTx, utxos: &impl UtxoStore<'b>) -> HashSet<Hash<28>> {
+    let (inputs, reference_inputs) = (metx.inputs(), metx.reference_inputs());
+
+    let referenced = inputs
+        .iter()
+        .chain(reference_inputs.iter())
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .filter_map(|x| match x.script_ref().map(|x| &x.0) {
+            Some(Script::NativeScript(x)) => Some(x.compute_hash()),
+            _ => None,
+        });
+
+    metx.native_scripts()
+        .iter()
+        .map(|x| x.compute_hash())
+        .chain(referenced)
+        .collect()
+}
+
+fn payment_script_hash(output: &MultiEraOutput) -> Option<Hash<28>> {
+    match output.address() {
+        Ok(Address::Shelley(x)) => match x.payment() {
+            ShelleyPaymentPart::Script(x) => Some(*x),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Key sorting reward accounts the way the ledger does: by network, then by
+/// stake credential, script credentials coming before key ones
+fn reward_account_order(account: &[u8]) -> (u8, bool, &[u8]) {
+    match account {
+        [header, hash @ ..] => (header & 0x0f, header & 0x10 == 0, hash),
+        [] => (0, false, account),
+    }
+}
+
+/// Script hash of a reward account, whose header has its four most
+/// significant bits set when the stake credential is a script
+fn reward_script_hash(account: &[u8]) -> Option<Hash<28>> {
+    match account {
+        [header, hash @ ..] if header & 0xf0 == 0xf0 => {
+            <[u8; 28]>::try_from(hash).ok().map(Hash::new)
+        }
+        _ => None,
+    }
+}
+
+fn datum_hash(output: &MultiEraOutput) -> Option<Hash<32>> {
+    match output.datum() {
+        Some(PseudoDatumOption::Hash(x)) => Some(x),
+        _ => None,
+    }
+}
+
+fn supplied_datums(metx: &MultiEraTx) -> HashSet<Hash<32>> {
+    metx.plutus_data()
+        .iter()
+        .map(|x| Hasher::<256>::hash(x.raw_cbor()))
+        .collect()
+}
+
+fn is_locked_by_script(output: &MultiEraOutput) -> bool {
+    matches!(
+        output.address(),
+        Ok(Address::Shelley(x)) if matches!(x.payment(), ShelleyPaymentPart::Script(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::*, UTxOs};
+
+    use pallas_codec::{minicbor, utils::KeyValuePairs};
+    use pallas_crypto::hash::Hash;
+    use pallas_primitives::{
+        alonzo::{ExUnits, NativeScript, PlutusData, Redeemer, TransactionOutput, Tx},
+        babbage,
+    };
+    use pallas_traverse::{Era, OutputRef};
+
+    fn datum_outputs() -> Vec<u8> {
+        let datum_hash = Some(Hash::new([7; 32]));
+
+        let outputs = vec![
+            alonzo_output(&script_address(Hash::new([1; 28])), 2_000_000),
+            TransactionOutput {
+                datum_hash,
+                ..alonzo_output(&script_address(Hash::new([1; 28])), 2_000_000)
+            },
+            TransactionOutput {
+                datum_hash,
+                ..alonzo_output(&key_address(key_hash(&secret_key(2))), 2_000_000)
+            },
+        ];
+
+        let body = alonzo_body(vec![tx_input(1, 0)], outputs, 200_000);
+        alonzo_tx(body, &[secret_key(1)], vec![])
+    }
+
+    #[test]
+    fn too_many_collateral_inputs() {
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.collateral = Some((2..5).map(|x| tx_input(x, 0)).collect());
+        let bytes = alonzo_tx(body.clone(), &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(check_collateral_inputs(&metx, 3), Ok(()));
+
+        body.collateral = Some((2..6).map(|x| tx_input(x, 0)).collect());
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_collateral_inputs(&metx, 3),
+            Err(ValidationError::TooManyCollateralInputs { count: 4, max: 3 })
+        );
+    }
+
+    #[test]
+    fn script_locked_collateral() {
+        let utxos = alonzo_utxos(&[
+            (
+                tx_input(2, 0),
+                alonzo_output(&key_address(key_hash(&secret_key(1))), 5_000_000),
+            ),
+            (
+                tx_input(3, 0),
+                alonzo_output(&script_address(Hash::new([1; 28])), 5_000_000),
+            ),
+        ]);
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.collateral = Some(vec![tx_input(2, 0), tx_input(3, 0)]);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_collateral_addresses(&metx, &utxos),
+            Err(ValidationError::CollateralIsScriptLocked(OutputRef::new(
+                Hash::new([3; 32]),
+                0
+            )))
+        );
+    }
+
+    #[test]
+    fn failed_tx_without_collateral() {
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body.clone(), &[secret_key(1)], vec![]);
+
+        let mut tx: Tx = minicbor::decode(&bytes).unwrap();
+        tx.success = false;
+        let bytes = minicbor::to_vec(&tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_failed_tx_collateral(&metx),
+            Err(ValidationError::NoCollateralForFailedTx)
+        );
+
+        body.collateral = Some(vec![tx_input(2, 0)]);
+        tx.transaction_body = body;
+        let bytes = minicbor::to_vec(&tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(check_failed_tx_collateral(&metx), Ok(()));
+    }
+
+    #[test]
+    fn datum_on_key_address_strict() {
+        let bytes = datum_outputs();
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_datums_on_key_addresses(&metx, Strictness::Strict),
+            Err(ValidationError::DatumOnKeyAddress(2))
+        );
+    }
+
+    #[test]
+    fn datum_on_key_address_lenient() {
+        let bytes = datum_outputs();
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_datums_on_key_addresses(&metx, Strictness::Lenient),
+            Ok(())
+        );
+    }
+
+    fn with_datums(bytes: &[u8], datums: Vec<PlutusData>) -> Vec<u8> {
+        let mut tx: Tx = minicbor::decode(bytes).unwrap();
+        tx.transaction_witness_set.plutus_data = Some(datums);
+
+        minicbor::to_vec(&tx).unwrap()
+    }
+
+    #[test]
+    fn datum_missing() {
+        let datum = PlutusData::BoundedBytes(vec![1].into());
+        let hash = Hasher::<256>::hash_cbor(&datum);
+
+        let utxos = alonzo_utxos(&[(
+            tx_input(1, 0),
+            TransactionOutput {
+                datum_hash: Some(hash),
+                ..alonzo_output(&script_address(Hash::new([1; 28])), 5_000_000)
+            },
+        )]);
+
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_datums_supplied(&metx, &utxos),
+            Err(ValidationError::DatumMissing(hash))
+        );
+
+        let bytes = with_datums(&bytes, vec![datum]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(check_datums_supplied(&metx, &utxos), Ok(()));
+        assert!(orphan_datums(&metx, &utxos).is_empty());
+    }
+
+    #[test]
+    fn orphan_datum() {
+        let used = PlutusData::BoundedBytes(vec![1].into());
+        let orphan = PlutusData::BoundedBytes(vec![2].into());
+        let orphan_hash = Hasher::<256>::hash_cbor(&orphan);
+
+        let output = TransactionOutput {
+            datum_hash: Some(Hasher::<256>::hash_cbor(&used)),
+            ..alonzo_output(&script_address(Hash::new([1; 28])), 2_000_000)
+        };
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![output], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let bytes = with_datums(&bytes, vec![used, orphan]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_orphan_datums(&metx, &UTxOs::new(), Strictness::Lenient),
+            Ok(())
+        );
+        assert_eq!(
+            check_orphan_datums(&metx, &UTxOs::new(), Strictness::Strict),
+            Err(ValidationError::OrphanDatum(orphan_hash))
+        );
+        assert_eq!(
+            orphan_datum_warnings(&metx, &UTxOs::new()),
+            vec![ValidationWarning::OrphanDatum(orphan_hash)]
+        );
+    }
+
+    #[test]
+    fn referenced_datum() {
+        let datum = PlutusData::BoundedBytes(vec![1].into());
+        let utxos = alonzo_utxos(&[(
+            tx_input(2, 0),
+            TransactionOutput {
+                datum_hash: Some(Hasher::<256>::hash_cbor(&datum)),
+                ..alonzo_output(&script_address(Hash::new([1; 28])), 2_000_000)
+            },
+        )]);
+
+        let mut body = babbage_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.reference_inputs = Some(vec![tx_input(2, 0)]);
+        let mut tx: babbage::Tx = minicbor::decode(&babbage_tx(body, &[secret_key(1)])).unwrap();
+        tx.transaction_witness_set.plutus_data = Some(vec![datum]);
+        let bytes = minicbor::to_vec(tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert!(orphan_datums(&metx, &utxos).is_empty());
+        assert_eq!(
+            check_orphan_datums(&metx, &utxos, Strictness::Strict),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn collateral_rounds_up() {
+        assert_eq!(required_collateral(0, 150), 0);
+        assert_eq!(required_collateral(1, 150), 2);
+        assert_eq!(required_collateral(200_000, 150), 300_000);
+        assert_eq!(required_collateral(200_001, 150), 300_002);
+        assert_eq!(required_collateral(101, 99), 100);
+        assert_eq!(required_collateral(100, 99), 99);
+        assert_eq!(required_collateral(u64::MAX, 150), u64::MAX);
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let collateral_tx = |coin| {
+            let utxos = alonzo_utxos(&[(tx_input(2, 0), alonzo_output(&address, coin))]);
+
+            let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_001);
+            body.collateral = Some(vec![tx_input(2, 0)]);
+            let mut tx: Tx = minicbor::decode(&alonzo_tx(body, &[secret_key(1)], vec![])).unwrap();
+            tx.transaction_witness_set.redeemer = Some(vec![Redeemer {
+                tag: RedeemerTag::Spend,
+                index: 0,
+                data: PlutusData::BoundedBytes(vec![].into()),
+                ex_units: ExUnits { mem: 1, steps: 2 },
+            }]);
+
+            (minicbor::to_vec(tx).unwrap(), utxos)
+        };
+
+        let (bytes, utxos) = collateral_tx(300_001);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(
+            check_collateral_amount(&metx, &utxos, 150),
+            Err(ValidationError::CollateralInsufficient {
+                provided: 300_001,
+                required: 300_002
+            })
+        );
+
+        let (bytes, utxos) = collateral_tx(300_002);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(check_collateral_amount(&metx, &utxos, 150), Ok(()));
+    }
+
+    #[test]
+    fn redeemer_purposes() {
+        let native = NativeScript::ScriptPubkey(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[
+            (
+                tx_input(1, 0),
+                alonzo_output(&script_address(Hash::new([1; 28])), 2_000_000),
+            ),
+            (
+                tx_input(2, 0),
+                alonzo_output(&script_address(native.compute_hash()), 2_000_000),
+            ),
+        ]);
+
+        let redeemer = |tag, index| Redeemer {
+            tag,
+            index,
+            data: PlutusData::BoundedBytes(vec![].into()),
+            ex_units: ExUnits { mem: 1, steps: 2 },
+        };
+
+        // the input locked by the native script comes second once sorted
+        let check = |redeemers: Vec<Redeemer>| {
+            let body = alonzo_body(vec![tx_input(2, 0), tx_input(1, 0)], vec![], 200_000);
+            let bytes = alonzo_tx(body, &[secret_key(1)], vec![native.clone()]);
+            let mut tx: Tx = minicbor::decode(&bytes).unwrap();
+            tx.transaction_witness_set.redeemer = Some(redeemers).filter(|x| !x.is_empty());
+            let bytes = minicbor::to_vec(tx).unwrap();
+            let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+            check_redeemer_purposes(&metx, &utxos)
+        };
+
+        let first = tx_input(1, 0).transaction_id < tx_input(2, 0).transaction_id;
+        let (plutus, native_index) = match first {
+            true => (0, 1),
+            false => (1, 0),
+        };
+
+        assert_eq!(check(vec![redeemer(RedeemerTag::Spend, plutus)]), Ok(()));
+        assert_eq!(
+            check(vec![]),
+            Err(ValidationError::RedeemerPurposeMismatch {
+                tag: RedeemerTag::Spend,
+                index: plutus
+            })
+        );
+        assert_eq!(
+            check(vec![
+                redeemer(RedeemerTag::Spend, plutus),
+                redeemer(RedeemerTag::Spend, native_index)
+            ]),
+            Err(ValidationError::RedeemerPurposeMismatch {
+                tag: RedeemerTag::Spend,
+                index: native_index
+            })
+        );
+        assert_eq!(
+            check(vec![
+                redeemer(RedeemerTag::Spend, plutus),
+                redeemer(RedeemerTag::Mint, 0)
+            ]),
+            Err(ValidationError::RedeemerPurposeMismatch {
+                tag: RedeemerTag::Mint,
+                index: 0
+            })
+        );
+        assert_eq!(
+            check(vec![
+                redeemer(RedeemerTag::Spend, plutus),
+                redeemer(RedeemerTag::Spend, plutus)
+            ]),
+            Err(ValidationError::DuplicateRedeemer {
+                tag: RedeemerTag::Spend,
+                index: plutus
+            })
+        );
+    }
+
+    #[test]
+    fn mixed_withdrawals() {
+        let utxos = alonzo_utxos(&[(
+            tx_input(1, 0),
+            alonzo_output(&key_address(key_hash(&secret_key(1))), 2_000_000),
+        )]);
+
+        // the key account comes first by its bytes, but second for the ledger
+        let key_account = [&[0xe1][..], &[0; 28]].concat();
+        let script_account = [&[0xf1][..], &[0xff; 28]].concat();
+
+        let check = |index| {
+            let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+            body.withdrawals = Some(KeyValuePairs::from(vec![
+                (key_account.clone().into(), 0),
+                (script_account.clone().into(), 0),
+            ]));
+            let mut tx: Tx = minicbor::decode(&alonzo_tx(body, &[secret_key(1)], vec![])).unwrap();
+            tx.transaction_witness_set.redeemer = Some(vec![Redeemer {
+                tag: RedeemerTag::Reward,
+                index,
+                data: PlutusData::BoundedBytes(vec![].into()),
+                ex_units: ExUnits { mem: 1, steps: 2 },
+            }]);
+            let bytes = minicbor::to_vec(tx).unwrap();
+            let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+            check_redeemer_purposes(&metx, &utxos)
+        };
+
+        assert_eq!(check(0), Ok(()));
+        assert_eq!(
+            check(1),
+            Err(ValidationError::RedeemerPurposeMismatch {
+                tag: RedeemerTag::Reward,
+                index: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Application of validated transactions to the UTxO set
+
+use std::borrow::Cow;
+
+use pallas_crypto::hash::Hash;
+use pallas_traverse::{MultiEraOutput, MultiEraTx, OutputRef};
+
+use crate::{
+    environment::Environment, utils::input_key, UTxOs, ValidationError, ValidationResult, Validator,
+};
+
+/// Applies a tx to the UTxO set in place, without validating it
+///
+/// The inputs it consumes are removed and the outputs it produces are added,
+/// which for a tx failing phase-2 validation means its collateral and its
+/// collateral return respectively.
+///
+/// Fails with [ValidationError::OutputCollision], leaving the set untouched,
+/// if any of the produced outputs is already in it, which would mean the tx is
+/// being applied twice.
+pub fn apply_tx<'b>(metx: &MultiEraTx<'b>, utxos: &mut UTxOs<'b>) -> ValidationResult {
+    let hash = metx.hash();
+    let produced = produced_outputs(metx);
+
+    check_output_collisions(hash, produced.len(), utxos)?;
+
+    for key in metx.consumes().iter().filter_map(input_key) {
+        utxos.remove(&key);
+    }
+
+    for (index, output) in produced {
+        utxos.insert(OutputRef::new(hash, index as u64), output);
+    }
+
+    Ok(())
+}
+
+/// Checks that none of the `count` outputs of the tx with the given hash is
+/// already in the UTxO set
+pub(crate) fn check_output_collisions(
+    hash: Hash<32>,
+    count: usize,
+    utxos: &UTxOs,
+) -> ValidationResult {
+    match (0..count as u64)
+        .map(|index| OutputRef::new(hash, index))
+        .find(|x| utxos.contains_key(x))
+    {
+        Some(output_ref) => Err(ValidationError::OutputCollision(output_ref)),
+        None => Ok(()),
+    }
+}
+
+/// Validates a tx with the default [Validator] and returns the UTxO set
+/// resulting from applying it, leaving `utxos` untouched
+///
+/// This clones the whole set, so for large ones prefer validating and then
+/// calling [apply_tx], which updates the set in place.
+pub fn validate_and_apply<'b>(
+    metx: &MultiEraTx<'b>,
+    utxos: &UTxOs<'b>,
+    env: &Environment,
+) -> Result<UTxOs<'b>, ValidationError> {
+    Validator::default().validate(metx, utxos, env)?;
+
+    let mut applied = utxos.clone();
+    apply_tx(metx, &mut applied)?;
+
+    Ok(applied)
+}
+
+/// Validates a tx with the default [Validator] and lists the outputs applying
+/// it would add to the UTxO set, keyed by their output refs
+///
+/// For a tx failing phase-2 validation that is just its collateral return, if
+/// any. Neither `utxos` nor anything else gets updated.
+pub fn validate_and_list_outputs<'b>(
+    metx: &MultiEraTx<'b>,
+    utxos: &UTxOs<'b>,
+    env: &Environment,
+) -> Result<Vec<(OutputRef, MultiEraOutput<'b>)>, ValidationError> {
+    Validator::default().validate(metx, utxos, env)?;
+
+    let hash = metx.hash();
+    let produced = produced_outputs(metx);
+
+    check_output_collisions(hash, produced.len(), utxos)?;
+
+    Ok(produced
+        .into_iter()
+        .map(|(index, output)| (OutputRef::new(hash, index as u64), output))
+        .collect())
+}
+
+/// Clones the outputs a tx adds to the UTxO set, along with their indexes
+///
+/// Unlike [MultiEraTx::produces], the outputs stay tied to the bytes of the tx
+/// rather than to the tx itself, so they outlive any borrow of it.
+fn produced_outputs<'b>(metx: &MultiEraTx<'b>) -> Vec<(usize, MultiEraOutput<'b>)> {
+    match metx {
+        MultiEraTx::Byron(x) => x
+            .transaction
+            .outputs
+            .iter()
+            .map(|x| MultiEraOutput::Byron(Box::new(Cow::Owned(x.clone()))))
+            .enumerate()
+            .collect(),
+        MultiEraTx::AlonzoCompatible(x, _) if metx.is_valid() => x
+            .transaction_body
+            .outputs
+            .iter()
+            .map(|x| MultiEraOutput::AlonzoCompatible(Box::new(Cow::Owned(x.clone()))))
+            .enumerate()
+            .collect(),
+        MultiEraTx::Babbage(x) => {
+            let body = &x.transaction_body;
+
+            match metx.is_valid() {
+                true => body
+                    .outputs
+                    .iter()
+                    .map(|x| MultiEraOutput::Babbage(Box::new(Cow::Owned(x.clone()))))
+                    .enumerate()
+                    .collect(),
+                false => body
+                    .collateral_return
+                    .iter()
+                    .map(|x| {
+                        let output = MultiEraOutput::Babbage(Box::new(Cow::Owned(x.clone())));
+                        (body.outputs.len(), output)
+                    })
+                    .collect(),
+            }
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::{GenesisDelegates, ProtocolParams, ShelleyProtParams, Strictness},
+        fixtures::*,
+    };
+
+    use std::collections::BTreeMap;
+
+    use pallas_traverse::Era;
+
+    const ENV: Environment = Environment {
+        prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        }),
+        protocol_version: (2, 0),
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+        slot: None,
+        strictness: Strictness::Lenient,
+    };
+
+    #[test]
+    fn applied_snapshot() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let applied = validate_and_apply(&metx, &utxos, &ENV).unwrap();
+
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(applied.len(), 1);
+        assert!(!applied.contains_key(&OutputRef::new(tx_input(1, 0).transaction_id, 0)));
+        assert_eq!(
+            applied[&OutputRef::new(metx.hash(), 0)].lovelace_amount(),
+            4_800_000
+        );
+
+        assert!(matches!(
+            validate_and_apply(&metx, &applied, &ENV),
+            Err(ValidationError::InputNotInUTxO(_))
+        ));
+    }
+
+    #[test]
+    fn listed_outputs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![
+                alonzo_output(&address, 1_800_000),
+                alonzo_output(&address, 3_000_000),
+            ],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let listed: Vec<_> = validate_and_list_outputs(&metx, &utxos, &ENV)
+            .unwrap()
+            .into_iter()
+            .map(|(output_ref, output)| (output_ref, output.lovelace_amount()))
+            .collect();
+
+        assert_eq!(
+            listed,
+            vec![
+                (OutputRef::new(metx.hash(), 0), 1_800_000),
+                (OutputRef::new(metx.hash(), 1), 3_000_000),
+            ]
+        );
+        assert!(matches!(
+            validate_and_list_outputs(&metx, &UTxOs::new(), &ENV),
+            Err(ValidationError::InputNotInUTxO(_))
+        ));
+    }
+
+    #[test]
+    fn double_apply() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let mut utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(apply_tx(&metx, &mut utxos), Ok(()));
+
+        assert_eq!(
+            apply_tx(&metx, &mut utxos),
+            Err(ValidationError::OutputCollision(OutputRef::new(
+                metx.hash(),
+                0
+            )))
+        );
+        assert_eq!(utxos.len(), 1);
+    }
+}
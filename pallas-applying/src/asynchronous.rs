@@ -0,0 +1,133 @@
+//! Validation against a UTxO set living behind an async interface
+
+use pallas_traverse::{MultiEraOutput, MultiEraTx, OutputRef};
+
+use crate::{
+    environment::Environment, utils::input_key, validator::Validator, UTxOs, ValidationResult,
+};
+
+/// A source of unspent outputs which resolves them asynchronously
+///
+/// The trait doesn't tie implementors to any runtime; the returned futures
+/// are simply awaited by [validate_async].
+#[allow(async_fn_in_trait)]
+pub trait AsyncUtxoStore<'b> {
+    async fn resolve(&self, output_ref: &OutputRef) -> Option<MultiEraOutput<'b>>;
+}
+
+/// Validates a transaction, awaiting the resolution of its inputs
+///
+/// Every input of the transaction, including collateral and reference inputs,
+/// is fetched from the store, one after the other, leaving out those resolving
+/// nowhere; the rest of the validation is the same synchronous one done by
+/// the default [Validator], so txs of any era are checked against `env`.
+pub async fn validate_async<'b>(
+    metx: &MultiEraTx<'_>,
+    store: &impl AsyncUtxoStore<'b>,
+    env: &Environment,
+) -> ValidationResult {
+    let mut utxos = UTxOs::new();
+
+    for input in metx
+        .inputs()
+        .into_iter()
+        .chain(metx.collateral())
+        .chain(metx.reference_inputs())
+    {
+        let Some(output_ref) = input_key(&input) else {
+            continue;
+        };
+
+        if let Some(output) = store.resolve(&output_ref).await {
+            utxos.insert(output_ref, output);
+        }
+    }
+
+    Validator::default().validate(metx, &utxos, env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::{GenesisDelegates, ProtocolParams, ShelleyProtParams, Strictness},
+        fixtures::*,
+        ValidationError,
+    };
+
+    use std::{
+        collections::BTreeMap,
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use pallas_traverse::Era;
+
+    const ENV: Environment = Environment {
+        prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        }),
+        protocol_version: (2, 0),
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+        slot: None,
+        strictness: Strictness::Lenient,
+    };
+
+    struct Store(UTxOs<'static>);
+
+    impl AsyncUtxoStore<'static> for Store {
+        async fn resolve(&self, output_ref: &OutputRef) -> Option<MultiEraOutput<'static>> {
+            self.0.get(output_ref).cloned()
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+
+        loop {
+            if let Poll::Ready(x) = future.as_mut().poll(&mut context) {
+                return x;
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_inputs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let inputs = vec![tx_input(1, 0), tx_input(2, 0)];
+        let store = Store(alonzo_utxos(&[
+            (inputs[0].clone(), alonzo_output(&address, 5_000_000)),
+            (inputs[1].clone(), alonzo_output(&address, 1_000_000)),
+        ]));
+
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 5_800_000);
+        let body = alonzo_body(inputs.clone(), vec![output], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(block_on(validate_async(&metx, &store, &ENV)), Ok(()));
+
+        let store = Store(alonzo_utxos(&[(
+            inputs[0].clone(),
+            alonzo_output(&address, 5_000_000),
+        )]));
+
+        assert_eq!(
+            block_on(validate_async(&metx, &store, &ENV)),
+            Err(ValidationError::InputNotInUTxO(OutputRef::new(
+                tx_input(2, 0).transaction_id,
+                0
+            )))
+        );
+    }
+}
@@ -0,0 +1,538 @@
+//! Validation of transactions from the Babbage era
+
+use pallas_codec::minicbor::{
+    data::{Tag, Type},
+    decode::Error,
+    Decoder,
+};
+use pallas_primitives::babbage::{PlutusData, PseudoDatumOption};
+use pallas_traverse::{MultiEraOutput, MultiEraTx};
+
+use crate::{
+    alonzo::{
+        check_collateral_addresses, check_collateral_amount, check_collateral_inputs,
+        check_datums_supplied, check_failed_tx_collateral, check_redeemer_purposes,
+    },
+    certs::{
+        check_certificate_scripts, check_contradictory_certificates, check_pool_registrations,
+    },
+    environment::BabbageProtParams,
+    fee::{check_fee, check_tx_size},
+    metadata::check_aux_data,
+    mint::check_mint,
+    native_script::check_native_scripts,
+    script_data::{check_script_data_hash, languages},
+    shelley_ma::check_preservation_of_value,
+    structure::{
+        check_current_slot, check_inputs_not_empty, check_inputs_unique, check_validity_interval,
+    },
+    value::{check_output_values, check_outputs_within_inputs},
+    witnesses::{
+        check_input_witness_eras, check_vkey_witnesses, check_withdrawals, verify_tx_witnesses,
+    },
+    UtxoStore, ValidationError, ValidationResult,
+};
+
+/// Bytes of overhead added to the size of an output when computing its
+/// minimum lovelace, accounting for the entry in the UTxO set
+const UTXO_ENTRY_OVERHEAD: u64 = 160;
+
+/// Validates a transaction of the Babbage era
+///
+/// Its validity interval is only checked against the current `slot` when
+/// given.
+pub fn validate_babbage_tx<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &BabbageProtParams,
+    slot: Option<u64>,
+) -> ValidationResult {
+    check_inputs_not_empty(metx)?;
+    check_inputs_unique(metx)?;
+    check_validity_interval(metx)?;
+    check_current_slot(metx, slot)?;
+    check_output_values(metx.outputs(), |x| compute_min_lovelace(x, prot_pps))?;
+    check_aux_data(metx)?;
+    check_mint(metx)?;
+    check_native_scripts(metx, utxos)?;
+    check_tx_size(metx, prot_pps.max_tx_size)?;
+    check_fee(metx, prot_pps)?;
+    check_outputs_within_inputs(metx, utxos, prot_pps.key_deposit)?;
+    check_preservation_of_value(
+        metx,
+        utxos,
+        prot_pps.key_deposit,
+        prot_pps.pool_deposit,
+        |_| false,
+    )?;
+    check_failed_tx_collateral(metx)?;
+    check_collateral_inputs(metx, prot_pps.max_collateral_inputs)?;
+    check_collateral_addresses(metx, utxos)?;
+    check_collateral_amount(metx, utxos, prot_pps.collateral_percentage)?;
+    check_collateral_return(metx, prot_pps)?;
+    check_input_witness_eras(metx, utxos)?;
+    verify_tx_witnesses(metx)?;
+    check_vkey_witnesses(metx, utxos)?;
+    check_withdrawals(metx)?;
+    check_certificate_scripts(metx, utxos)?;
+    check_pool_registrations(metx)?;
+    check_contradictory_certificates(metx)?;
+    check_plutus_v1_features(metx, utxos)?;
+    check_inline_datums(metx)?;
+    check_datums_supplied(metx, utxos)?;
+    check_redeemer_purposes(metx, utxos)?;
+    check_script_data_hash(metx, utxos, &prot_pps.cost_models)
+}
+
+/// Computes the minimum lovelace (`(160 + size) * coinsPerUTxOByte`) an output
+/// must hold
+pub fn compute_min_lovelace(output: &MultiEraOutput, prot_pps: &BabbageProtParams) -> u64 {
+    (UTXO_ENTRY_OVERHEAD + output.encode().len() as u64) * prot_pps.coins_per_utxo_byte
+}
+
+/// Checks that the collateral return output, if any, holds its minimum
+/// lovelace like any other output
+pub fn check_collateral_return(
+    metx: &MultiEraTx,
+    prot_pps: &BabbageProtParams,
+) -> ValidationResult {
+    let output = match metx.collateral_return() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let min = compute_min_lovelace(&output, prot_pps);
+    let actual = output.lovelace_amount();
+
+    match actual >= min {
+        true => Ok(()),
+        false => Err(ValidationError::CollateralReturnTooSmall { min, actual }),
+    }
+}
+
+/// Checks that a tx running Plutus V1 scripts doesn't use the features their
+/// script context can't represent
+///
+/// V1 scripts count whether they come in the witness set or as reference
+/// scripts of the spent or referenced outputs. Such a tx can't have reference
+/// inputs, spend outputs holding an inline datum, nor produce outputs holding
+/// an inline datum or a reference script.
+pub fn check_plutus_v1_features<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> ValidationResult {
+    if !languages(metx, utxos).plutus_v1 {
+        return Ok(());
+    }
+
+    let inline_datum = |x: &MultiEraOutput| matches!(x.datum(), Some(PseudoDatumOption::Data(_)));
+
+    let spent_inline_datum = metx
+        .inputs()
+        .iter()
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .any(|x| inline_datum(&x));
+
+    let outputs = metx.outputs();
+
+    let feature = if !metx.reference_inputs().is_empty() {
+        "reference_inputs"
+    } else if spent_inline_datum {
+        "spent_inline_datum"
+    } else if outputs.iter().any(inline_datum) {
+        "inline_datum"
+    } else if outputs.iter().any(|x| x.script_ref().is_some()) {
+        "reference_script"
+    } else {
+        return Ok(());
+    };
+
+    Err(ValidationError::PlutusV1FeatureConflict { feature })
+}
+
+/// Checks that every inline datum embeds exactly one well-formed `PlutusData`
+///
+/// Inline datums are encoded as `#6.24(bytes .cbor plutus_data)`, but decoding
+/// the tx accepts any tag and ignores whatever follows the datum inside the
+/// bytes, so the outputs are walked again from the original body bytes.
+pub fn check_inline_datums(metx: &MultiEraTx) -> ValidationResult {
+    let tx = match metx.as_babbage() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    // the body was already decoded once, so walking it again can't fail
+    check_body_datums(tx.transaction_body.raw_cbor()).unwrap_or(Ok(()))
+}
+
+fn check_body_datums(body: &[u8]) -> Result<ValidationResult, Error> {
+    let mut d = Decoder::new(body);
+    let len = d.map()?;
+
+    for_each_entry(&mut d, len, |d| match d.u8()? {
+        1 => {
+            let outputs_len = d.array()?;
+
+            let mut index = 0;
+            for_each_entry(d, outputs_len, |d| {
+                let result = check_output_datum(d, index);
+                index += 1;
+                result
+            })
+        }
+        _ => d.skip().map(Ok),
+    })
+}
+
+/// Calls `f` for each entry of a map or array of the given (possibly
+/// indefinite) length, stopping at the first validation error
+fn for_each_entry<'b>(
+    d: &mut Decoder<'b>,
+    len: Option<u64>,
+    mut f: impl FnMut(&mut Decoder<'b>) -> Result<ValidationResult, Error>,
+) -> Result<ValidationResult, Error> {
+    let mut i = 0;
+
+    while len.map_or(d.datatype()? != Type::Break, |len| i < len) {
+        if let Err(error) = f(d)? {
+            return Ok(Err(error));
+        }
+
+        i += 1;
+    }
+
+    // step over the break closing an indefinite length entity
+    if len.is_none() {
+        d.set_position(d.position() + 1);
+    }
+
+    Ok(Ok(()))
+}
+
+fn check_output_datum(d: &mut Decoder, index: usize) -> Result<ValidationResult, Error> {
+    // legacy outputs are arrays and can't hold inline datums
+    if !matches!(d.datatype()?, Type::Map | Type::MapIndef) {
+        return d.skip().map(Ok);
+    }
+
+    let len = d.map()?;
+
+    for_each_entry(d, len, |d| match d.u8()? {
+        2 => {
+            d.array()?;
+
+            if d.u8()? != 1 {
+                return d.skip().map(Ok);
+            }
+
+            let tag = d.tag()?;
+            let bytes = d.bytes()?;
+
+            let mut inner = Decoder::new(bytes);
+            let well_formed = tag == Tag::Cbor
+                && inner.decode::<PlutusData>().is_ok()
+                && inner.position() == bytes.len();
+
+            match well_formed {
+                true => Ok(Ok(())),
+                false => Ok(Err(ValidationError::MalformedInlineDatum(index))),
+            }
+        }
+        _ => d.skip().map(Ok),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fee::FeeModel, fixtures::*, UTxOs};
+
+    use std::borrow::Cow;
+
+    use pallas_codec::{minicbor, utils::CborWrap};
+    use pallas_primitives::{
+        alonzo::{ExUnits, PlutusScript, RationalNumber},
+        babbage::{self, CostMdls, Script},
+    };
+    use pallas_traverse::{Era, OutputRef};
+
+    const PROT_PPS: BabbageProtParams = BabbageProtParams {
+        min_fee_a: 44,
+        min_fee_b: 155381,
+        max_tx_size: 16384,
+        key_deposit: 2_000_000,
+        pool_deposit: 500_000_000,
+        mem_price: RationalNumber {
+            numerator: 577,
+            denominator: 10000,
+        },
+        step_price: RationalNumber {
+            numerator: 721,
+            denominator: 10000000,
+        },
+        max_block_ex_units: ExUnits {
+            mem: 62_000_000,
+            steps: 40_000_000_000,
+        },
+        max_collateral_inputs: 3,
+        collateral_percentage: 150,
+        coins_per_utxo_byte: 4310,
+        cost_models: CostMdls {
+            plutus_v1: None,
+            plutus_v2: None,
+        },
+    };
+
+    #[test]
+    fn collateral_return_too_small() {
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let utxos = alonzo_utxos(&[
+            (tx_input(1, 0), alonzo_output(&address, 5_000_000)),
+            (tx_input(2, 0), alonzo_output(&address, 5_000_000)),
+        ]);
+
+        let mut body = babbage_body(
+            vec![tx_input(1, 0)],
+            vec![babbage_output(&address, 4_800_000)],
+            200_000,
+        );
+        body.collateral = Some(vec![tx_input(2, 0)]);
+        body.collateral_return = Some(babbage_output(&address, 1_000_000));
+        let bytes = babbage_tx(body.clone(), &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(validate_babbage_tx(&metx, &utxos, &PROT_PPS, None), Ok(()));
+
+        body.collateral_return = Some(babbage_output(&address, 100_000));
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+        let min = compute_min_lovelace(&metx.collateral_return().unwrap(), &PROT_PPS);
+
+        assert_eq!(
+            validate_babbage_tx(&metx, &utxos, &PROT_PPS, None),
+            Err(ValidationError::CollateralReturnTooSmall {
+                min,
+                actual: 100_000
+            })
+        );
+    }
+
+    #[test]
+    fn inputs_resolved_and_balanced() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let check = |input, coin| {
+            let body = babbage_body(vec![input], vec![babbage_output(&address, coin)], 200_000);
+            let bytes = babbage_tx(body, &[secret_key(1)]);
+            let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+            validate_babbage_tx(&metx, &utxos, &PROT_PPS, None)
+        };
+
+        assert_eq!(check(tx_input(1, 0), 4_800_000), Ok(()));
+        assert_eq!(
+            check(tx_input(1, 0), 9_800_000),
+            Err(ValidationError::OutputsExceedInputs {
+                inputs: 5_000_000,
+                outputs: 10_000_000
+            })
+        );
+        assert_eq!(
+            check(tx_input(2, 0), 4_800_000),
+            Err(ValidationError::InputNotInUTxO(OutputRef::new(
+                tx_input(2, 0).transaction_id,
+                0
+            )))
+        );
+    }
+
+    #[test]
+    fn fee_covers_collateral_return() {
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let mut body = babbage_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.collateral = Some(vec![tx_input(2, 0)]);
+
+        // fees all take the same five bytes, so the size doesn't depend on them
+        let min_fee = |body: &babbage::TransactionBody| {
+            let bytes = babbage_tx(body.clone(), &[secret_key(1)]);
+            let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+            PROT_PPS.min_fee(&metx, None).unwrap()
+        };
+
+        let without_return = min_fee(&body);
+        body.collateral_return = Some(babbage_output(&address, 1_000_000));
+        let with_return = min_fee(&body);
+        assert!(with_return > without_return);
+
+        let check = |fee| {
+            let bytes = babbage_tx(
+                babbage::TransactionBody {
+                    fee,
+                    ..body.clone()
+                },
+                &[secret_key(1)],
+            );
+            let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+            check_fee(&metx, &PROT_PPS)
+        };
+
+        assert_eq!(check(with_return), Ok(()));
+        assert_eq!(
+            check(without_return),
+            Err(ValidationError::FeesBelowMin {
+                fee: without_return,
+                min_fee: with_return
+            })
+        );
+    }
+
+    #[test]
+    fn plutus_v1_with_reference_inputs() {
+        let mut body = babbage_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.reference_inputs = Some(vec![tx_input(2, 0)]);
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(check_plutus_v1_features(&metx, &UTxOs::new()), Ok(()));
+
+        let mut tx: babbage::Tx = minicbor::decode(&bytes).unwrap();
+        tx.transaction_witness_set.plutus_v1_script = Some(vec![PlutusScript(vec![0x4e].into())]);
+        let bytes = minicbor::to_vec(tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_plutus_v1_features(&metx, &UTxOs::new()),
+            Err(ValidationError::PlutusV1FeatureConflict {
+                feature: "reference_inputs"
+            })
+        );
+    }
+
+    #[test]
+    fn plutus_v1_with_datums_and_scripts() {
+        // the outputs borrow from the bytes they are decoded from
+        fn utxos(bytes: &[u8]) -> UTxOs<'_> {
+            let output: babbage::MintedTransactionOutput = minicbor::decode(bytes).unwrap();
+
+            UTxOs::from([(
+                OutputRef::new(tx_input(1, 0).transaction_id, 0),
+                MultiEraOutput::Babbage(Box::new(Cow::Owned(output))),
+            )])
+        }
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let v1_script = || Script::PlutusV1Script(PlutusScript(vec![0x4e].into()));
+        let inline_datum =
+            || babbage::DatumOption::Data(CborWrap(PlutusData::BoundedBytes(vec![1].into())));
+
+        let output =
+            |datum_option, script_ref: Option<Script>| match babbage_output(&address, 2_000_000) {
+                babbage::TransactionOutput::PostAlonzo(x) => {
+                    babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+                        datum_option,
+                        script_ref: script_ref.map(CborWrap),
+                        ..x
+                    })
+                }
+                x => x,
+            };
+
+        let tx = |outputs, v1_witness| {
+            let body = babbage_body(vec![tx_input(1, 0)], outputs, 200_000);
+            let mut tx: babbage::Tx =
+                minicbor::decode(&babbage_tx(body, &[secret_key(1)])).unwrap();
+            if v1_witness {
+                tx.transaction_witness_set.plutus_v1_script =
+                    Some(vec![PlutusScript(vec![0x4e].into())]);
+            }
+            minicbor::to_vec(tx).unwrap()
+        };
+
+        let spent =
+            |datum_option, script_ref| minicbor::to_vec(output(datum_option, script_ref)).unwrap();
+
+        // the spent output holds an inline datum
+        let spent_bytes = spent(Some(inline_datum()), None);
+        let bytes = tx(vec![], true);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_plutus_v1_features(&metx, &utxos(&spent_bytes)),
+            Err(ValidationError::PlutusV1FeatureConflict {
+                feature: "spent_inline_datum"
+            })
+        );
+
+        // an output holds a reference script
+        let spent_bytes = spent(None, None);
+        let bytes = tx(vec![output(None, Some(v1_script()))], true);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_plutus_v1_features(&metx, &utxos(&spent_bytes)),
+            Err(ValidationError::PlutusV1FeatureConflict {
+                feature: "reference_script"
+            })
+        );
+
+        // the V1 script only comes as the reference script of the spent output
+        let bytes = tx(vec![output(Some(inline_datum()), None)], false);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_plutus_v1_features(&metx, &utxos(&spent_bytes)),
+            Ok(())
+        );
+
+        let spent_bytes = spent(None, Some(v1_script()));
+
+        assert_eq!(
+            check_plutus_v1_features(&metx, &utxos(&spent_bytes)),
+            Err(ValidationError::PlutusV1FeatureConflict {
+                feature: "inline_datum"
+            })
+        );
+    }
+
+    #[test]
+    fn inline_datum_with_trailing_bytes() {
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let output = match babbage_output(&address, 2_000_000) {
+            babbage::TransactionOutput::PostAlonzo(x) => {
+                babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+                    datum_option: Some(babbage::DatumOption::Data(CborWrap(
+                        PlutusData::BoundedBytes(vec![1, 2].into()),
+                    ))),
+                    ..x
+                })
+            }
+            x => x,
+        };
+
+        let body = babbage_body(vec![tx_input(1, 0)], vec![output], 200_000);
+        let canonical = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &canonical).unwrap();
+
+        assert_eq!(check_inline_datums(&metx), Ok(()));
+
+        // append a byte to the datum, within its bytes wrapper
+        let datum = [0xd8, 0x18, 0x43, 0x42, 0x01, 0x02];
+        let at = canonical.windows(6).position(|x| x == datum).unwrap();
+        let bytes = [
+            &canonical[..at],
+            &[0xd8, 0x18, 0x44, 0x42, 0x01, 0x02, 0x00],
+            &canonical[at + 6..],
+        ]
+        .concat();
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_inline_datums(&metx),
+            Err(ValidationError::MalformedInlineDatum(0))
+        );
+    }
+}
@@ -0,0 +1,697 @@
+//! Validation of whole blocks
+
+use std::borrow::Cow;
+
+use pallas_primitives::{alonzo::ExUnits, byron};
+use pallas_traverse::{Era, MultiEraBlock, MultiEraOutput, MultiEraTx, OutputRef};
+
+use crate::{
+    apply::check_output_collisions,
+    environment::{Environment, ProtocolParams},
+    fee::total_ex_units,
+    ledger::{check_delegation_targets, check_pointer_addresses, register_pools, LedgerState},
+    shelley_ma::check_preservation_of_value,
+    update::{
+        byron_proposal_id, check_byron_update_quorum, validate_byron_update_proposal,
+        validate_byron_update_vote,
+    },
+    UTxOs, ValidationError, ValidationResult, Validator,
+};
+
+/// Validates the transactions of a block, applying each of them in order
+///
+/// A transaction sees the UTxO set and the ledger state left by the ones
+/// before it, so it may spend their outputs, and is validated at the slot of
+/// the block, whatever the slot of `env`. On failure, the index of the
+/// offending transaction is returned along with the error, while `utxos` and
+/// `state` keep the effects of the transactions applied until then. Bytes not
+/// decoding to a block are reported at index 0 as [ValidationError::Decode],
+/// and so are the failures of the update payload of a Byron block: its
+/// proposal and votes are validated, the proposal getting registered in
+/// `state` before the votes, and they have to add up to a quorum of genesis
+/// delegates.
+pub fn validate_block_bytes<'b>(
+    bytes: &'b [u8],
+    utxos: &mut UTxOs<'b>,
+    state: &mut impl LedgerState,
+    env: &Environment,
+) -> Result<(), (usize, ValidationError)> {
+    let block = MultiEraBlock::decode(bytes)
+        .map_err(|error| (0, ValidationError::Decode(error.to_string())))?;
+
+    validate_and_apply_block(&block, utxos, state, env)
+}
+
+/// Validates every transaction of a block against the same UTxO set, with the
+/// default [Validator], reporting all the failures instead of the first one
+///
+/// Transactions are validated independently of each other, so one spending
+/// the outputs of an earlier transaction of the block fails to resolve them,
+/// as opposed to [apply_block]; nothing gets applied, neither to `utxos` nor
+/// to any ledger state. Validity intervals are checked against the slot of the
+/// block. The failures come as `(index, error)` pairs ordered by index,
+/// including the transaction taking the scripts of an Alonzo or Babbage block
+/// past `maxBlockExUnits`. The block is valid only if there are none.
+pub fn validate_block(
+    block: &MultiEraBlock,
+    utxos: &UTxOs,
+    env: &Environment,
+) -> Result<(), Vec<(usize, ValidationError)>> {
+    let txs = block.txs();
+    let validator = Validator::default();
+    let env = &at_block_slot(block, env);
+
+    let ex_units = match &env.prot_params {
+        ProtocolParams::Alonzo(x) => validate_block_ex_units(&txs, &x.max_block_ex_units),
+        ProtocolParams::Babbage(x) => validate_block_ex_units(&txs, &x.max_block_ex_units),
+        _ => Ok(()),
+    };
+
+    let mut errors: Vec<_> = txs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, metx)| {
+            validator
+                .validate(metx, utxos, env)
+                .err()
+                .map(|x| (index, x))
+        })
+        .chain(ex_units.err())
+        .collect();
+
+    errors.sort_by_key(|(index, _)| *index);
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+/// Validates and applies the transactions of a decoded block, as done by
+/// [validate_block_bytes]
+pub(crate) fn validate_and_apply_block<'b>(
+    block: &MultiEraBlock<'b>,
+    utxos: &mut UTxOs<'b>,
+    state: &mut impl LedgerState,
+    env: &Environment,
+) -> Result<(), (usize, ValidationError)> {
+    let env = &at_block_slot(block, env);
+
+    if let Some(byron) = block.as_byron() {
+        validate_byron_update(byron, state, env).map_err(|error| (0, error))?;
+    }
+
+    let txs = block.txs();
+
+    match &env.prot_params {
+        ProtocolParams::Alonzo(x) => validate_block_ex_units(&txs, &x.max_block_ex_units)?,
+        ProtocolParams::Babbage(x) => validate_block_ex_units(&txs, &x.max_block_ex_units)?,
+        _ => (),
+    }
+
+    for (index, metx) in txs.iter().enumerate() {
+        validate_tx(metx, utxos, state, env).map_err(|error| (index, error))?;
+        apply_block_tx(block, index, metx, utxos).map_err(|error| (index, error))?;
+        register_pools(metx, state);
+    }
+
+    Ok(())
+}
+
+/// Validates the transactions of a block in order with the default
+/// [Validator], returning the UTxO set resulting from applying all of them
+/// and leaving `utxos` untouched
+///
+/// Unlike [validate_block], each transaction is validated against the set
+/// left by the ones before it, so it may spend their outputs. The first
+/// failure is returned along with the index of the offending transaction. No
+/// ledger state is involved; see [validate_block_bytes] for validating a
+/// block against one.
+pub fn apply_block<'b>(
+    block: &MultiEraBlock<'b>,
+    utxos: &UTxOs<'b>,
+    env: &Environment,
+) -> Result<UTxOs<'b>, (usize, ValidationError)> {
+    let txs = block.txs();
+    let validator = Validator::default();
+    let env = &at_block_slot(block, env);
+
+    match &env.prot_params {
+        ProtocolParams::Alonzo(x) => validate_block_ex_units(&txs, &x.max_block_ex_units)?,
+        ProtocolParams::Babbage(x) => validate_block_ex_units(&txs, &x.max_block_ex_units)?,
+        _ => (),
+    }
+
+    let mut applied = utxos.clone();
+
+    for (index, metx) in txs.iter().enumerate() {
+        validator
+            .validate(metx, &applied, env)
+            .and_then(|_| apply_block_tx(block, index, metx, &mut applied))
+            .map_err(|error| (index, error))?;
+    }
+
+    Ok(applied)
+}
+
+/// The environment the transactions of a block are validated in, with the
+/// slot of the block as the current one
+fn at_block_slot(block: &MultiEraBlock, env: &Environment) -> Environment {
+    Environment {
+        slot: Some(block.slot()),
+        ..env.clone()
+    }
+}
+
+/// Applies the transaction of the block at the given index to the UTxO set,
+/// like [apply_tx](crate::apply::apply_tx) does with a standalone one
+fn apply_block_tx<'b>(
+    block: &MultiEraBlock<'b>,
+    index: usize,
+    metx: &MultiEraTx,
+    utxos: &mut UTxOs<'b>,
+) -> ValidationResult {
+    let hash = metx.hash();
+    let produced = produced_outputs(block, index, metx.is_valid());
+
+    check_output_collisions(hash, produced.len(), utxos)?;
+
+    for input in metx.consumes() {
+        utxos.remove(&input.output_ref());
+    }
+
+    for (output_index, output) in produced {
+        utxos.insert(OutputRef::new(hash, output_index as u64), output);
+    }
+
+    Ok(())
+}
+
+/// Checks that the scripts of the transactions of a block fit in
+/// `maxBlockExUnits` altogether
+///
+/// The execution units budgeted by the redeemers of every transaction are
+/// added up in order, those failing phase-2 validation included, and the
+/// first transaction taking the total past the limit is reported by its
+/// index.
+pub fn validate_block_ex_units(
+    txs: &[MultiEraTx],
+    max: &ExUnits,
+) -> Result<(), (usize, ValidationError)> {
+    let (mut used_mem, mut used_steps) = (0u64, 0u64);
+
+    for (index, metx) in txs.iter().enumerate() {
+        if let Some(ex_units) = total_ex_units(metx) {
+            used_mem = used_mem.saturating_add(ex_units.mem as u64);
+            used_steps = used_steps.saturating_add(ex_units.steps);
+        }
+
+        if used_mem > max.mem as u64 || used_steps > max.steps {
+            let error = ValidationError::BlockExUnitsExceeded {
+                used_mem,
+                used_steps,
+                max_mem: max.mem as u64,
+                max_steps: max.steps,
+            };
+
+            return Err((index, error));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates the update proposal and votes carried by a Byron block
+fn validate_byron_update(
+    block: &byron::MintedBlock,
+    state: &mut impl LedgerState,
+    env: &Environment,
+) -> ValidationResult {
+    let update = &block.body.upd_payload;
+    let protocol_magic = match &env.prot_params {
+        ProtocolParams::Byron(x) => x.protocol_magic,
+        _ => return Err(ValidationError::EraMismatch(Era::Byron)),
+    };
+
+    if let Some(proposal) = update.proposal.as_ref() {
+        validate_byron_update_proposal(proposal, &env.genesis_delegates, protocol_magic)?;
+        state.register_update_proposal(byron_proposal_id(proposal));
+    }
+
+    for vote in update.votes.iter() {
+        validate_byron_update_vote(vote, state, protocol_magic)?;
+    }
+
+    check_byron_update_quorum(block, &env.genesis_delegates)
+}
+
+/// Runs the default [Validator] against the transaction, followed by the
+/// checks which need the ledger state
+///
+/// This way a block gets validated by the same rules as a standalone
+/// transaction, whatever the entry point. The only difference is the
+/// `preservation_of_value` rule, which takes the pools registered in `state`
+/// into account, so that re-registering one pays no deposit.
+pub(crate) fn validate_tx(
+    metx: &MultiEraTx,
+    utxos: &UTxOs,
+    state: &impl LedgerState,
+    env: &Environment,
+) -> ValidationResult {
+    Validator::default()
+        .without_rule("preservation_of_value")
+        .validate(metx, utxos, env)?;
+
+    check_delegation_targets(metx, state)?;
+    check_pointer_addresses(metx, utxos, state)?;
+
+    match env.prot_params.deposits() {
+        Some((key_deposit, pool_deposit)) => {
+            check_preservation_of_value(metx, utxos, key_deposit, pool_deposit, |x| {
+                state.is_pool_registered(x)
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Clones the outputs a transaction of the block adds to the UTxO set, along
+/// with their indexes
+///
+/// The outputs are taken from the block rather than from the transaction so
+/// they stay tied to the block bytes instead of to the decoded block.
+fn produced_outputs<'b>(
+    block: &MultiEraBlock<'b>,
+    index: usize,
+    is_valid: bool,
+) -> Vec<(usize, MultiEraOutput<'b>)> {
+    match block {
+        MultiEraBlock::Byron(x) => x.body.tx_payload[index]
+            .transaction
+            .outputs
+            .iter()
+            .map(|x| MultiEraOutput::Byron(Box::new(Cow::Owned(x.clone()))))
+            .enumerate()
+            .collect(),
+        MultiEraBlock::AlonzoCompatible(x, _) if is_valid => x.transaction_bodies[index]
+            .outputs
+            .iter()
+            .map(|x| MultiEraOutput::AlonzoCompatible(Box::new(Cow::Owned(x.clone()))))
+            .enumerate()
+            .collect(),
+        MultiEraBlock::Babbage(x) => {
+            let body = &x.transaction_bodies[index];
+
+            match is_valid {
+                true => body
+                    .outputs
+                    .iter()
+                    .map(|x| MultiEraOutput::Babbage(Box::new(Cow::Owned(x.clone()))))
+                    .enumerate()
+                    .collect(),
+                false => body
+                    .collateral_return
+                    .iter()
+                    .map(|x| {
+                        let output = MultiEraOutput::Babbage(Box::new(Cow::Owned(x.clone())));
+                        (body.outputs.len(), output)
+                    })
+                    .collect(),
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// Checks that the transactions of a block fit in `maxBlockBodySize`
+///
+/// Takes the bare limit since every era from Shelley onwards defines it. The
+/// size of each transaction is measured on its original bytes, as done for
+/// the per-tx size checks.
+pub fn validate_block_size(txs: &[MultiEraTx], max_block_body_size: u64) -> ValidationResult {
+    let size: u64 = txs.iter().map(|x| x.size() as u64).sum();
+
+    match size <= max_block_body_size {
+        true => Ok(()),
+        false => Err(ValidationError::MaxBlockBodySizeExceeded {
+            size,
+            max: max_block_body_size,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use std::collections::BTreeMap;
+
+    use pallas_addresses::Pointer;
+    use pallas_codec::minicbor;
+    use pallas_crypto::hash::Hash;
+    use pallas_primitives::alonzo::{
+        PlutusData, Redeemer, RedeemerTag, StakeCredential, TransactionInput, Tx,
+    };
+
+    use crate::environment::{ByronProtParams, GenesisDelegates, ShelleyProtParams, Strictness};
+
+    struct NoPools;
+
+    impl LedgerState for NoPools {
+        fn is_pool_registered(&self, _: &Hash<28>) -> bool {
+            false
+        }
+
+        fn register_pool(&mut self, _: Hash<28>) {}
+
+        fn resolve_pointer(&self, _: &Pointer) -> Option<StakeCredential> {
+            None
+        }
+
+        fn is_update_proposal_active(&self, _: &Hash<32>) -> bool {
+            false
+        }
+
+        fn register_update_proposal(&mut self, _: Hash<32>) {}
+    }
+
+    const ENV: Environment = Environment {
+        prot_params: ProtocolParams::Byron(ByronProtParams {
+            summand: 155381,
+            multiplier: 44,
+            max_tx_size: 4096,
+            max_tx_outputs: None,
+            protocol_magic: 764824073,
+        }),
+        protocol_version: (1, 0),
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+        slot: None,
+        strictness: Strictness::Lenient,
+    };
+
+    #[test]
+    fn block_bytes() {
+        let bytes = hex::decode(include_str!("../../test_data/byron2.block")).unwrap();
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+        let input = block.txs()[0].inputs()[0].output_ref();
+
+        assert_eq!(
+            validate_block_bytes(&bytes, &mut UTxOs::new(), &mut NoPools, &ENV),
+            Err((0, ValidationError::InputNotInUTxO(input)))
+        );
+        assert!(matches!(
+            validate_block_bytes(&bytes[1..], &mut UTxOs::new(), &mut NoPools, &ENV),
+            Err((0, ValidationError::Decode(_)))
+        ));
+    }
+
+    #[test]
+    fn block_size() {
+        let txs: Vec<_> = (1..=2)
+            .map(|x| {
+                alonzo_tx(
+                    alonzo_body(vec![tx_input(x, 0)], vec![], 200_000),
+                    &[],
+                    vec![],
+                )
+            })
+            .collect();
+
+        let txs: Vec<_> = txs
+            .iter()
+            .map(|x| MultiEraTx::decode(Era::Shelley, x).unwrap())
+            .collect();
+
+        let size = txs.iter().map(|x| x.size() as u64).sum();
+
+        assert_eq!(validate_block_size(&txs, size), Ok(()));
+        assert_eq!(
+            validate_block_size(&txs, size - 1),
+            Err(ValidationError::MaxBlockBodySizeExceeded {
+                size,
+                max: size - 1
+            })
+        );
+    }
+
+    #[test]
+    fn block_ex_units() {
+        let txs: Vec<_> = [(1, 400, 3_000), (2, 0, 0), (3, 300, 1_000)]
+            .into_iter()
+            .map(|(x, mem, steps)| {
+                let bytes = alonzo_tx(
+                    alonzo_body(vec![tx_input(x, 0)], vec![], 200_000),
+                    &[],
+                    vec![],
+                );
+
+                let mut tx: Tx = minicbor::decode(&bytes).unwrap();
+                tx.transaction_witness_set.redeemer = (mem > 0).then(|| {
+                    vec![Redeemer {
+                        tag: RedeemerTag::Spend,
+                        index: 0,
+                        data: PlutusData::BoundedBytes(vec![].into()),
+                        ex_units: ExUnits { mem, steps },
+                    }]
+                });
+
+                minicbor::to_vec(tx).unwrap()
+            })
+            .collect();
+
+        let txs: Vec<_> = txs
+            .iter()
+            .map(|x| MultiEraTx::decode(Era::Alonzo, x).unwrap())
+            .collect();
+
+        assert_eq!(
+            validate_block_ex_units(
+                &txs,
+                &ExUnits {
+                    mem: 700,
+                    steps: 4_000
+                }
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate_block_ex_units(
+                &txs,
+                &ExUnits {
+                    mem: 699,
+                    steps: 4_000
+                }
+            ),
+            Err((
+                2,
+                ValidationError::BlockExUnitsExceeded {
+                    used_mem: 700,
+                    used_steps: 4_000,
+                    max_mem: 699,
+                    max_steps: 4_000
+                }
+            ))
+        );
+        assert_eq!(
+            validate_block_ex_units(
+                &txs,
+                &ExUnits {
+                    mem: 700,
+                    steps: 2_999
+                }
+            ),
+            Err((
+                0,
+                ValidationError::BlockExUnitsExceeded {
+                    used_mem: 400,
+                    used_steps: 3_000,
+                    max_mem: 700,
+                    max_steps: 2_999
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn every_failure() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[
+            (tx_input(1, 0), alonzo_output(&address, 5_000_000)),
+            (tx_input(2, 0), alonzo_output(&address, 5_000_000)),
+        ]);
+
+        let tx = |input, fee| {
+            let outputs = vec![alonzo_output(&address, 5_000_000 - fee)];
+            alonzo_tx(
+                alonzo_body(vec![input], outputs, fee),
+                &[secret_key(1)],
+                vec![],
+            )
+        };
+
+        let env = Environment {
+            prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+                min_utxo_value: 1_000_000,
+            }),
+            protocol_version: (2, 0),
+            ..ENV
+        };
+
+        let bytes = shelley_block(&[tx(tx_input(1, 0), 200_000), tx(tx_input(2, 0), 200_000)]);
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+
+        assert_eq!(validate_block(&block, &utxos, &env), Ok(()));
+
+        let bytes = shelley_block(&[tx(tx_input(1, 0), 200_000), tx(tx_input(3, 0), 200_000)]);
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+        let missing = OutputRef::new(tx_input(3, 0).transaction_id, 0);
+
+        assert_eq!(
+            validate_block(&block, &utxos, &env),
+            Err(vec![(1, ValidationError::InputNotInUTxO(missing.clone()))])
+        );
+
+        // the failure of the first tx doesn't hide the one of the second
+        let bytes = shelley_block(&[tx(tx_input(1, 0), 0), tx(tx_input(3, 0), 200_000)]);
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+        let errors = validate_block(&block, &utxos, &env).unwrap_err();
+
+        assert!(matches!(
+            errors[..],
+            [(0, ValidationError::FeesBelowMin { fee: 0, .. }), (1, _)]
+        ));
+        assert_eq!(errors[1], (1, ValidationError::InputNotInUTxO(missing)));
+    }
+
+    #[test]
+    fn chained_block_txs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let tx = |input, coin: u64| {
+            let outputs = vec![alonzo_output(&address, coin - 200_000)];
+            alonzo_tx(
+                alonzo_body(vec![input], outputs, 200_000),
+                &[secret_key(1)],
+                vec![],
+            )
+        };
+
+        let env = Environment {
+            prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+                min_utxo_value: 1_000_000,
+            }),
+            protocol_version: (2, 0),
+            ..ENV
+        };
+
+        let first = tx(tx_input(1, 0), 5_000_000);
+        let first_hash = MultiEraTx::decode(Era::Shelley, &first).unwrap().hash();
+        let spending_first = TransactionInput {
+            transaction_id: first_hash,
+            index: 0,
+        };
+        let second = tx(spending_first, 4_800_000);
+        let second_hash = MultiEraTx::decode(Era::Shelley, &second).unwrap().hash();
+
+        let bytes = shelley_block(&[first.clone(), second.clone()]);
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+        let applied = apply_block(&block, &utxos, &env).unwrap();
+
+        assert_eq!(applied.len(), 1);
+        assert_eq!(
+            applied[&OutputRef::new(second_hash, 0)].lovelace_amount(),
+            4_600_000
+        );
+        assert_eq!(utxos.len(), 1);
+
+        // validated on their own, the second tx can't resolve its input
+        assert_eq!(
+            validate_block(&block, &utxos, &env),
+            Err(vec![(
+                1,
+                ValidationError::InputNotInUTxO(OutputRef::new(first_hash, 0))
+            )])
+        );
+
+        // nor can it before the first one is applied
+        let bytes = shelley_block(&[second, first]);
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+
+        assert_eq!(
+            apply_block(&block, &utxos, &env).err(),
+            Some((
+                0,
+                ValidationError::InputNotInUTxO(OutputRef::new(first_hash, 0))
+            ))
+        );
+    }
+
+    #[test]
+    fn validity_at_block_slot() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let env = Environment {
+            prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+                min_utxo_value: 1_000_000,
+            }),
+            protocol_version: (2, 0),
+            ..ENV
+        };
+
+        let block = |ttl| {
+            let mut body = alonzo_body(
+                vec![tx_input(1, 0)],
+                vec![alonzo_output(&address, 4_800_000)],
+                200_000,
+            );
+            body.ttl = Some(ttl);
+            shelley_block(&[alonzo_tx(body, &[secret_key(1)], vec![])])
+        };
+
+        let slot = MultiEraBlock::decode(&block(0)).unwrap().slot();
+
+        let bytes = block(slot);
+        let block_at_ttl = MultiEraBlock::decode(&bytes).unwrap();
+
+        assert_eq!(validate_block(&block_at_ttl, &utxos, &env), Ok(()));
+        assert!(apply_block(&block_at_ttl, &utxos, &env).is_ok());
+
+        let bytes = block(slot - 1);
+        let block_past_ttl = MultiEraBlock::decode(&bytes).unwrap();
+        let expired = ValidationError::TxExpired(slot, slot - 1);
+
+        assert_eq!(
+            validate_block(&block_past_ttl, &utxos, &env),
+            Err(vec![(0, expired.clone())])
+        );
+        assert_eq!(
+            apply_block(&block_past_ttl, &utxos, &env).err(),
+            Some((0, expired.clone()))
+        );
+        assert_eq!(
+            validate_block_bytes(&bytes, &mut utxos.clone(), &mut NoPools, &env),
+            Err((0, expired))
+        );
+    }
+}
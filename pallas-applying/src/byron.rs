@@ -0,0 +1,1251 @@
+//! Validation of transactions from the Byron era
+
+use std::{borrow::Cow, collections::HashSet};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use cryptoxide::hashing::sha3_256;
+use pallas_addresses::byron::AddrType;
+use pallas_codec::{
+    minicbor::{self, data::Type, decode::Error, Decoder, Encoder},
+    utils::{CborWrap, MaybeIndefArray},
+};
+use pallas_crypto::hash::{Hash, Hasher};
+use pallas_primitives::byron::{Address, MintedTxPayload, Twit, Tx, TxIn, TxOut};
+use pallas_traverse::{MultiEraOutput, MultiEraTx, OutputRef};
+
+use crate::{
+    apply::check_output_collisions,
+    environment::{ByronProtParams, Strictness},
+    fee::FeeModel,
+    utils::utxo_key,
+    value::{check_output_values, Value},
+    witnesses::{Ed25519Verifier, SignatureVerifier},
+    UTxOs, UtxoStore, ValidationError, ValidationResult,
+};
+
+/// Address attribute holding the (encrypted) HD derivation path
+const ATTR_DERIVATION_PATH: u64 = 1;
+
+/// Address attribute holding the network magic
+const ATTR_NETWORK_MAGIC: u64 = 2;
+
+/// Tag of pubkey addresses, also tagging their spending data
+pub(crate) const ADDR_TYPE_PUBKEY: u8 = 0;
+
+/// Tag of redeem addresses, also tagging their spending data
+pub(crate) const ADDR_TYPE_REDEEM: u8 = 2;
+
+/// Upper bound (exclusive) for the accumulated size of the address attributes
+/// unknown to the ledger
+const MAX_UNKNOWN_ADDR_ATTRS_SIZE: usize = 128;
+
+/// Upper bound (inclusive) for any amount of lovelace, the total supply
+const MAX_LOVELACE: u64 = 45_000_000_000_000_000;
+
+/// Checksum of the payload of an address, carried alongside it
+pub(crate) const ADDR_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Prefix of the messages signed by the witnesses of pubkey addresses
+const SIGN_TAG_TX: u8 = 0x01;
+
+/// Prefix of the messages signed by the witnesses of redeem addresses
+const SIGN_TAG_REDEEM_TX: u8 = 0x02;
+
+pub fn validate_byron_tx<'b>(
+    mtxp: &MintedTxPayload,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ByronProtParams,
+    strictness: Strictness,
+    verifier: &impl SignatureVerifier,
+) -> ValidationResult {
+    let tx: &Tx = &mtxp.transaction;
+    let size = get_byron_tx_size(mtxp);
+
+    check_ins_not_empty(tx)?;
+    check_ins_variants(tx)?;
+    check_ins_unique(tx)?;
+    check_array_encodings(tx, strictness)?;
+    check_outs_not_empty(tx)?;
+    check_ins_in_utxos(tx, utxos)?;
+    check_witnesses_count(mtxp)?;
+    check_witness_keys(mtxp, utxos)?;
+    check_outputs(tx)?;
+    check_outs_count(tx, prot_pps)?;
+    check_size(size, prot_pps)?;
+    check_fees(mtxp, utxos, prot_pps)?;
+    check_witness_signatures(mtxp, prot_pps.protocol_magic, verifier)
+}
+
+/// Validates a tx with [validate_byron_tx] and returns the UTxO set resulting
+/// from applying it, leaving `utxos` untouched
+///
+/// The returned set is a clone of `utxos`, still borrowing whatever its
+/// outputs borrow, without the outputs the tx spends and with the ones it
+/// produces, which are owned, keyed by the tx id and their index. For large
+/// sets prefer validating and then calling [apply_tx](crate::apply::apply_tx),
+/// which updates the set in place.
+pub fn apply_byron_tx<'b>(
+    mtxp: &MintedTxPayload,
+    utxos: &UTxOs<'b>,
+    prot_pps: &ByronProtParams,
+    strictness: Strictness,
+) -> Result<UTxOs<'b>, ValidationError> {
+    validate_byron_tx(mtxp, utxos, prot_pps, strictness, &Ed25519Verifier)?;
+
+    let tx: &Tx = &mtxp.transaction;
+    let hash = Hasher::<256>::hash(mtxp.transaction.raw_cbor());
+
+    check_output_collisions(hash, tx.outputs.len(), utxos)?;
+
+    let mut applied = utxos.clone();
+
+    for output_ref in tx.inputs.iter().filter_map(utxo_key) {
+        applied.remove(&output_ref);
+    }
+
+    for (index, output) in tx.outputs.iter().enumerate() {
+        let output = MultiEraOutput::Byron(Box::new(Cow::Owned(output.clone())));
+        applied.insert(OutputRef::new(hash, index as u64), output);
+    }
+
+    Ok(applied)
+}
+
+/// Runs the checks of [validate_byron_tx] which don't depend on the UTxO set
+pub fn check_byron_structure(
+    mtxp: &MintedTxPayload,
+    prot_pps: &ByronProtParams,
+) -> ValidationResult {
+    let tx: &Tx = &mtxp.transaction;
+
+    check_ins_not_empty(tx)?;
+    check_ins_variants(tx)?;
+    check_ins_unique(tx)?;
+    check_outs_not_empty(tx)?;
+    check_witnesses_count(mtxp)?;
+    check_outputs(tx)?;
+    check_outs_count(tx, prot_pps)?;
+    check_size(get_byron_tx_size(mtxp), prot_pps)
+}
+
+/// Size in bytes of a tx payload (the tx together with its witnesses)
+///
+/// The size is measured on the bytes the payload was decoded from, so it
+/// matches the one seen by the node no matter how the arrays were encoded
+/// (re-encoding is only faithful as long as every `MaybeIndefArray` keeps its
+/// original `Def` / `Indef` variant).
+pub fn get_byron_tx_size(mtxp: &MintedTxPayload) -> u64 {
+    // one byte for the header of the two-element array wrapping the payload
+    (1 + mtxp.transaction.raw_cbor().len() + mtxp.witness.raw_cbor().len()) as u64
+}
+
+fn check_ins_not_empty(tx: &Tx) -> ValidationResult {
+    match tx.inputs.is_empty() {
+        true => Err(ValidationError::TxInsEmpty),
+        false => Ok(()),
+    }
+}
+
+/// Checks that every input is of the standard `[0, [txid, index]]` variant,
+/// the only one referring to an output
+///
+/// Inputs of any other variant decode fine, keeping their tag, but there's
+/// nothing they could spend.
+fn check_ins_variants(tx: &Tx) -> ValidationResult {
+    for input in tx.inputs.iter() {
+        match input {
+            TxIn::Variant0(_) => (),
+            TxIn::Other(tag, _) => return Err(ValidationError::UnsupportedTxInVariant(*tag)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that no output is spent twice by the same tx
+///
+/// Inputs are compared by the output they refer to, so the same reference
+/// encoded twice counts as a duplicate.
+fn check_ins_unique(tx: &Tx) -> ValidationResult {
+    let mut spent = HashSet::new();
+
+    for output_ref in tx.inputs.iter().filter_map(utxo_key) {
+        if let Some(output_ref) = spent.replace(output_ref) {
+            return Err(ValidationError::DuplicateInput(output_ref));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the inputs and outputs are encoded as indefinite-length
+/// arrays, the way the Byron node encodes them
+///
+/// The ledger decodes either form, so the check only applies in
+/// [Strictness::Strict] mode: a tx using definite-length arrays has a
+/// different id than the one the node would have computed for it, which
+/// breaks byte-exact replay of mainnet blocks.
+pub fn check_array_encodings(tx: &Tx, strictness: Strictness) -> ValidationResult {
+    if strictness == Strictness::Lenient {
+        return Ok(());
+    }
+
+    if let MaybeIndefArray::Def(_) = tx.inputs {
+        return Err(ValidationError::UnexpectedArrayEncoding { field: "inputs" });
+    }
+
+    match tx.outputs {
+        MaybeIndefArray::Def(_) => {
+            Err(ValidationError::UnexpectedArrayEncoding { field: "outputs" })
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_outs_not_empty(tx: &Tx) -> ValidationResult {
+    match tx.outputs.is_empty() {
+        true => Err(ValidationError::TxOutsEmpty),
+        false => Ok(()),
+    }
+}
+
+/// Checks that every input is in the UTxO set, resolving to an amount no
+/// greater than the total supply
+///
+/// An out-of-range amount can only come from a corrupt UTxO set, so it gets
+/// reported on the input resolving to it rather than on the balance.
+fn check_ins_in_utxos<'b>(tx: &Tx, utxos: &impl UtxoStore<'b>) -> ValidationResult {
+    for input in tx.inputs.iter() {
+        let Some(output_ref) = utxo_key(input) else {
+            continue;
+        };
+
+        match utxos.resolve(&output_ref) {
+            None => return Err(ValidationError::InputNotInUTxO(output_ref)),
+            Some(output) if output.lovelace_amount() > MAX_LOVELACE => {
+                return Err(ValidationError::ResolvedInputOutOfRange(input.clone()))
+            }
+            Some(_) => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that there are no more witnesses than inputs, before going through
+/// the witnesses one by one
+fn check_witnesses_count(mtxp: &MintedTxPayload) -> ValidationResult {
+    let inputs = mtxp.transaction.inputs.len();
+    let witnesses = mtxp.witness.len();
+
+    match witnesses <= inputs {
+        true => Ok(()),
+        false => Err(ValidationError::TooManyWitnesses { inputs, witnesses }),
+    }
+}
+
+/// Checks that the key of each witness is the one the address of the matching
+/// input (witnesses come in the same order as inputs) derives from
+///
+/// The kind of witness has to follow the type of the address: pubkey
+/// addresses take pubkey witnesses, while the redeem addresses created by the
+/// AVVM distribution take redeem witnesses.
+fn check_witness_keys<'b>(mtxp: &MintedTxPayload, utxos: &impl UtxoStore<'b>) -> ValidationResult {
+    for (input, witness) in mtxp.transaction.inputs.iter().zip(mtxp.witness.iter()) {
+        let output_ref = match utxo_key(input) {
+            Some(output_ref) => output_ref,
+            None => continue,
+        };
+
+        let output = utxos.resolve(&output_ref);
+        let address = match output.as_deref().and_then(|x| x.as_byron()) {
+            Some(output) => &output.address,
+            None => continue,
+        };
+
+        let (addr_type, key) = match (byron_address_type(address), witness) {
+            (Ok(AddrType::PubKey), Twit::PkWitness(CborWrap((xpub, _)))) => {
+                (ADDR_TYPE_PUBKEY, xpub)
+            }
+            (Ok(AddrType::Redeem), Twit::RedeemWitness(CborWrap((key, _)))) => {
+                (ADDR_TYPE_REDEEM, key)
+            }
+            (Ok(AddrType::PubKey | AddrType::Redeem), _)
+            | (Ok(_), Twit::PkWitness(_) | Twit::RedeemWitness(_)) => {
+                return Err(ValidationError::WitnessTypeMismatch(output_ref))
+            }
+            (Ok(_), _) => continue,
+            (Err(_), _) => return Err(ValidationError::WitnessKeyAddressMismatch(output_ref)),
+        };
+
+        let matches = match split_addr_payload(&address.payload.0) {
+            Ok((root, attributes)) => address_root(addr_type, key, attributes).as_ref() == root,
+            Err(_) => false,
+        };
+
+        if !matches {
+            return Err(ValidationError::WitnessKeyAddressMismatch(output_ref));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every input comes with a witness, and that the pubkey and
+/// redeem witnesses sign the tx id under the protocol magic
+///
+/// Witnesses come in the same order as inputs, so an input past the last
+/// witness fails with [ValidationError::MissingWitness]. Pubkey witnesses
+/// sign with the public half of their extended key, redeem witnesses with
+/// their plain key, and a signature not matching the key fails with
+/// [ValidationError::WrongSignature], reported by the hash of the key.
+/// Whether the keys are the ones the addresses call for is left to
+/// [check_witness_keys].
+fn check_witness_signatures(
+    mtxp: &MintedTxPayload,
+    protocol_magic: u32,
+    verifier: &impl SignatureVerifier,
+) -> ValidationResult {
+    let tx_hash = Hasher::<256>::hash(mtxp.transaction.raw_cbor());
+
+    for (index, input) in mtxp.transaction.inputs.iter().enumerate() {
+        let Some(output_ref) = utxo_key(input) else {
+            continue;
+        };
+
+        let (tag, key, signature) = match mtxp.witness.get(index) {
+            Some(Twit::PkWitness(CborWrap((xpub, signature)))) => (SIGN_TAG_TX, xpub, signature),
+            Some(Twit::RedeemWitness(CborWrap((key, signature)))) => {
+                (SIGN_TAG_REDEEM_TX, key, signature)
+            }
+            Some(_) => continue,
+            None => return Err(ValidationError::MissingWitness(output_ref)),
+        };
+
+        let message = byron_signed_data(tag, protocol_magic, tx_hash);
+        let public_key = key.get(..32).unwrap_or(key);
+
+        if !verifier.verify(public_key, &message, signature) {
+            return Err(ValidationError::WrongSignature(Hasher::<224>::hash(key)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the `tag ‖ cbor(protocol_magic) ‖ cbor(body)` message Byron keys
+/// sign, the tag telling what kind of body is signed
+pub(crate) fn byron_signed_data(
+    tag: u8,
+    protocol_magic: u32,
+    body: impl minicbor::Encode<()>,
+) -> Vec<u8> {
+    [
+        vec![tag],
+        minicbor::to_vec(protocol_magic).unwrap(),
+        minicbor::to_vec(body).unwrap(),
+    ]
+    .concat()
+}
+
+/// Computes the root of a pubkey or redeem address, i.e. the hash of its
+/// spending data (a single key tagged with the address type) together with its
+/// attributes
+///
+/// The key is the extended public key for pubkey addresses and the plain one
+/// for redeem addresses. The attributes are passed as the CBOR map found in
+/// the address payload.
+pub(crate) fn address_root(addr_type: u8, key: &[u8], attributes: &[u8]) -> Hash<28> {
+    let mut e = Encoder::new(Vec::new());
+
+    // [addrtype, [addrtype, key], attributes]
+    e.array(3).unwrap();
+    e.u8(addr_type).unwrap();
+    e.array(2)
+        .unwrap()
+        .u8(addr_type)
+        .unwrap()
+        .bytes(key)
+        .unwrap();
+
+    let mut bytes = e.into_writer();
+    bytes.extend_from_slice(attributes);
+
+    Hasher::<224>::hash(&sha3_256(&bytes))
+}
+
+/// Splits the `[root, attributes, type]` payload of an address into the root
+/// and the raw CBOR of the attributes
+pub(crate) fn split_addr_payload(payload: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let mut d = Decoder::new(payload);
+
+    d.array()?;
+    let root = d.bytes()?;
+
+    let start = d.position();
+    d.skip()?;
+    let attributes = &payload[start..d.position()];
+
+    Ok((root, attributes))
+}
+
+fn check_outputs(tx: &Tx) -> ValidationResult {
+    check_output_values(tx.outputs.iter().map(MultiEraOutput::from_byron), |_| 0)?;
+
+    for (index, output) in tx.outputs.iter().enumerate() {
+        check_output_address(index, output)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes the type (pubkey, script or redeem) of an address from the last
+/// element of its `[root, attributes, type]` payload
+pub fn byron_address_type(address: &Address) -> Result<AddrType, Error> {
+    let mut d = Decoder::new(&address.payload.0);
+
+    d.array()?;
+    d.skip()?;
+    d.skip()?;
+
+    d.decode()
+}
+
+fn check_outs_count(tx: &Tx, prot_pps: &ByronProtParams) -> ValidationResult {
+    let count = tx.outputs.len() as u32;
+
+    match prot_pps.max_tx_outputs {
+        Some(max) if count > max => Err(ValidationError::TooManyOutputs { count, max }),
+        _ => Ok(()),
+    }
+}
+
+fn check_size(size: u64, prot_pps: &ByronProtParams) -> ValidationResult {
+    match size <= prot_pps.max_tx_size {
+        true => Ok(()),
+        false => Err(ValidationError::MaxTxSizeExceeded {
+            size,
+            max: prot_pps.max_tx_size,
+        }),
+    }
+}
+
+/// Checks that the inputs cover the outputs, the difference being the fee,
+/// and that the fee is no lower than the minimum
+///
+/// Outputs exceeding inputs fail with [ValidationError::ValueNotConserved],
+/// carrying the lovelace of the inputs and of the outputs, while totals
+/// overflowing a `u64` fail with [ValidationError::ValueOverflow].
+fn check_fees<'b>(
+    mtxp: &MintedTxPayload,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ByronProtParams,
+) -> ValidationResult {
+    let tx: &Tx = &mtxp.transaction;
+
+    let consumed = tx
+        .inputs
+        .iter()
+        .filter_map(|x| utxos.resolve(&utxo_key(x)?))
+        .try_fold(Value::default(), |acc, x| {
+            acc.checked_add(&Value::from_output(&x))
+        })
+        .ok_or(ValidationError::ValueOverflow)?;
+
+    let produced = tx
+        .outputs
+        .iter()
+        .try_fold(Value::default(), |acc, x| {
+            acc.checked_add(&Value::from_coin(x.amount))
+        })
+        .ok_or(ValidationError::ValueOverflow)?;
+
+    // the fee is implicit, so outputs exceeding inputs would make it negative
+    let fee = consumed
+        .checked_sub(&produced)
+        .ok_or(ValidationError::ValueNotConserved(
+            consumed.coin(),
+            produced.coin(),
+        ))?
+        .coin();
+    let min_fee = prot_pps.min_fee(&MultiEraTx::from_byron(mtxp), None)?;
+
+    match fee >= min_fee {
+        true => Ok(()),
+        false => Err(ValidationError::FeesBelowMin { fee, min_fee }),
+    }
+}
+
+/// Checks the checksum, the attributes map and the type of the address of an
+/// output
+///
+/// Addresses may carry any attributes (derivation paths from HD wallets being
+/// the most common), but the ones the ledger doesn't know about must add up to
+/// less than [MAX_UNKNOWN_ADDR_ATTRS_SIZE] bytes. Outputs must be locked by
+/// pubkey addresses, since redeem addresses are only created by the AVVM
+/// distribution at genesis.
+fn check_output_address(index: usize, output: &TxOut) -> ValidationResult {
+    if !check_address_crc(&output.address) {
+        return Err(ValidationError::AddressCrcMismatch(index));
+    }
+
+    let size = unknown_addr_attrs_size(&output.address.payload.0)
+        .map_err(|_| ValidationError::OutputAddressMalformed(index))?;
+
+    if size >= MAX_UNKNOWN_ADDR_ATTRS_SIZE {
+        return Err(ValidationError::OutputAddressAttrsTooLarge { index, size });
+    }
+
+    match byron_address_type(&output.address) {
+        Ok(AddrType::PubKey) => Ok(()),
+        Ok(addr_type) => Err(ValidationError::UnexpectedByronAddressType { index, addr_type }),
+        Err(_) => Err(ValidationError::OutputAddressMalformed(index)),
+    }
+}
+
+/// Whether the CRC32 an address carries is the checksum of its payload
+pub fn check_address_crc(address: &Address) -> bool {
+    ADDR_CRC.checksum(&address.payload.0) == address.crc
+}
+
+/// Walks the `[root, attributes, type]` payload of an address, adding up the
+/// size of the attribute values other than the derivation path and the
+/// network magic
+fn unknown_addr_attrs_size(payload: &[u8]) -> Result<usize, Error> {
+    let mut d = Decoder::new(payload);
+
+    d.array()?;
+    d.bytes()?;
+
+    let len = d.map()?.unwrap_or_default();
+    let mut size = 0;
+
+    for _ in 0..len {
+        let key = d.u64()?;
+
+        let value_size = match d.datatype()? {
+            Type::Bytes => d.bytes()?.len(),
+            _ => {
+                let start = d.position();
+                d.skip()?;
+                d.position() - start
+            }
+        };
+
+        if key != ATTR_DERIVATION_PATH && key != ATTR_NETWORK_MAGIC {
+            size += value_size;
+        }
+    }
+
+    d.u64()?;
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_codec::{minicbor, utils::EmptyMap};
+    use pallas_primitives::byron;
+    use pallas_traverse::MultiEraBlock;
+
+    const PROT_PPS: ByronProtParams = ByronProtParams {
+        summand: 155381,
+        multiplier: 44,
+        max_tx_size: 4096,
+        max_tx_outputs: None,
+        protocol_magic: 764824073,
+    };
+
+    #[test]
+    fn applied_tx() {
+        let inputs = [byron_input(1, 0), byron_input(1, 1)];
+        let utxos = byron_utxos(&[
+            (
+                inputs[0].clone(),
+                byron_key_output(&secret_key(1), 2_000_000),
+            ),
+            (inputs[1].clone(), byron_output(&[], 3_000_000)),
+        ]);
+
+        let outputs = vec![byron_output(&[], 1_000_000), byron_output(&[], 800_000)];
+        let bytes = byron_signed_tx(inputs[..1].to_vec(), outputs, &[secret_key(1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+        let hash = MultiEraTx::from_byron(&mtxp).hash();
+
+        let applied = apply_byron_tx(&mtxp, &utxos, &PROT_PPS, Strictness::Lenient).unwrap();
+
+        assert_eq!(applied.len(), 3);
+        assert!(!applied.contains_key(&utxo_key(&inputs[0]).unwrap()));
+        assert!(applied.contains_key(&utxo_key(&inputs[1]).unwrap()));
+        assert_eq!(
+            applied
+                .get(&OutputRef::new(hash, 0))
+                .map(|x| x.lovelace_amount()),
+            Some(1_000_000)
+        );
+        assert_eq!(
+            applied
+                .get(&OutputRef::new(hash, 1))
+                .map(|x| x.lovelace_amount()),
+            Some(800_000)
+        );
+        assert_eq!(utxos.len(), 2);
+
+        // applying it twice would spend an output which is gone by then
+        assert_eq!(
+            apply_byron_tx(&mtxp, &applied, &PROT_PPS, Strictness::Lenient).err(),
+            Some(ValidationError::InputNotInUTxO(
+                utxo_key(&inputs[0]).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn derivation_path_attribute() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron_key_output(&secret_key(1), 2_000_000),
+        )]);
+
+        let derivation_path = vec![0xa5; 28];
+        let output = byron_output(&[(ATTR_DERIVATION_PATH, derivation_path)], 1_800_000);
+
+        let bytes = byron_signed_tx(inputs, vec![output], &[secret_key(1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn strict_array_encodings() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron_key_output(&secret_key(1), 2_000_000),
+        )]);
+
+        let output = byron_output(&[], 1_800_000);
+
+        let bytes = byron_signed_tx(inputs, vec![output], &[secret_key(1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Strict,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::UnexpectedArrayEncoding { field: "inputs" })
+        );
+    }
+
+    #[test]
+    fn unknown_attributes_too_large() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let output = byron_output(&[(7, vec![0; 100]), (8, vec![0; 28])], 1_800_000);
+
+        let bytes = byron_tx(inputs, vec![output]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::OutputAddressAttrsTooLarge {
+                index: 0,
+                size: 128
+            })
+        );
+    }
+
+    #[test]
+    fn address_crc() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let mut output = byron_output(&[], 1_800_000);
+        assert!(check_address_crc(&output.address));
+
+        output.address.crc ^= 0xff00;
+        assert!(!check_address_crc(&output.address));
+
+        let bytes = byron_tx(inputs, vec![byron_output(&[], 1_000), output]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::AddressCrcMismatch(1))
+        );
+
+        // outputs from mainnet
+        let bytes = hex::decode(include_str!("../../test_data/byron2.block")).unwrap();
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+
+        for metx in block.txs() {
+            let tx = &metx.as_byron().unwrap().transaction;
+
+            assert!(tx.outputs.iter().all(|x| check_address_crc(&x.address)));
+        }
+    }
+
+    #[test]
+    fn redeem_address_output() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let output = byron::TxOut {
+            address: byron_typed_address(Hash::new([0; 28]), &[], 2),
+            amount: 1_800_000,
+        };
+
+        let bytes = byron_tx(inputs, vec![byron_output(&[], 1_000), output]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::UnexpectedByronAddressType {
+                index: 1,
+                addr_type: AddrType::Redeem
+            })
+        );
+    }
+
+    #[test]
+    fn outputs_exceed_inputs() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let bytes = byron_tx(inputs, vec![byron_output(&[], 2_000_001)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::ValueNotConserved(2_000_000, 2_000_001))
+        );
+    }
+
+    #[test]
+    fn outputs_overflow() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let outputs = vec![byron_output(&[], u64::MAX / 2 + 1); 2];
+        let bytes = byron_tx(inputs, outputs);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::ValueOverflow)
+        );
+    }
+
+    #[test]
+    fn resolved_input_out_of_range() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], MAX_LOVELACE + 1))]);
+
+        let bytes = byron_tx(inputs.clone(), vec![byron_output(&[], 1_000_000)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::ResolvedInputOutOfRange(inputs[0].clone()))
+        );
+    }
+
+    #[test]
+    fn duplicate_input() {
+        let inputs = vec![byron_input(1, 0), byron_input(1, 1), byron_input(1, 0)];
+        let utxos = byron_utxos(&[
+            (inputs[0].clone(), byron_output(&[], 2_000_000)),
+            (inputs[1].clone(), byron_output(&[], 2_000_000)),
+        ]);
+
+        let bytes = byron_tx(inputs.clone(), vec![byron_output(&[], 1_000_000)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::DuplicateInput(
+                utxo_key(&inputs[0]).unwrap()
+            ))
+        );
+        assert_eq!(
+            check_byron_structure(&mtxp, &PROT_PPS),
+            Err(ValidationError::DuplicateInput(
+                utxo_key(&inputs[0]).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn unsupported_input_variant() {
+        let inputs = vec![byron_input(1, 0), byron::TxIn::Other(3, vec![0x80].into())];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let bytes = byron_tx(inputs, vec![byron_output(&[], 1_000_000)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::UnsupportedTxInVariant(3))
+        );
+        assert_eq!(
+            check_byron_structure(&mtxp, &PROT_PPS),
+            Err(ValidationError::UnsupportedTxInVariant(3))
+        );
+    }
+
+    #[test]
+    fn size_follows_array_encoding() {
+        let inputs = vec![byron_input(1, 0), byron_input(1, 1)];
+        let outputs = vec![byron_output(&[], 1_000_000)];
+
+        let def_bytes = byron_tx(inputs.clone(), outputs.clone());
+
+        let indef_bytes = minicbor::to_vec(byron::TxPayload {
+            transaction: byron::Tx {
+                inputs: MaybeIndefArray::Indef(inputs),
+                outputs: MaybeIndefArray::Indef(outputs),
+                attributes: EmptyMap,
+            },
+            witness: MaybeIndefArray::Indef(vec![]),
+        })
+        .unwrap();
+
+        let def: MintedTxPayload = minicbor::decode(&def_bytes).unwrap();
+        let indef: MintedTxPayload = minicbor::decode(&indef_bytes).unwrap();
+
+        assert_eq!(get_byron_tx_size(&def), def_bytes.len() as u64);
+        assert_eq!(get_byron_tx_size(&indef), indef_bytes.len() as u64);
+        assert_eq!(get_byron_tx_size(&indef), get_byron_tx_size(&def) + 3);
+    }
+
+    #[test]
+    fn array_encodings() {
+        let inputs = vec![byron_input(1, 0)];
+        let outputs = vec![byron_output(&[], 1_000_000)];
+
+        let def_bytes = byron_tx(inputs.clone(), outputs.clone());
+        let def: MintedTxPayload = minicbor::decode(&def_bytes).unwrap();
+
+        assert_eq!(
+            check_array_encodings(&def.transaction, Strictness::Lenient),
+            Ok(())
+        );
+        assert_eq!(
+            check_array_encodings(&def.transaction, Strictness::Strict),
+            Err(ValidationError::UnexpectedArrayEncoding { field: "inputs" })
+        );
+
+        let indef = byron::Tx {
+            inputs: MaybeIndefArray::Indef(inputs),
+            outputs: MaybeIndefArray::Indef(outputs.clone()),
+            attributes: EmptyMap,
+        };
+
+        assert_eq!(check_array_encodings(&indef, Strictness::Strict), Ok(()));
+
+        let mixed = byron::Tx {
+            outputs: MaybeIndefArray::Def(outputs),
+            ..indef
+        };
+
+        assert_eq!(
+            check_array_encodings(&mixed, Strictness::Strict),
+            Err(ValidationError::UnexpectedArrayEncoding { field: "outputs" })
+        );
+
+        // txs from mainnet, as encoded by the node
+        let bytes = hex::decode(include_str!("../../test_data/byron2.block")).unwrap();
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+
+        for metx in block.txs() {
+            let mtxp = metx.as_byron().unwrap();
+
+            assert_eq!(
+                check_array_encodings(&mtxp.transaction, Strictness::Strict),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn max_tx_size_exceeded() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(inputs[0].clone(), byron_output(&[], 2_000_000))]);
+
+        let bytes = byron_tx(inputs, vec![byron_output(&[], 1_800_000)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        let prot_pps = ByronProtParams {
+            max_tx_size: 64,
+            ..PROT_PPS
+        };
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &prot_pps,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::MaxTxSizeExceeded {
+                size: bytes.len() as u64,
+                max: 64
+            })
+        );
+    }
+
+    #[test]
+    fn too_many_outputs() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron_key_output(&secret_key(1), 2_000_000),
+        )]);
+
+        let outputs = vec![byron_output(&[], 900_000), byron_output(&[], 900_000)];
+        let bytes = byron_signed_tx(inputs, outputs, &[secret_key(1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Ok(())
+        );
+
+        let prot_pps = ByronProtParams {
+            max_tx_outputs: Some(1),
+            ..PROT_PPS
+        };
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &prot_pps,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::TooManyOutputs { count: 2, max: 1 })
+        );
+    }
+
+    #[test]
+    fn witness_key_address_mismatch() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron_key_output(&secret_key(1), 2_000_000),
+        )]);
+
+        let outputs = vec![byron_output(&[], 1_800_000)];
+
+        let bytes = byron_signed_tx(inputs.clone(), outputs.clone(), &[secret_key(1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Ok(())
+        );
+
+        let bytes = byron_signed_tx(inputs.clone(), outputs, &[secret_key(2)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::WitnessKeyAddressMismatch(
+                utxo_key(&inputs[0]).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn too_many_witnesses() {
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron_key_output(&secret_key(1), 2_000_000),
+        )]);
+
+        let outputs = vec![byron_output(&[], 1_800_000)];
+        let keys = [secret_key(1), secret_key(2), secret_key(3)];
+
+        let bytes = byron_signed_tx(inputs, outputs, &keys);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::TooManyWitnesses {
+                inputs: 1,
+                witnesses: 3
+            })
+        );
+    }
+
+    #[test]
+    fn redeem_witness() {
+        let key = secret_key(1);
+        let root = address_root(ADDR_TYPE_REDEEM, key.public_key().as_ref(), &[0xa0]);
+
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron::TxOut {
+                address: byron_typed_address(root, &[], 2),
+                amount: 2_000_000,
+            },
+        )]);
+
+        let transaction = byron::Tx {
+            inputs: MaybeIndefArray::Def(inputs.clone()),
+            outputs: MaybeIndefArray::Def(vec![byron_output(&[], 1_800_000)]),
+            attributes: EmptyMap,
+        };
+
+        let payload = |witness| {
+            minicbor::to_vec(byron::TxPayload {
+                transaction: transaction.clone(),
+                witness: MaybeIndefArray::Def(vec![witness]),
+            })
+            .unwrap()
+        };
+
+        let signature = || byron_tx_signature(&key, SIGN_TAG_REDEEM_TX, &transaction).into();
+
+        let bytes = payload(byron::Twit::RedeemWitness(CborWrap((
+            key.public_key().as_ref().to_vec().into(),
+            signature(),
+        ))));
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Ok(())
+        );
+
+        let bytes = payload(byron::Twit::PkWitness(CborWrap((
+            byron_xpub(&key).into(),
+            signature(),
+        ))));
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::WitnessTypeMismatch(
+                utxo_key(&inputs[0]).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn witness_signatures() {
+        let inputs = vec![byron_input(1, 0), byron_input(1, 1)];
+        let utxos = byron_utxos(&[
+            (
+                inputs[0].clone(),
+                byron_key_output(&secret_key(1), 1_000_000),
+            ),
+            (
+                inputs[1].clone(),
+                byron_key_output(&secret_key(2), 1_000_000),
+            ),
+        ]);
+
+        let outputs = vec![byron_output(&[], 1_800_000)];
+        let keys = [secret_key(1), secret_key(2)];
+
+        let bytes = byron_signed_tx(inputs.clone(), outputs.clone(), &keys);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Ok(())
+        );
+
+        // flip a bit of the last signature, the last byte of the payload
+        let mut tampered = bytes.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let mtxp: MintedTxPayload = minicbor::decode(&tampered).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::WrongSignature(Hasher::<224>::hash(
+                &byron_xpub(&secret_key(2))
+            )))
+        );
+
+        // signed under another network
+        let testnet = ByronProtParams {
+            protocol_magic: 1097911063,
+            ..PROT_PPS
+        };
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &testnet,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::WrongSignature(Hasher::<224>::hash(
+                &byron_xpub(&secret_key(1))
+            )))
+        );
+
+        let bytes = byron_signed_tx(inputs.clone(), outputs, &keys[..1]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_byron_tx(
+                &mtxp,
+                &utxos,
+                &PROT_PPS,
+                Strictness::Lenient,
+                &Ed25519Verifier
+            ),
+            Err(ValidationError::MissingWitness(
+                utxo_key(&inputs[1]).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn mainnet_witness_signatures() {
+        let bytes = hex::decode(include_str!("../../test_data/byron2.block")).unwrap();
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+
+        for metx in block.txs() {
+            let mtxp = metx.as_byron().unwrap();
+
+            assert_eq!(
+                check_witness_signatures(mtxp, 764824073, &Ed25519Verifier),
+                Ok(())
+            );
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Memoization of input resolution and tx hashing across validations
+
+use std::collections::HashMap;
+
+use pallas_crypto::hash::Hash;
+use pallas_traverse::{MultiEraOutput, MultiEraTx};
+
+use crate::{utils::input_key, UTxOs, UtxoKey};
+
+/// Outputs resolved, and optionally tx ids computed, by earlier validations,
+/// reused by [Validator::validate_cached](crate::Validator::validate_cached)
+///
+/// Caching is safe as long as the UTxO set the outputs were resolved from
+/// stays the same, as when re-validating overlapping sets of txs against a
+/// confirmed set. It is unsafe once that set changes: an output spent since
+/// it got cached still resolves, letting a tx spend it twice, and an output
+/// replaced under the same reference resolves to its old value. Call
+/// [invalidate](Self::invalidate) whenever the set changes, or
+/// [forget](Self::forget) the outputs known to be gone. Missing outputs are
+/// never cached, so outputs added to the set are picked up either way.
+///
+/// Tx ids only depend on the bytes a tx was decoded from, and stay valid
+/// whatever happens to the UTxO set.
+#[derive(Debug, Default)]
+pub struct ValidatorCache<'b> {
+    resolved: HashMap<UtxoKey, MultiEraOutput<'b>>,
+    tx_hashes: Option<HashMap<RawBytes<'b>, Hash<32>>>,
+}
+
+impl<'b> ValidatorCache<'b> {
+    /// A cache of resolved outputs only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache of resolved outputs which also keeps the id of every tx given
+    /// to [tx_hash](Self::tx_hash)
+    pub fn with_tx_hashes() -> Self {
+        Self {
+            tx_hashes: Some(HashMap::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Resolves every input of a tx, including collateral and reference
+    /// inputs, into the set of outputs it spends or references
+    ///
+    /// Outputs are taken from the cache first, then from `utxos`, caching
+    /// whatever gets found there. Inputs resolving nowhere are left out.
+    pub fn resolve(&mut self, metx: &MultiEraTx, utxos: &UTxOs<'b>) -> UTxOs<'b> {
+        metx.inputs()
+            .into_iter()
+            .chain(metx.collateral())
+            .chain(metx.reference_inputs())
+            .filter_map(|x| input_key(&x))
+            .filter_map(|key| {
+                if !self.resolved.contains_key(&key) {
+                    let output = utxos.get(&key)?;
+                    self.resolved.insert(key.clone(), output.clone());
+                }
+
+                let output = self.resolved[&key].clone();
+
+                Some((key, output))
+            })
+            .collect()
+    }
+
+    /// Computes the id of a tx, reusing the one computed last time the same
+    /// tx was given if tx ids are cached
+    ///
+    /// Txs are told apart by the location of the bytes they were decoded
+    /// from, which the cache keeps borrowed, so a tx decoded anew from a copy
+    /// of the bytes gets hashed again.
+    pub fn tx_hash(&mut self, metx: &MultiEraTx<'b>) -> Hash<32> {
+        let (Some(hashes), Some(raw)) = (self.tx_hashes.as_mut(), hashed_bytes(metx)) else {
+            return metx.hash();
+        };
+
+        *hashes.entry(raw).or_insert_with(|| metx.hash())
+    }
+
+    /// Forgets a single resolved output, to be resolved again next time
+    pub fn forget(&mut self, key: &UtxoKey) {
+        self.resolved.remove(key);
+    }
+
+    /// Forgets every resolved output, keeping the cached tx ids
+    pub fn invalidate(&mut self) {
+        self.resolved.clear();
+    }
+
+    /// Number of resolved outputs in the cache
+    pub fn len(&self) -> usize {
+        self.resolved.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resolved.is_empty()
+    }
+}
+
+/// Bytes compared and hashed by location rather than by content
+#[derive(Debug, Clone, Copy)]
+struct RawBytes<'b>(&'b [u8]);
+
+impl PartialEq for RawBytes<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for RawBytes<'_> {}
+
+impl std::hash::Hash for RawBytes<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state);
+        self.0.len().hash(state);
+    }
+}
+
+/// The bytes a tx id is the hash of
+fn hashed_bytes<'b>(metx: &MultiEraTx<'b>) -> Option<RawBytes<'b>> {
+    match metx {
+        MultiEraTx::AlonzoCompatible(x, _) => Some(RawBytes(x.transaction_body.raw_cbor())),
+        MultiEraTx::Babbage(x) => Some(RawBytes(x.transaction_body.raw_cbor())),
+        MultiEraTx::Byron(x) => Some(RawBytes(x.transaction.raw_cbor())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apply::apply_tx,
+        environment::{
+            Environment, GenesisDelegates, ProtocolParams, ShelleyProtParams, Strictness,
+        },
+        fixtures::*,
+        ValidationError, Validator,
+    };
+
+    use std::collections::BTreeMap;
+
+    use pallas_traverse::Era;
+
+    const ENV: Environment = Environment {
+        prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        }),
+        protocol_version: (2, 0),
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+        slot: None,
+        strictness: Strictness::Lenient,
+    };
+
+    #[test]
+    fn cached_resolution() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let mut utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let validator = Validator::default();
+        let mut cache = ValidatorCache::new();
+
+        assert_eq!(
+            validator.validate_cached(&metx, &utxos, &ENV, &mut cache),
+            Ok(())
+        );
+        assert_eq!(cache.len(), 1);
+
+        apply_tx(&metx, &mut utxos).unwrap();
+
+        // the spent output still resolves from the stale cache
+        assert_eq!(
+            validator.validate_cached(&metx, &utxos, &ENV, &mut cache),
+            Ok(())
+        );
+
+        cache.invalidate();
+
+        assert!(matches!(
+            validator.validate_cached(&metx, &utxos, &ENV, &mut cache),
+            Err(ValidationError::InputNotInUTxO(_))
+        ));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn cached_tx_hashes() {
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let copy = bytes.clone();
+
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+        let again = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+        let copied = MultiEraTx::decode(Era::Shelley, &copy).unwrap();
+
+        let mut cache = ValidatorCache::with_tx_hashes();
+
+        assert_eq!(cache.tx_hash(&metx), metx.hash());
+        assert_eq!(cache.tx_hash(&again), metx.hash());
+        assert_eq!(cache.tx_hashes.as_ref().map(|x| x.len()), Some(1));
+
+        assert_eq!(cache.tx_hash(&copied), metx.hash());
+        assert_eq!(cache.tx_hashes.as_ref().map(|x| x.len()), Some(2));
+    }
+}
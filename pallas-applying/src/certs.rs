@@ -0,0 +1,374 @@
+//! Structural checks over the certificates of a transaction
+
+use std::collections::BTreeMap;
+
+use pallas_crypto::hash::Hash;
+use pallas_primitives::{
+    alonzo::{Certificate, Relay, StakeCredential},
+    babbage::Script,
+};
+use pallas_traverse::{ComputeHash, MultiEraTx};
+
+use crate::{
+    native_script::{eval_native_script, NativeScriptContext},
+    witnesses::is_script_satisfied,
+    UtxoStore, ValidationError, ValidationResult,
+};
+
+/// Maximum length in bytes of a DNS name within a pool relay
+const MAX_DNS_NAME_LEN: usize = 64;
+
+/// Maximum length in bytes of the URL of the pool metadata
+const MAX_POOL_METADATA_URL_LEN: usize = 64;
+
+/// Checks the relays and the metadata of every pool registration
+///
+/// Relays must carry ports fitting in 16 bits, 4-byte IPv4 and 16-byte IPv6
+/// addresses and DNS names of at most [MAX_DNS_NAME_LEN] bytes, while the URL
+/// of the metadata is bounded by [MAX_POOL_METADATA_URL_LEN] bytes.
+pub fn check_pool_registrations(metx: &MultiEraTx) -> ValidationResult {
+    for cert in metx.certs() {
+        let (pool, relays, pool_metadata) = match cert.as_alonzo() {
+            Some(Certificate::PoolRegistration {
+                operator,
+                relays,
+                pool_metadata,
+                ..
+            }) => (*operator, relays, pool_metadata),
+            _ => continue,
+        };
+
+        if let Some(index) = relays.iter().position(|x| !is_valid_relay(x)) {
+            return Err(ValidationError::PoolRelayInvalid { pool, index });
+        }
+
+        if let Some(metadata) = pool_metadata {
+            if metadata.url.len() > MAX_POOL_METADATA_URL_LEN {
+                return Err(ValidationError::PoolMetadataUrlTooLong {
+                    pool,
+                    len: metadata.url.len(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that no certificate contradicts an earlier one of the same tx on the
+/// same stake credential
+///
+/// Certificates apply in order, so registering a credential twice without
+/// deregistering it in between, deregistering it twice without registering it
+/// in between, or delegating it once deregistered can never succeed, whatever
+/// the state of the ledger. Both the earlier and the later certificate are
+/// reported, by their index. Delegating the same credential more than once
+/// is merely redundant, the last delegation winning.
+pub fn check_contradictory_certificates(metx: &MultiEraTx) -> ValidationResult {
+    // index of the last (de)registration of each credential, and whether it
+    // registered the credential
+    let mut last: BTreeMap<&StakeCredential, (usize, bool)> = BTreeMap::new();
+
+    let certs = metx.certs();
+
+    for (index_b, cert) in certs.iter().enumerate() {
+        let (credential, registration) = match cert.as_alonzo() {
+            Some(Certificate::StakeRegistration(x)) => (x, Some(true)),
+            Some(Certificate::StakeDeregistration(x)) => (x, Some(false)),
+            Some(Certificate::StakeDelegation(x, _)) => (x, None),
+            _ => continue,
+        };
+
+        let contradiction = match (last.get(credential), registration) {
+            (Some((index_a, registered)), Some(x)) if *registered == x => Some(*index_a),
+            (Some((index_a, false)), None) => Some(*index_a),
+            _ => None,
+        };
+
+        if let Some(index_a) = contradiction {
+            return Err(ValidationError::ContradictoryCertificates { index_a, index_b });
+        }
+
+        if let Some(x) = registration {
+            last.insert(credential, (index_b, x));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every certificate of a script stake credential is authorized by
+/// that script
+///
+/// Just like for withdrawals, the script has to be a native one evaluating
+/// successfully or a Plutus one, carried in the witness set or as the
+/// reference script of an output the tx spends or references. Registrations
+/// need no witness, so only deregistrations and delegations are checked.
+pub fn check_certificate_scripts<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> ValidationResult {
+    let context = NativeScriptContext::from_tx(metx);
+
+    let (inputs, reference_inputs) = (metx.inputs(), metx.reference_inputs());
+    let outputs: Vec<_> = inputs
+        .iter()
+        .chain(reference_inputs.iter())
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .collect();
+    let reference_scripts: Vec<_> = outputs
+        .iter()
+        .filter_map(|x| x.script_ref().map(|x| &x.0))
+        .collect();
+
+    for cert in metx.certs() {
+        let hash = match cert.as_alonzo() {
+            Some(Certificate::StakeDeregistration(StakeCredential::Scripthash(x)))
+            | Some(Certificate::StakeDelegation(StakeCredential::Scripthash(x), _)) => x,
+            _ => continue,
+        };
+
+        let referenced = reference_scripts
+            .iter()
+            .any(|x| is_reference_script_satisfied(x, hash, &context));
+
+        if !referenced && !is_script_satisfied(metx, hash, &context) {
+            return Err(ValidationError::CertificateScriptMissing(*hash));
+        }
+    }
+
+    Ok(())
+}
+
+fn is_reference_script_satisfied(
+    script: &Script,
+    hash: &Hash<28>,
+    context: &NativeScriptContext,
+) -> bool {
+    match script {
+        Script::NativeScript(x) => {
+            x.compute_hash() == *hash && eval_native_script(x, context).is_ok()
+        }
+        Script::PlutusV1Script(x) => x.compute_hash() == *hash,
+        Script::PlutusV2Script(x) => x.compute_hash() == *hash,
+    }
+}
+
+fn is_valid_relay(relay: &Relay) -> bool {
+    let valid_port = |port: &Option<u32>| port.is_none_or(|x| x <= u16::MAX as u32);
+    let valid_dns_name = |name: &String| name.len() <= MAX_DNS_NAME_LEN;
+
+    match relay {
+        Relay::SingleHostAddr(port, ipv4, ipv6) => {
+            valid_port(port)
+                && ipv4.as_ref().is_none_or(|x| x.len() == 4)
+                && ipv6.as_ref().is_none_or(|x| x.len() == 16)
+        }
+        Relay::SingleHostName(port, name) => valid_port(port) && valid_dns_name(name),
+        Relay::MultiHostName(name) => valid_dns_name(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::*, UTxOs};
+
+    use std::borrow::Cow;
+
+    use pallas_codec::{minicbor, utils::CborWrap};
+    use pallas_primitives::{
+        alonzo::{NativeScript, PoolMetadata, RationalNumber},
+        babbage::{self, MintedTransactionOutput},
+    };
+    use pallas_traverse::{Era, MultiEraOutput, OutputRef};
+
+    fn registration_tx(relays: Vec<Relay>, url: &str) -> Vec<u8> {
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.certificates = Some(vec![Certificate::PoolRegistration {
+            operator: Hash::new([1; 28]),
+            vrf_keyhash: Hash::new([0; 32]),
+            pledge: 0,
+            cost: 340_000_000,
+            margin: RationalNumber {
+                numerator: 1,
+                denominator: 100,
+            },
+            reward_account: vec![0xe0; 29].into(),
+            pool_owners: vec![],
+            relays,
+            pool_metadata: Some(PoolMetadata {
+                url: url.to_string(),
+                hash: Hash::new([0; 32]),
+            }),
+        }]);
+
+        alonzo_tx(body, &[secret_key(1)], vec![])
+    }
+
+    #[test]
+    fn relays() {
+        let valid = vec![
+            Relay::SingleHostAddr(Some(3001), Some(vec![10, 0, 0, 1].into()), None),
+            Relay::SingleHostName(None, "relay.example.com".to_string()),
+            Relay::MultiHostName("a".repeat(64)),
+        ];
+
+        let bytes = registration_tx(valid.clone(), "https://example.com/pool.json");
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(check_pool_registrations(&metx), Ok(()));
+
+        let invalid = [
+            Relay::SingleHostAddr(Some(65536), None, None),
+            Relay::SingleHostAddr(None, None, Some(vec![0; 4].into())),
+            Relay::MultiHostName("a".repeat(65)),
+        ];
+
+        for relay in invalid {
+            let bytes = registration_tx([valid.clone(), vec![relay]].concat(), "");
+            let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+            assert_eq!(
+                check_pool_registrations(&metx),
+                Err(ValidationError::PoolRelayInvalid {
+                    pool: Hash::new([1; 28]),
+                    index: 3
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn metadata_url_too_long() {
+        let url = format!("https://{}", "a".repeat(57));
+
+        let bytes = registration_tx(vec![], &url);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_pool_registrations(&metx),
+            Err(ValidationError::PoolMetadataUrlTooLong {
+                pool: Hash::new([1; 28]),
+                len: 65
+            })
+        );
+    }
+
+    #[test]
+    fn contradictory_certificates() {
+        let credential = StakeCredential::AddrKeyhash(key_hash(&secret_key(1)));
+        let registration = Certificate::StakeRegistration(credential.clone());
+        let deregistration = Certificate::StakeDeregistration(credential.clone());
+        let delegation = Certificate::StakeDelegation(credential, Hash::new([1; 28]));
+
+        let cases = [
+            (
+                vec![registration.clone(), delegation.clone(), delegation.clone()],
+                Ok(()),
+            ),
+            (
+                vec![
+                    registration.clone(),
+                    deregistration.clone(),
+                    registration.clone(),
+                ],
+                Ok(()),
+            ),
+            (
+                vec![
+                    registration.clone(),
+                    delegation.clone(),
+                    registration.clone(),
+                ],
+                Err((0, 2)),
+            ),
+            (
+                vec![deregistration.clone(), deregistration.clone()],
+                Err((0, 1)),
+            ),
+            (vec![deregistration, delegation], Err((0, 1))),
+        ];
+
+        for (certs, expected) in cases {
+            let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+            body.certificates = Some(certs);
+            let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+            let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+            assert_eq!(
+                check_contradictory_certificates(&metx),
+                expected.map_err(|(index_a, index_b)| {
+                    ValidationError::ContradictoryCertificates { index_a, index_b }
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn script_delegation() {
+        let script = NativeScript::ScriptPubkey(key_hash(&secret_key(1)));
+        let credential = StakeCredential::Scripthash(script.compute_hash());
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.certificates = Some(vec![Certificate::StakeDelegation(
+            credential,
+            Hash::new([1; 28]),
+        )]);
+
+        let bytes = alonzo_tx(body.clone(), &[secret_key(1)], vec![script.clone()]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(check_certificate_scripts(&metx, &UTxOs::new()), Ok(()));
+
+        let bytes = alonzo_tx(body, &[secret_key(2)], vec![script.clone()]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_certificate_scripts(&metx, &UTxOs::new()),
+            Err(ValidationError::CertificateScriptMissing(
+                script.compute_hash()
+            ))
+        );
+    }
+
+    #[test]
+    fn script_delegation_by_reference() {
+        let script = NativeScript::ScriptPubkey(key_hash(&secret_key(1)));
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let output = match babbage_output(&address, 2_000_000) {
+            babbage::TransactionOutput::PostAlonzo(x) => {
+                babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+                    script_ref: Some(CborWrap(Script::NativeScript(script.clone()))),
+                    ..x
+                })
+            }
+            x => x,
+        };
+        let output_bytes = minicbor::to_vec(output).unwrap();
+        let output: MintedTransactionOutput = minicbor::decode(&output_bytes).unwrap();
+
+        let input = tx_input(2, 0);
+        let utxos = UTxOs::from([(
+            OutputRef::new(input.transaction_id, input.index),
+            MultiEraOutput::Babbage(Box::new(Cow::Owned(output))),
+        )]);
+
+        let mut body = babbage_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.reference_inputs = Some(vec![input]);
+        body.certificates = Some(vec![Certificate::StakeDeregistration(
+            StakeCredential::Scripthash(script.compute_hash()),
+        )]);
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(check_certificate_scripts(&metx, &utxos), Ok(()));
+        assert_eq!(
+            check_certificate_scripts(&metx, &UTxOs::new()),
+            Err(ValidationError::CertificateScriptMissing(
+                script.compute_hash()
+            ))
+        );
+    }
+}
@@ -0,0 +1,442 @@
+//! Protocol parameters and other chain settings the validation depends on
+
+use std::collections::BTreeMap;
+
+use pallas_crypto::hash::Hash;
+use pallas_primitives::{
+    alonzo::{ExUnits, ProtocolVersion, RationalNumber},
+    babbage::CostMdls,
+};
+
+/// Protocol parameters for validating Shelley, Allegra and Mary transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShelleyProtParams {
+    /// Fee coefficient per byte of transaction (`minFeeA`)
+    pub min_fee_a: u64,
+
+    /// Constant fee component (`minFeeB`)
+    pub min_fee_b: u64,
+
+    /// Maximum size of a transaction, in bytes (`maxTxSize`)
+    pub max_tx_size: u64,
+
+    /// Deposit paid when registering a stake credential (`keyDeposit`)
+    pub key_deposit: u64,
+
+    /// Deposit paid when registering a stake pool (`poolDeposit`)
+    pub pool_deposit: u64,
+
+    /// Minimum lovelace of an output (`minUTxOValue`)
+    pub min_utxo_value: u64,
+}
+
+/// Protocol parameters for validating Byron transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByronProtParams {
+    /// Constant component of the fee policy
+    pub summand: u64,
+
+    /// Fee per byte of transaction
+    pub multiplier: u64,
+
+    /// Maximum size of a transaction, in bytes
+    pub max_tx_size: u64,
+
+    /// Maximum number of outputs of a transaction, if any
+    pub max_tx_outputs: Option<u32>,
+
+    /// Magic of the network, signed over by Byron witnesses and update
+    /// payloads
+    pub protocol_magic: u32,
+}
+
+/// Protocol parameters for validating Alonzo transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlonzoProtParams {
+    /// Fee coefficient per byte of transaction (`minFeeA`)
+    pub min_fee_a: u64,
+
+    /// Constant fee component (`minFeeB`)
+    pub min_fee_b: u64,
+
+    /// Maximum size of a transaction, in bytes (`maxTxSize`)
+    pub max_tx_size: u64,
+
+    /// Deposit paid when registering a stake credential (`keyDeposit`)
+    pub key_deposit: u64,
+
+    /// Deposit paid when registering a stake pool (`poolDeposit`)
+    pub pool_deposit: u64,
+
+    /// Lovelace charged per unit of memory used by scripts
+    pub mem_price: RationalNumber,
+
+    /// Lovelace charged per CPU step used by scripts
+    pub step_price: RationalNumber,
+
+    /// Maximum execution units the scripts of all the transactions of a block
+    /// may use together (`maxBlockExUnits`)
+    pub max_block_ex_units: ExUnits,
+
+    /// Maximum number of collateral inputs a transaction may declare
+    pub max_collateral_inputs: u32,
+
+    /// Collateral required from a transaction running scripts, as a
+    /// percentage of its fee (`collateralPercentage`)
+    pub collateral_percentage: u64,
+
+    /// Cost models of the Plutus languages, hashed into the script data hash
+    pub cost_models: CostMdls,
+}
+
+/// Protocol parameters for validating Babbage transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BabbageProtParams {
+    /// Fee coefficient per byte of transaction (`minFeeA`)
+    pub min_fee_a: u64,
+
+    /// Constant fee component (`minFeeB`)
+    pub min_fee_b: u64,
+
+    /// Maximum size of a transaction, in bytes (`maxTxSize`)
+    pub max_tx_size: u64,
+
+    /// Deposit paid when registering a stake credential (`keyDeposit`)
+    pub key_deposit: u64,
+
+    /// Deposit paid when registering a stake pool (`poolDeposit`)
+    pub pool_deposit: u64,
+
+    /// Lovelace charged per unit of memory used by scripts
+    pub mem_price: RationalNumber,
+
+    /// Lovelace charged per CPU step used by scripts
+    pub step_price: RationalNumber,
+
+    /// Maximum execution units the scripts of all the transactions of a block
+    /// may use together (`maxBlockExUnits`)
+    pub max_block_ex_units: ExUnits,
+
+    /// Maximum number of collateral inputs a transaction may declare
+    pub max_collateral_inputs: u32,
+
+    /// Collateral required from a transaction running scripts, as a
+    /// percentage of its fee (`collateralPercentage`)
+    pub collateral_percentage: u64,
+
+    /// Lovelace required per byte of output (`coinsPerUTxOByte`)
+    pub coins_per_utxo_byte: u64,
+
+    /// Cost models of the Plutus languages, hashed into the script data hash
+    pub cost_models: CostMdls,
+}
+
+/// Protocol parameters for computing the fees of Conway transactions
+///
+/// There are no Conway primitives to validate yet, so these are only taken by
+/// the fee helpers and aren't part of [ProtocolParams].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConwayProtParams {
+    /// Fee coefficient per byte of transaction (`minFeeA`)
+    pub min_fee_a: u64,
+
+    /// Constant fee component (`minFeeB`)
+    pub min_fee_b: u64,
+
+    /// Lovelace charged per unit of memory used by scripts
+    pub mem_price: RationalNumber,
+
+    /// Lovelace charged per CPU step used by scripts
+    pub step_price: RationalNumber,
+
+    /// Lovelace charged per byte of the reference scripts of the first tier
+    /// (`minFeeRefScriptCostPerByte`)
+    pub min_fee_ref_script_cost_per_byte: RationalNumber,
+
+    /// Maximum total size in bytes of the reference scripts a transaction may
+    /// use (`maxRefScriptSizePerTx`)
+    pub max_ref_scripts_size: u64,
+}
+
+/// Protocol parameters of any era
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProtocolParams {
+    Byron(ByronProtParams),
+    Shelley(ShelleyProtParams),
+    Alonzo(AlonzoProtParams),
+    Babbage(BabbageProtParams),
+}
+
+/// The chain settings a transaction is validated under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Environment {
+    /// Protocol parameters in effect, which must belong to the era of the
+    /// transactions being validated
+    pub prot_params: ProtocolParams,
+
+    /// Protocol version in effect, as `(major, minor)`, which must fall within
+    /// the era of the transactions being validated
+    pub protocol_version: ProtocolVersion,
+
+    /// Genesis delegates authorizing protocol parameter updates
+    pub genesis_delegates: GenesisDelegates,
+
+    /// Current slot, i.e. the one of the block including the transactions,
+    /// if known
+    ///
+    /// Only when given is a transaction checked to fall within its validity
+    /// interval, from its validity start up to its TTL.
+    pub slot: Option<u64>,
+
+    /// Whether to also reject transactions the ledger accepts but which are
+    /// likely mistaken
+    pub strictness: Strictness,
+}
+
+/// The genesis delegates whose quorum authorizes protocol parameter updates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GenesisDelegates {
+    /// Key hash of the delegate of each genesis key, keyed by the hash of the
+    /// genesis key
+    pub delegates: BTreeMap<Hash<28>, Hash<28>>,
+
+    /// Number of distinct delegates an update has to be authorized by
+    pub quorum: usize,
+}
+
+impl ProtocolParams {
+    /// The deposits for registering a stake credential and a stake pool, as
+    /// `(keyDeposit, poolDeposit)`, defined from Shelley onwards
+    pub fn deposits(&self) -> Option<(u64, u64)> {
+        match self {
+            ProtocolParams::Byron(_) => None,
+            ProtocolParams::Shelley(x) => Some((x.key_deposit, x.pool_deposit)),
+            ProtocolParams::Alonzo(x) => Some((x.key_deposit, x.pool_deposit)),
+            ProtocolParams::Babbage(x) => Some((x.key_deposit, x.pool_deposit)),
+        }
+    }
+
+    fn era_name(&self) -> &'static str {
+        match self {
+            ProtocolParams::Byron(_) => "Byron",
+            ProtocolParams::Shelley(_) => "Shelley",
+            ProtocolParams::Alonzo(_) => "Alonzo",
+            ProtocolParams::Babbage(_) => "Babbage",
+        }
+    }
+
+    /// Lists the name and the displayed value of every field of the params
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        match self {
+            ProtocolParams::Byron(x) => vec![
+                ("summand", x.summand.to_string()),
+                ("multiplier", x.multiplier.to_string()),
+                ("max_tx_size", x.max_tx_size.to_string()),
+                ("max_tx_outputs", format!("{:?}", x.max_tx_outputs)),
+                ("protocol_magic", x.protocol_magic.to_string()),
+            ],
+            ProtocolParams::Shelley(x) => vec![
+                ("min_fee_a", x.min_fee_a.to_string()),
+                ("min_fee_b", x.min_fee_b.to_string()),
+                ("max_tx_size", x.max_tx_size.to_string()),
+                ("key_deposit", x.key_deposit.to_string()),
+                ("pool_deposit", x.pool_deposit.to_string()),
+                ("min_utxo_value", x.min_utxo_value.to_string()),
+            ],
+            ProtocolParams::Alonzo(x) => vec![
+                ("min_fee_a", x.min_fee_a.to_string()),
+                ("min_fee_b", x.min_fee_b.to_string()),
+                ("max_tx_size", x.max_tx_size.to_string()),
+                ("key_deposit", x.key_deposit.to_string()),
+                ("pool_deposit", x.pool_deposit.to_string()),
+                ("mem_price", display_rational(&x.mem_price)),
+                ("step_price", display_rational(&x.step_price)),
+                (
+                    "max_block_ex_units",
+                    display_ex_units(&x.max_block_ex_units),
+                ),
+                ("max_collateral_inputs", x.max_collateral_inputs.to_string()),
+                ("collateral_percentage", x.collateral_percentage.to_string()),
+                ("cost_models", format!("{:?}", x.cost_models)),
+            ],
+            ProtocolParams::Babbage(x) => vec![
+                ("min_fee_a", x.min_fee_a.to_string()),
+                ("min_fee_b", x.min_fee_b.to_string()),
+                ("max_tx_size", x.max_tx_size.to_string()),
+                ("key_deposit", x.key_deposit.to_string()),
+                ("pool_deposit", x.pool_deposit.to_string()),
+                ("mem_price", display_rational(&x.mem_price)),
+                ("step_price", display_rational(&x.step_price)),
+                (
+                    "max_block_ex_units",
+                    display_ex_units(&x.max_block_ex_units),
+                ),
+                ("max_collateral_inputs", x.max_collateral_inputs.to_string()),
+                ("collateral_percentage", x.collateral_percentage.to_string()),
+                ("coins_per_utxo_byte", x.coins_per_utxo_byte.to_string()),
+                ("cost_models", format!("{:?}", x.cost_models)),
+            ],
+        }
+    }
+}
+
+fn display_rational(x: &RationalNumber) -> String {
+    format!("{}/{}", x.numerator, x.denominator)
+}
+
+fn display_ex_units(x: &ExUnits) -> String {
+    format!("{} mem, {} steps", x.mem, x.steps)
+}
+
+/// A protocol parameter whose value differs between two sets of params
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// Lists the parameters whose value changed from `old` to `new`
+///
+/// Params from different eras have different fields, so in that case the only
+/// change reported is the one of the `era` itself.
+pub fn diff_params(old: &ProtocolParams, new: &ProtocolParams) -> Vec<ParamChange> {
+    if old.era_name() != new.era_name() {
+        return vec![ParamChange {
+            field: "era",
+            old: old.era_name().into(),
+            new: new.era_name().into(),
+        }];
+    }
+
+    old.fields()
+        .into_iter()
+        .zip(new.fields())
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((field, old), (_, new))| ParamChange { field, old, new })
+        .collect()
+}
+
+/// How far the validation goes beyond the rules enforced by the ledger
+///
+/// Strict mode adds checks for conditions the ledger accepts but which
+/// usually point to a mistake in the construction of the transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    #[default]
+    Lenient,
+    Strict,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_fields() {
+        let old = ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        };
+
+        let new = ShelleyProtParams {
+            min_fee_a: 45,
+            key_deposit: 3_000_000,
+            ..old.clone()
+        };
+
+        assert_eq!(
+            diff_params(
+                &ProtocolParams::Shelley(old.clone()),
+                &ProtocolParams::Shelley(new)
+            ),
+            vec![
+                ParamChange {
+                    field: "min_fee_a",
+                    old: "44".into(),
+                    new: "45".into(),
+                },
+                ParamChange {
+                    field: "key_deposit",
+                    old: "2000000".into(),
+                    new: "3000000".into(),
+                },
+            ]
+        );
+
+        assert_eq!(
+            diff_params(
+                &ProtocolParams::Shelley(old.clone()),
+                &ProtocolParams::Shelley(old)
+            ),
+            vec![]
+        );
+
+        let old = ByronProtParams {
+            summand: 155381,
+            multiplier: 44,
+            max_tx_size: 4096,
+            max_tx_outputs: None,
+            protocol_magic: 764824073,
+        };
+
+        let new = ByronProtParams {
+            protocol_magic: 1097911063,
+            ..old.clone()
+        };
+
+        assert_eq!(
+            diff_params(&ProtocolParams::Byron(old), &ProtocolParams::Byron(new)),
+            vec![ParamChange {
+                field: "protocol_magic",
+                old: "764824073".into(),
+                new: "1097911063".into(),
+            }]
+        );
+
+        let old = AlonzoProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            mem_price: RationalNumber {
+                numerator: 577,
+                denominator: 10000,
+            },
+            step_price: RationalNumber {
+                numerator: 721,
+                denominator: 10000000,
+            },
+            max_block_ex_units: ExUnits {
+                mem: 50_000_000,
+                steps: 40_000_000_000,
+            },
+            max_collateral_inputs: 3,
+            collateral_percentage: 150,
+            cost_models: CostMdls {
+                plutus_v1: None,
+                plutus_v2: None,
+            },
+        };
+
+        let new = AlonzoProtParams {
+            collateral_percentage: 200,
+            ..old.clone()
+        };
+
+        assert_eq!(
+            diff_params(&ProtocolParams::Alonzo(old), &ProtocolParams::Alonzo(new)),
+            vec![ParamChange {
+                field: "collateral_percentage",
+                old: "150".into(),
+                new: "200".into(),
+            }]
+        );
+    }
+}
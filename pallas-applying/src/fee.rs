@@ -0,0 +1,562 @@
+//! Fee models of the different eras
+
+use std::collections::HashSet;
+
+use pallas_codec::{minicbor, utils::Nullable};
+use pallas_primitives::{
+    alonzo::{ExUnits, RationalNumber},
+    babbage::Script,
+};
+use pallas_traverse::MultiEraTx;
+
+use crate::{
+    byron::get_byron_tx_size,
+    environment::{
+        AlonzoProtParams, BabbageProtParams, ByronProtParams, ConwayProtParams, ShelleyProtParams,
+    },
+    UtxoStore, ValidationError, ValidationResult,
+};
+
+/// Size in bytes of each tier of the reference scripts fee
+const REF_SCRIPT_TIER_SIZE: u64 = 25_600;
+
+/// Growth of the price per byte from one tier of the reference scripts fee to
+/// the next one, as a fraction (1.2)
+const REF_SCRIPT_TIER_MULTIPLIER: (u128, u128) = (6, 5);
+
+/// Size in bytes of a transaction, as measured for fee purposes
+pub type TxSize = u64;
+
+/// Size of a transaction the fee models charge for
+///
+/// Always measured on the bytes the transaction was decoded from, so it can't
+/// drift from what the node charges for:
+///
+/// - Byron: the tx payload, that is the tx and its witnesses together with the
+///   header of the array wrapping them (see [get_byron_tx_size]).
+/// - Shelley onwards: the whole serialized tx, that is the header of the array
+///   wrapping it, the body, the witness set and the auxiliary data (a single
+///   byte when null); only the validity flag of Alonzo onwards is left out.
+pub fn tx_fee_size(metx: &MultiEraTx) -> TxSize {
+    if let Some(mtxp) = metx.as_byron() {
+        return get_byron_tx_size(mtxp);
+    }
+
+    let (body, witness_set, aux_data) = match (metx.as_alonzo(), metx.as_babbage()) {
+        (Some(x), _) => (
+            x.transaction_body.raw_cbor().len(),
+            x.transaction_witness_set.raw_cbor().len(),
+            &x.auxiliary_data,
+        ),
+        (_, Some(x)) => (
+            x.transaction_body.raw_cbor().len(),
+            x.transaction_witness_set.raw_cbor().len(),
+            &x.auxiliary_data,
+        ),
+        _ => return metx.size() as u64,
+    };
+
+    let aux_data = match aux_data {
+        Nullable::Some(x) => x.raw_cbor().len(),
+        _ => 1,
+    };
+
+    // one more byte for the header of the array wrapping everything
+    (1 + body + witness_set + aux_data) as u64
+}
+
+/// Computes the minimum fee a transaction has to pay
+///
+/// Implemented by the protocol params of each era, so validators get the
+/// model from the params they are given. A fee too large for a `u64`, which
+/// a tx budgeting absurd execution units can ask for, fails with
+/// [ValidationError::FeeOverflow].
+pub trait FeeModel {
+    /// Minimum fee of a transaction whose scripts use `ex_units` in total
+    fn min_fee(&self, metx: &MultiEraTx, ex_units: Option<ExUnits>)
+        -> Result<u64, ValidationError>;
+}
+
+impl FeeModel for ByronProtParams {
+    fn min_fee(
+        &self,
+        metx: &MultiEraTx,
+        _ex_units: Option<ExUnits>,
+    ) -> Result<u64, ValidationError> {
+        linear_fee(self.multiplier, tx_fee_size(metx), self.summand)
+            .ok_or(ValidationError::FeeOverflow)
+    }
+}
+
+impl FeeModel for ShelleyProtParams {
+    fn min_fee(
+        &self,
+        metx: &MultiEraTx,
+        _ex_units: Option<ExUnits>,
+    ) -> Result<u64, ValidationError> {
+        linear_fee(self.min_fee_a, tx_fee_size(metx), self.min_fee_b)
+            .ok_or(ValidationError::FeeOverflow)
+    }
+}
+
+impl FeeModel for AlonzoProtParams {
+    fn min_fee(
+        &self,
+        metx: &MultiEraTx,
+        ex_units: Option<ExUnits>,
+    ) -> Result<u64, ValidationError> {
+        linear_fee(self.min_fee_a, tx_fee_size(metx), self.min_fee_b)
+            .and_then(|x| x.checked_add(script_fee(ex_units, &self.mem_price, &self.step_price)?))
+            .ok_or(ValidationError::FeeOverflow)
+    }
+}
+
+impl FeeModel for BabbageProtParams {
+    fn min_fee(
+        &self,
+        metx: &MultiEraTx,
+        ex_units: Option<ExUnits>,
+    ) -> Result<u64, ValidationError> {
+        linear_fee(self.min_fee_a, tx_fee_size(metx), self.min_fee_b)
+            .and_then(|x| x.checked_add(script_fee(ex_units, &self.mem_price, &self.step_price)?))
+            .ok_or(ValidationError::FeeOverflow)
+    }
+}
+
+impl FeeModel for ConwayProtParams {
+    /// The fee without the reference scripts component, which needs the UTxO
+    /// set (see [conway_min_fee])
+    fn min_fee(
+        &self,
+        metx: &MultiEraTx,
+        ex_units: Option<ExUnits>,
+    ) -> Result<u64, ValidationError> {
+        linear_fee(self.min_fee_a, tx_fee_size(metx), self.min_fee_b)
+            .and_then(|x| x.checked_add(script_fee(ex_units, &self.mem_price, &self.step_price)?))
+            .ok_or(ValidationError::FeeOverflow)
+    }
+}
+
+/// Computes `a * size + b`, if it fits
+fn linear_fee(a: u64, size: TxSize, b: u64) -> Option<u64> {
+    a.checked_mul(size)?.checked_add(b)
+}
+
+/// Adds up the execution units budgeted by the redeemers of a transaction,
+/// if it has any
+pub fn total_ex_units(metx: &MultiEraTx) -> Option<ExUnits> {
+    let redeemers = metx.redeemers();
+
+    if redeemers.is_empty() {
+        return None;
+    }
+
+    Some(
+        redeemers
+            .iter()
+            .fold(ExUnits { mem: 0, steps: 0 }, |total, x| ExUnits {
+                mem: total.mem.saturating_add(x.ex_units.mem),
+                steps: total.steps.saturating_add(x.ex_units.steps),
+            }),
+    )
+}
+
+/// Checks that the declared fee covers the minimum of the given fee model
+///
+/// Both the size and the execution units are taken from the tx exactly as
+/// submitted, collateral return and every other optional field included, so
+/// the check agrees with the one done by the node however the fee was
+/// estimated.
+pub fn check_fee(metx: &MultiEraTx, model: &impl FeeModel) -> ValidationResult {
+    let fee = metx.fee().unwrap_or_default();
+    let min_fee = model.min_fee(metx, total_ex_units(metx))?;
+
+    match fee >= min_fee {
+        true => Ok(()),
+        false => Err(ValidationError::FeesBelowMin { fee, min_fee }),
+    }
+}
+
+/// Checks the size of a transaction against `maxTxSize`
+///
+/// The size is the one fees are charged for (see [tx_fee_size]), so from
+/// Shelley onwards the witnesses and the auxiliary data count as much as the
+/// body does.
+pub fn check_tx_size(metx: &MultiEraTx, max: u64) -> ValidationResult {
+    let size = tx_fee_size(metx);
+
+    match size <= max {
+        true => Ok(()),
+        false => Err(ValidationError::MaxTxSizeExceeded { size, max }),
+    }
+}
+
+/// Computes the minimum fee of a Conway transaction, including the fee for the
+/// reference scripts of its spent and reference inputs
+pub fn conway_min_fee<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    ex_units: Option<ExUnits>,
+    prot_pps: &ConwayProtParams,
+) -> Result<u64, ValidationError> {
+    let size = ref_scripts_size(metx, utxos)?;
+
+    prot_pps
+        .min_fee(metx, ex_units)?
+        .checked_add(ref_script_fee(size, prot_pps))
+        .ok_or(ValidationError::FeeOverflow)
+}
+
+/// Adds up the size of the scripts held by the outputs a transaction spends or
+/// references, counting each output once
+pub fn ref_scripts_size<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> Result<u64, ValidationError> {
+    let mut seen = HashSet::new();
+    let mut size = 0;
+
+    for input in metx.inputs().iter().chain(metx.reference_inputs().iter()) {
+        let output_ref = input.output_ref();
+
+        if !seen.insert(output_ref.clone()) {
+            continue;
+        }
+
+        let output = utxos
+            .resolve(&output_ref)
+            .ok_or(ValidationError::InputNotInUTxO(output_ref))?;
+
+        size += match output.script_ref().map(|x| &x.0) {
+            Some(Script::NativeScript(x)) => minicbor::to_vec(x).unwrap().len(),
+            Some(Script::PlutusV1Script(x)) => x.0.len(),
+            Some(Script::PlutusV2Script(x)) => x.0.len(),
+            None => 0,
+        } as u64;
+    }
+
+    Ok(size)
+}
+
+/// Checks that the reference scripts used by a Conway transaction add up to
+/// no more than the protocol maximum, measured like for their fee
+pub fn check_ref_scripts_size<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ConwayProtParams,
+) -> ValidationResult {
+    let size = ref_scripts_size(metx, utxos)?;
+    let max = prot_pps.max_ref_scripts_size;
+
+    match size <= max {
+        true => Ok(()),
+        false => Err(ValidationError::RefScriptsTooLarge { size, max }),
+    }
+}
+
+/// Computes the tiered fee for `total_ref_script_size` bytes of reference
+/// scripts
+///
+/// Each tier of [REF_SCRIPT_TIER_SIZE] bytes is priced 1.2 times the previous
+/// one, starting at `minFeeRefScriptCostPerByte`, and the total is rounded
+/// down. Saturates at `u64::MAX` for sizes way past any protocol limit.
+pub fn ref_script_fee(total_ref_script_size: u64, prot_pps: &ConwayProtParams) -> u64 {
+    let price = &prot_pps.min_fee_ref_script_cost_per_byte;
+    let (growth, shrink) = REF_SCRIPT_TIER_MULTIPLIER;
+
+    let rest = (total_ref_script_size % REF_SCRIPT_TIER_SIZE) as u128;
+
+    // sum over tier i of `size_i * price * 1.2^i`, brought to the common
+    // denominator `price.denominator * 5^tiers`
+    let fee = || -> Option<u128> {
+        let tiers = u32::try_from(total_ref_script_size / REF_SCRIPT_TIER_SIZE).ok()?;
+        let mut numerator = rest.checked_mul(growth.checked_pow(tiers)?)?;
+
+        for tier in 0..tiers {
+            let term = (REF_SCRIPT_TIER_SIZE as u128)
+                .checked_mul(growth.checked_pow(tier)?)?
+                .checked_mul(shrink.checked_pow(tiers - tier)?)?;
+
+            numerator = numerator.checked_add(term)?;
+        }
+
+        let numerator = numerator.checked_mul(price.numerator as u128)?;
+        let denominator = (price.denominator as u128).checked_mul(shrink.checked_pow(tiers)?)?;
+
+        Some(numerator / denominator)
+    };
+
+    fee().map_or(u64::MAX, |x| x.try_into().unwrap_or(u64::MAX))
+}
+
+/// Computes `ceiling(mem * mem_price + steps * step_price)`, if it fits
+fn script_fee(
+    ex_units: Option<ExUnits>,
+    mem_price: &RationalNumber,
+    step_price: &RationalNumber,
+) -> Option<u64> {
+    let ExUnits { mem, steps } = match ex_units {
+        Some(x) => x,
+        None => return Some(0),
+    };
+
+    let term = |units: u128, price: &RationalNumber, other: &RationalNumber| {
+        units
+            .checked_mul(price.numerator as u128)?
+            .checked_mul(other.denominator as u128)
+    };
+
+    let mem = term(mem as u128, mem_price, step_price)?;
+    let steps = term(steps as u128, step_price, mem_price)?;
+
+    let numerator = mem.checked_add(steps)?;
+    let denominator = (mem_price.denominator as u128)
+        .checked_mul(step_price.denominator as u128)
+        .filter(|x| *x > 0)?;
+
+    u64::try_from(numerator.div_ceil(denominator)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::*, UTxOs};
+
+    use std::borrow::Cow;
+
+    use pallas_codec::{
+        minicbor,
+        utils::{CborWrap, KeyValuePairs},
+    };
+    use pallas_primitives::{
+        alonzo::{self, AuxiliaryData, Metadatum},
+        babbage::{self, PlutusV2Script},
+        byron::MintedTxPayload,
+    };
+    use pallas_traverse::{Era, MultiEraOutput, OutputRef};
+
+    #[test]
+    fn linear_models() {
+        let byron = ByronProtParams {
+            summand: 155381,
+            multiplier: 44,
+            max_tx_size: 4096,
+            max_tx_outputs: None,
+            protocol_magic: 764824073,
+        };
+
+        let bytes = byron_tx(vec![byron_input(1, 0)], vec![byron_output(&[], 1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+        let metx = MultiEraTx::from_byron(&mtxp);
+
+        assert_eq!(
+            byron.min_fee(&metx, None),
+            Ok(155381 + 44 * bytes.len() as u64)
+        );
+
+        let shelley = ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        };
+
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            shelley.min_fee(&metx, None),
+            Ok(155381 + 44 * tx_fee_size(&metx))
+        );
+
+        let shelley = ShelleyProtParams {
+            min_fee_a: u64::MAX,
+            ..shelley
+        };
+
+        assert_eq!(
+            shelley.min_fee(&metx, None),
+            Err(ValidationError::FeeOverflow)
+        );
+        assert_eq!(
+            check_fee(&metx, &shelley),
+            Err(ValidationError::FeeOverflow)
+        );
+    }
+
+    #[test]
+    fn fee_sizes() {
+        let bytes = byron_tx(vec![byron_input(1, 0)], vec![byron_output(&[], 1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            tx_fee_size(&MultiEraTx::from_byron(&mtxp)),
+            bytes.len() as u64
+        );
+
+        // everything but the one byte of the validity flag
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(tx_fee_size(&metx), bytes.len() as u64 - 1);
+
+        // present auxiliary data is counted as encoded, along with the header
+        let mut tx: alonzo::Tx = minicbor::decode(&bytes).unwrap();
+        tx.auxiliary_data = Nullable::Some(AuxiliaryData::Shelley(KeyValuePairs::from(vec![(
+            674,
+            Metadatum::Text("fee".into()),
+        )])));
+        let bytes = minicbor::to_vec(&tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+
+        assert_eq!(tx_fee_size(&metx), bytes.len() as u64 - 1);
+    }
+
+    #[test]
+    fn size_covers_witnesses() {
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let body_size = minicbor::to_vec(&body).unwrap().len() as u64;
+
+        let keys: Vec<_> = (1..=4).map(secret_key).collect();
+        let bytes = alonzo_tx(body, &keys, vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        // the body alone fits, the witnesses don't
+        let max = body_size + 100;
+        assert!(tx_fee_size(&metx) > max);
+
+        assert_eq!(
+            check_tx_size(&metx, max),
+            Err(ValidationError::MaxTxSizeExceeded {
+                size: tx_fee_size(&metx),
+                max
+            })
+        );
+        assert_eq!(check_tx_size(&metx, tx_fee_size(&metx)), Ok(()));
+    }
+
+    #[test]
+    fn script_prices_round_up() {
+        let mem_price = RationalNumber {
+            numerator: 577,
+            denominator: 10000,
+        };
+        let step_price = RationalNumber {
+            numerator: 721,
+            denominator: 10000000,
+        };
+
+        let ex_units = ExUnits {
+            mem: 1_000,
+            steps: 1_000_001,
+        };
+
+        // 57.7 + 72.1000721
+        assert_eq!(
+            script_fee(Some(ex_units), &mem_price, &step_price),
+            Some(130)
+        );
+        assert_eq!(script_fee(None, &mem_price, &step_price), Some(0));
+
+        // a budget the tx is free to declare, priced past any u64
+        let ex_units = ExUnits {
+            mem: u32::MAX,
+            steps: u64::MAX,
+        };
+        let step_price = RationalNumber {
+            numerator: 721,
+            denominator: 10,
+        };
+
+        assert_eq!(script_fee(Some(ex_units), &mem_price, &step_price), None);
+    }
+
+    #[test]
+    fn ref_script_tiers() {
+        let prot_pps = ConwayProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            mem_price: RationalNumber {
+                numerator: 577,
+                denominator: 10000,
+            },
+            step_price: RationalNumber {
+                numerator: 721,
+                denominator: 10000000,
+            },
+            min_fee_ref_script_cost_per_byte: RationalNumber {
+                numerator: 15,
+                denominator: 1,
+            },
+            max_ref_scripts_size: 204_800,
+        };
+
+        assert_eq!(ref_script_fee(0, &prot_pps), 0);
+        assert_eq!(ref_script_fee(25_600, &prot_pps), 384_000);
+        assert_eq!(ref_script_fee(30_000, &prot_pps), 384_000 + 4_400 * 18);
+        // 100 bytes at 21.6
+        assert_eq!(ref_script_fee(51_300, &prot_pps), 384_000 + 460_800 + 2_160);
+        assert_eq!(ref_script_fee(u64::MAX, &prot_pps), u64::MAX);
+    }
+
+    #[test]
+    fn ref_scripts_too_large() {
+        let mut prot_pps = ConwayProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            mem_price: RationalNumber {
+                numerator: 577,
+                denominator: 10000,
+            },
+            step_price: RationalNumber {
+                numerator: 721,
+                denominator: 10000000,
+            },
+            min_fee_ref_script_cost_per_byte: RationalNumber {
+                numerator: 15,
+                denominator: 1,
+            },
+            max_ref_scripts_size: 100,
+        };
+
+        let output = match babbage_output(&key_address(key_hash(&secret_key(1))), 2_000_000) {
+            babbage::TransactionOutput::PostAlonzo(x) => {
+                babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+                    script_ref: Some(CborWrap(Script::PlutusV2Script(PlutusV2Script(
+                        vec![0; 101].into(),
+                    )))),
+                    ..x
+                })
+            }
+            x => x,
+        };
+        let output_bytes = minicbor::to_vec(output).unwrap();
+        let output: babbage::MintedTransactionOutput = minicbor::decode(&output_bytes).unwrap();
+
+        let input = tx_input(2, 0);
+        let utxos = UTxOs::from([(
+            OutputRef::new(input.transaction_id, input.index),
+            MultiEraOutput::Babbage(Box::new(Cow::Owned(output))),
+        )]);
+
+        let mut body = babbage_body(vec![], vec![], 200_000);
+        body.reference_inputs = Some(vec![input]);
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_ref_scripts_size(&metx, &utxos, &prot_pps),
+            Err(ValidationError::RefScriptsTooLarge {
+                size: 101,
+                max: 100
+            })
+        );
+
+        prot_pps.max_ref_scripts_size = 101;
+        assert_eq!(check_ref_scripts_size(&metx, &utxos, &prot_pps), Ok(()));
+    }
+}
@@ -0,0 +1,334 @@
+//! Helpers for building the transactions used across the test suites
+
+use std::borrow::Cow;
+
+use pallas_addresses::{Network, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart};
+use pallas_codec::{
+    minicbor,
+    utils::{CborWrap, EmptyMap, KeyValuePairs, MaybeIndefArray, Nullable, TagWrap},
+};
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::SecretKey,
+};
+use pallas_primitives::{
+    alonzo::{
+        Block, NativeScript, TransactionBody, TransactionInput, TransactionOutput, Tx, VKeyWitness,
+        Value, WitnessSet,
+    },
+    babbage, byron,
+};
+use pallas_traverse::{MultiEraOutput, OutputRef};
+
+use crate::{
+    byron::{address_root, byron_signed_data, ADDR_CRC, ADDR_TYPE_PUBKEY},
+    utxo_key, UTxOs,
+};
+
+pub const BYRON_MAINNET_MAGIC: u32 = 764824073;
+
+pub fn secret_key(seed: u8) -> SecretKey {
+    SecretKey::from([seed; SecretKey::SIZE])
+}
+
+pub fn key_hash(key: &SecretKey) -> Hash<28> {
+    Hasher::<224>::hash(key.public_key().as_ref())
+}
+
+pub fn tx_input(seed: u8, index: u64) -> TransactionInput {
+    TransactionInput {
+        transaction_id: Hash::new([seed; 32]),
+        index,
+    }
+}
+
+pub fn key_address(hash: Hash<28>) -> Vec<u8> {
+    ShelleyAddress::new(
+        Network::Testnet,
+        ShelleyPaymentPart::Key(hash),
+        ShelleyDelegationPart::Null,
+    )
+    .to_vec()
+}
+
+pub fn script_address(hash: Hash<28>) -> Vec<u8> {
+    ShelleyAddress::new(
+        Network::Testnet,
+        ShelleyPaymentPart::Script(hash),
+        ShelleyDelegationPart::Null,
+    )
+    .to_vec()
+}
+
+pub fn alonzo_output(address: &[u8], coin: u64) -> TransactionOutput {
+    TransactionOutput {
+        address: address.to_vec().into(),
+        amount: Value::Coin(coin),
+        datum_hash: None,
+    }
+}
+
+pub fn alonzo_utxos<'b>(entries: &[(TransactionInput, TransactionOutput)]) -> UTxOs<'b> {
+    entries
+        .iter()
+        .map(|(input, output)| {
+            (
+                OutputRef::new(input.transaction_id, input.index),
+                MultiEraOutput::AlonzoCompatible(Box::new(Cow::Owned(output.clone()))),
+            )
+        })
+        .collect()
+}
+
+pub fn alonzo_body(
+    inputs: Vec<TransactionInput>,
+    outputs: Vec<TransactionOutput>,
+    fee: u64,
+) -> TransactionBody {
+    TransactionBody {
+        inputs,
+        outputs,
+        fee,
+        ttl: None,
+        certificates: None,
+        withdrawals: None,
+        update: None,
+        auxiliary_data_hash: None,
+        validity_interval_start: None,
+        mint: None,
+        script_data_hash: None,
+        collateral: None,
+        required_signers: None,
+        network_id: None,
+    }
+}
+
+/// Signs the body with each of the keys and returns the CBOR of the full tx
+pub fn alonzo_tx(
+    body: TransactionBody,
+    keys: &[SecretKey],
+    native_scripts: Vec<NativeScript>,
+) -> Vec<u8> {
+    let body_hash = Hasher::<256>::hash_cbor(&body);
+
+    let vkeywitness = keys
+        .iter()
+        .map(|key| VKeyWitness {
+            vkey: key.public_key().as_ref().to_vec().into(),
+            signature: key.sign(body_hash).as_ref().to_vec().into(),
+        })
+        .collect::<Vec<_>>();
+
+    let tx = Tx {
+        transaction_body: body,
+        transaction_witness_set: WitnessSet {
+            vkeywitness: Some(vkeywitness).filter(|x| !x.is_empty()),
+            native_script: Some(native_scripts).filter(|x| !x.is_empty()),
+            bootstrap_witness: None,
+            plutus_script: None,
+            plutus_data: None,
+            redeemer: None,
+        },
+        success: true,
+        auxiliary_data: Nullable::Null,
+    };
+
+    minicbor::to_vec(tx).unwrap()
+}
+
+/// Puts the txs built by [alonzo_tx] in a Shelley block, under the header of
+/// a mainnet one, and returns the CBOR of the block tagged with its era
+pub fn shelley_block(txs: &[Vec<u8>]) -> Vec<u8> {
+    let bytes = hex::decode(include_str!("../../test_data/shelley1.block")).unwrap();
+    let (era, mut block): (u16, Block) = minicbor::decode(&bytes).unwrap();
+
+    let txs: Vec<Tx> = txs.iter().map(|x| minicbor::decode(x).unwrap()).collect();
+
+    block.transaction_bodies = txs.iter().map(|x| x.transaction_body.clone()).collect();
+    block.transaction_witness_sets = txs.into_iter().map(|x| x.transaction_witness_set).collect();
+    block.auxiliary_data_set = KeyValuePairs::Def(vec![]);
+    block.invalid_transactions = None;
+
+    minicbor::to_vec((era, block)).unwrap()
+}
+
+pub fn babbage_output(address: &[u8], coin: u64) -> babbage::TransactionOutput {
+    babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+        address: address.to_vec().into(),
+        value: Value::Coin(coin),
+        datum_option: None,
+        script_ref: None,
+    })
+}
+
+pub fn babbage_body(
+    inputs: Vec<TransactionInput>,
+    outputs: Vec<babbage::TransactionOutput>,
+    fee: u64,
+) -> babbage::TransactionBody {
+    babbage::TransactionBody {
+        inputs,
+        outputs,
+        fee,
+        ttl: None,
+        certificates: None,
+        withdrawals: None,
+        update: None,
+        auxiliary_data_hash: None,
+        validity_interval_start: None,
+        mint: None,
+        script_data_hash: None,
+        collateral: None,
+        required_signers: None,
+        network_id: None,
+        collateral_return: None,
+        total_collateral: None,
+        reference_inputs: None,
+    }
+}
+
+/// Signs the body with each of the keys and returns the CBOR of the full tx
+pub fn babbage_tx(body: babbage::TransactionBody, keys: &[SecretKey]) -> Vec<u8> {
+    let body_hash = Hasher::<256>::hash_cbor(&body);
+
+    let vkeywitness = keys
+        .iter()
+        .map(|key| VKeyWitness {
+            vkey: key.public_key().as_ref().to_vec().into(),
+            signature: key.sign(body_hash).as_ref().to_vec().into(),
+        })
+        .collect::<Vec<_>>();
+
+    let tx = babbage::Tx {
+        transaction_body: body,
+        transaction_witness_set: babbage::WitnessSet {
+            vkeywitness: Some(vkeywitness).filter(|x| !x.is_empty()),
+            native_script: None,
+            bootstrap_witness: None,
+            plutus_v1_script: None,
+            plutus_data: None,
+            redeemer: None,
+            plutus_v2_script: None,
+        },
+        success: true,
+        auxiliary_data: Nullable::Null,
+    };
+
+    minicbor::to_vec(tx).unwrap()
+}
+
+pub fn byron_input(seed: u8, index: u32) -> byron::TxIn {
+    byron::TxIn::Variant0(CborWrap((Hash::new([seed; 32]), index)))
+}
+
+pub fn byron_address(root: Hash<28>, attributes: &[(u64, Vec<u8>)]) -> byron::Address {
+    byron_typed_address(root, attributes, 0)
+}
+
+pub fn byron_typed_address(
+    root: Hash<28>,
+    attributes: &[(u64, Vec<u8>)],
+    addr_type: u64,
+) -> byron::Address {
+    let mut payload = minicbor::Encoder::new(Vec::new());
+
+    payload.array(3).unwrap();
+    payload.bytes(root.as_ref()).unwrap();
+    payload.map(attributes.len() as u64).unwrap();
+
+    for (key, value) in attributes {
+        payload.u64(*key).unwrap().bytes(value).unwrap();
+    }
+
+    payload.u64(addr_type).unwrap();
+
+    let payload = payload.into_writer();
+    let crc = ADDR_CRC.checksum(&payload);
+
+    byron::Address {
+        payload: TagWrap(payload.into()),
+        crc,
+    }
+}
+
+/// Builds a pubkey output whose address carries the given attributes
+///
+/// The root of the address doesn't belong to any key.
+pub fn byron_output(attributes: &[(u64, Vec<u8>)], amount: u64) -> byron::TxOut {
+    byron::TxOut {
+        address: byron_address(Hash::new([0; 28]), attributes),
+        amount,
+    }
+}
+
+/// The extended public key of a secret key, with an all-zeros chain code
+pub fn byron_xpub(key: &SecretKey) -> Vec<u8> {
+    [key.public_key().as_ref(), &[0; 32]].concat()
+}
+
+/// Builds an output locked by the address of the given key, with no
+/// attributes
+pub fn byron_key_output(key: &SecretKey, amount: u64) -> byron::TxOut {
+    let root = address_root(ADDR_TYPE_PUBKEY, &byron_xpub(key), &[0xa0]);
+
+    byron::TxOut {
+        address: byron_address(root, &[]),
+        amount,
+    }
+}
+
+pub fn byron_utxos(entries: &[(byron::TxIn, byron::TxOut)]) -> UTxOs<'static> {
+    entries
+        .iter()
+        .map(|(input, output)| {
+            (
+                utxo_key(input).unwrap(),
+                MultiEraOutput::Byron(Box::new(Cow::Owned(output.clone()))),
+            )
+        })
+        .collect()
+}
+
+/// Returns the CBOR of an unsigned tx payload
+pub fn byron_tx(inputs: Vec<byron::TxIn>, outputs: Vec<byron::TxOut>) -> Vec<u8> {
+    byron_signed_tx(inputs, outputs, &[])
+}
+
+/// Signs the id of a Byron tx under the mainnet protocol magic, with the
+/// given tag
+pub fn byron_tx_signature(key: &SecretKey, tag: u8, tx: &byron::Tx) -> Vec<u8> {
+    let message = byron_signed_data(tag, BYRON_MAINNET_MAGIC, Hasher::<256>::hash_cbor(tx));
+
+    key.sign(message).as_ref().to_vec()
+}
+
+/// Returns the CBOR of a tx payload with a pubkey witness for each of the keys,
+/// signing the tx under the mainnet protocol magic
+pub fn byron_signed_tx(
+    inputs: Vec<byron::TxIn>,
+    outputs: Vec<byron::TxOut>,
+    keys: &[SecretKey],
+) -> Vec<u8> {
+    let transaction = byron::Tx {
+        inputs: MaybeIndefArray::Def(inputs),
+        outputs: MaybeIndefArray::Def(outputs),
+        attributes: EmptyMap,
+    };
+
+    let witnesses = keys
+        .iter()
+        .map(|key| {
+            byron::Twit::PkWitness(CborWrap((
+                byron_xpub(key).into(),
+                byron_tx_signature(key, 0x01, &transaction).into(),
+            )))
+        })
+        .collect();
+
+    let payload = byron::TxPayload {
+        transaction,
+        witness: MaybeIndefArray::Def(witnesses),
+    };
+
+    minicbor::to_vec(payload).unwrap()
+}
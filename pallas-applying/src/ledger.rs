@@ -0,0 +1,673 @@
+//! Access to the parts of the ledger state beyond the UTxO set, and a
+//! [Ledger] keeping it together with the UTxO set and the environment
+
+use std::collections::HashSet;
+
+use pallas_addresses::{Address, Pointer, ShelleyDelegationPart};
+use pallas_crypto::hash::Hash;
+use pallas_primitives::alonzo::{Certificate, StakeCredential};
+use pallas_traverse::{MultiEraBlock, MultiEraTx};
+
+use crate::{
+    apply,
+    block::{validate_and_apply_block, validate_tx},
+    environment::{Environment, ShelleyProtParams},
+    shelley_ma::certificate_deposits,
+    UTxOs, UtxoStore, ValidationError, ValidationResult,
+};
+
+/// The ledger state a transaction is checked against, other than its inputs
+pub trait LedgerState {
+    /// Whether a stake pool with the given id is currently registered
+    fn is_pool_registered(&self, pool: &Hash<28>) -> bool;
+
+    /// Records a stake pool registered by an applied transaction
+    fn register_pool(&mut self, pool: Hash<28>);
+
+    /// The stake credential registered by the certificate a pointer address
+    /// points at, if any
+    fn resolve_pointer(&self, pointer: &Pointer) -> Option<StakeCredential>;
+
+    /// Whether the Byron update proposal with the given id is still open to
+    /// votes
+    fn is_update_proposal_active(&self, proposal: &Hash<32>) -> bool;
+
+    /// Records a Byron update proposal carried by an applied block
+    fn register_update_proposal(&mut self, proposal: Hash<32>);
+}
+
+/// Records the stake pools registered by the certificates of a transaction
+pub(crate) fn register_pools(metx: &MultiEraTx, state: &mut impl LedgerState) {
+    for cert in metx.certs() {
+        if let Some(Certificate::PoolRegistration { operator, .. }) = cert.as_alonzo() {
+            state.register_pool(*operator);
+        }
+    }
+}
+
+/// Checks that every stake delegation certificate targets a registered pool
+///
+/// Certificates are processed in order, so delegating to a pool registered by
+/// an earlier certificate of the same transaction is accepted. Conway vote
+/// delegation to DReps isn't covered, as there are no Conway certificates in
+/// the primitives yet.
+pub fn check_delegation_targets(metx: &MultiEraTx, state: &impl LedgerState) -> ValidationResult {
+    let mut registered = HashSet::new();
+
+    for cert in metx.certs() {
+        match cert.as_alonzo() {
+            Some(Certificate::PoolRegistration { operator, .. }) => {
+                registered.insert(*operator);
+            }
+            Some(Certificate::StakeDelegation(_, pool))
+                if !registered.contains(pool) && !state.is_pool_registered(pool) =>
+            {
+                return Err(ValidationError::DelegationTargetNotRegistered { target: *pool });
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the pointer addresses of the outputs a transaction spends and
+/// produces point at a stake registration known to the ledger state
+///
+/// Inputs missing from `utxos` are left for the checks resolving them to
+/// report.
+pub fn check_pointer_addresses<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    state: &impl LedgerState,
+) -> ValidationResult {
+    let spent = metx
+        .inputs()
+        .iter()
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .map(|x| x.address())
+        .collect::<Vec<_>>();
+
+    let produced = metx
+        .outputs()
+        .iter()
+        .map(|x| x.address())
+        .collect::<Vec<_>>();
+
+    for address in spent.into_iter().chain(produced) {
+        if let Ok(Address::Shelley(address)) = address {
+            if let ShelleyDelegationPart::Pointer(pointer) = address.delegation() {
+                if state.resolve_pointer(pointer).is_none() {
+                    return Err(ValidationError::PointerAddressUnresolved(pointer.clone()));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Lovelace moved between the protocol-level pots by a transaction
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AccountingDelta {
+    /// Paid into the deposit pot by new stake and pool registrations
+    pub deposits: u64,
+
+    /// Taken from the deposit pot by stake deregistrations
+    pub refunds: u64,
+
+    /// Paid into the fee pot
+    pub fees: u64,
+
+    /// Taken from the reward accounts
+    pub withdrawals: u64,
+}
+
+impl AccountingDelta {
+    /// Net change of the deposit pot
+    pub fn deposit_pot_change(&self) -> i128 {
+        self.deposits as i128 - self.refunds as i128
+    }
+}
+
+/// Computes the lovelace a transaction moves between the deposit pot, the fee
+/// pot and the reward accounts, without changing anything
+///
+/// Registering a pool already registered, either in the ledger state or by an
+/// earlier certificate of the same tx, updates its params and pays no deposit.
+/// A tx failing phase-2 validation moves nothing but its collateral, net of
+/// the collateral return, to the fee pot. For a valid tx, the value of the
+/// inputs plus `withdrawals` and `refunds` matches the value of the outputs
+/// plus `fees` and `deposits`, which makes for a cross-check of
+/// [check_preservation_of_value](crate::shelley_ma::check_preservation_of_value).
+pub fn accounting_delta<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    state: &impl LedgerState,
+    prot_pps: &ShelleyProtParams,
+) -> AccountingDelta {
+    if !metx.is_valid() {
+        let collateral = metx.total_collateral().unwrap_or_else(|| {
+            let total: u64 = metx
+                .collateral()
+                .iter()
+                .filter_map(|x| utxos.resolve(&x.output_ref()))
+                .map(|x| x.lovelace_amount())
+                .sum();
+
+            let returned = metx.collateral_return().map(|x| x.lovelace_amount());
+
+            total.saturating_sub(returned.unwrap_or_default())
+        });
+
+        return AccountingDelta {
+            fees: collateral,
+            ..Default::default()
+        };
+    }
+
+    let (deposits, refunds) =
+        certificate_deposits(metx, prot_pps.key_deposit, prot_pps.pool_deposit, |x| {
+            state.is_pool_registered(x)
+        });
+
+    AccountingDelta {
+        deposits,
+        refunds,
+        fees: metx.fee().unwrap_or_default(),
+        withdrawals: metx
+            .withdrawals()
+            .collect::<Vec<_>>()
+            .iter()
+            .map(|(_, x)| x)
+            .sum(),
+    }
+}
+
+/// How slots map to epochs, from the first slot of a run of epochs of the
+/// same length onwards
+///
+/// On mainnet, for instance, Byron epochs span 21600 slots while those from
+/// Shelley onwards span 432000, so the schedule of the latter starts at slot
+/// 4492800, the first one of epoch 208.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochSchedule {
+    pub first_slot: u64,
+    pub first_epoch: u64,
+    pub epoch_length: u64,
+}
+
+impl EpochSchedule {
+    /// The epoch the given slot belongs to, taking slots before the first one
+    /// of the schedule as part of its first epoch
+    pub fn epoch(&self, slot: u64) -> u64 {
+        self.first_epoch + slot.saturating_sub(self.first_slot) / self.epoch_length.max(1)
+    }
+}
+
+/// The UTxO set, the rest of the ledger state and the environment, evolving
+/// together as transactions and blocks get applied
+///
+/// An entry point for a stateful validator, sparing the caller from threading
+/// the three of them through every call. The free functions it builds upon
+/// remain available for stateless use.
+pub struct Ledger<'b, S: LedgerState> {
+    utxos: UTxOs<'b>,
+    state: S,
+    env: Environment,
+    tip_slot: Option<u64>,
+    epochs: Option<EpochSchedule>,
+}
+
+impl<'b, S: LedgerState> Ledger<'b, S> {
+    pub fn new(utxos: UTxOs<'b>, state: S, env: Environment) -> Self {
+        Self {
+            utxos,
+            state,
+            env,
+            tip_slot: None,
+            epochs: None,
+        }
+    }
+
+    /// Has [current_epoch](Self::current_epoch) follow the given schedule
+    pub fn with_epoch_schedule(mut self, epochs: EpochSchedule) -> Self {
+        self.epochs = Some(epochs);
+        self
+    }
+
+    pub fn utxos(&self) -> &UTxOs<'b> {
+        &self.utxos
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.env
+    }
+
+    /// Replaces the environment, as when new protocol parameters come into
+    /// effect
+    pub fn set_environment(&mut self, env: Environment) {
+        self.env = env;
+    }
+
+    /// Slot of the last block applied, if any
+    pub fn tip_slot(&self) -> Option<u64> {
+        self.tip_slot
+    }
+
+    /// Epoch of the last block applied, if any, as long as there is an epoch
+    /// schedule to go by
+    pub fn current_epoch(&self) -> Option<u64> {
+        Some(self.epochs?.epoch(self.tip_slot?))
+    }
+
+    /// Validates a transaction against the current UTxO set, ledger state and
+    /// environment, without applying it
+    ///
+    /// Once a block got applied, the validity interval of the transaction is
+    /// checked against the [tip_slot](Self::tip_slot) rather than against the
+    /// slot of the environment.
+    pub fn validate_tx(&self, metx: &MultiEraTx) -> ValidationResult {
+        let env = Environment {
+            slot: self.tip_slot.or(self.env.slot),
+            ..self.env.clone()
+        };
+
+        validate_tx(metx, &self.utxos, &self.state, &env)
+    }
+
+    /// Applies a transaction, assumed to be valid, to the UTxO set and the
+    /// ledger state
+    ///
+    /// See [apply_tx](crate::apply::apply_tx) for the effects on the UTxO set.
+    /// Nothing changes if the transaction fails to apply.
+    pub fn apply_tx(&mut self, metx: &MultiEraTx<'b>) -> ValidationResult {
+        apply::apply_tx(metx, &mut self.utxos)?;
+        register_pools(metx, &mut self.state);
+
+        Ok(())
+    }
+
+    /// Validates the transactions of a block, applying each of them in order,
+    /// and moves the tip to the block
+    ///
+    /// Failures are reported as done by
+    /// [validate_block_bytes](crate::block::validate_block_bytes), which
+    /// includes keeping the effects of the transactions applied before the
+    /// one at fault. The tip only moves when the whole block gets applied.
+    pub fn validate_and_apply_block(
+        &mut self,
+        bytes: &'b [u8],
+    ) -> Result<(), (usize, ValidationError)> {
+        let block = MultiEraBlock::decode(bytes)
+            .map_err(|error| (0, ValidationError::Decode(error.to_string())))?;
+
+        validate_and_apply_block(&block, &mut self.utxos, &mut self.state, &self.env)?;
+        self.tip_slot = Some(block.slot());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_addresses::{Network, ShelleyAddress, ShelleyPaymentPart};
+    use pallas_codec::utils::KeyValuePairs;
+    use pallas_primitives::alonzo::RationalNumber;
+    use pallas_traverse::{Era, OutputRef};
+
+    use std::collections::BTreeMap;
+
+    use crate::environment::{GenesisDelegates, ProtocolParams, Strictness};
+
+    struct Pools(Vec<Hash<28>>);
+
+    impl LedgerState for Pools {
+        fn is_pool_registered(&self, pool: &Hash<28>) -> bool {
+            self.0.contains(pool)
+        }
+
+        fn register_pool(&mut self, pool: Hash<28>) {
+            self.0.push(pool);
+        }
+
+        fn resolve_pointer(&self, pointer: &Pointer) -> Option<StakeCredential> {
+            let registered = Pointer::new(10, 0, 0);
+
+            (pointer == &registered).then(|| StakeCredential::AddrKeyhash(Hash::new([1; 28])))
+        }
+
+        fn is_update_proposal_active(&self, _: &Hash<32>) -> bool {
+            false
+        }
+
+        fn register_update_proposal(&mut self, _: Hash<32>) {}
+    }
+
+    fn pool_registration(operator: Hash<28>) -> Certificate {
+        Certificate::PoolRegistration {
+            operator,
+            vrf_keyhash: Hash::new([0; 32]),
+            pledge: 0,
+            cost: 340_000_000,
+            margin: RationalNumber {
+                numerator: 1,
+                denominator: 100,
+            },
+            reward_account: vec![0xe0; 29].into(),
+            pool_owners: vec![],
+            relays: vec![],
+            pool_metadata: None,
+        }
+    }
+
+    #[test]
+    fn pots_moved_by_certificates() {
+        let prot_pps = ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        };
+
+        let credential = StakeCredential::AddrKeyhash(key_hash(&secret_key(1)));
+        let (known, new) = (Hash::new([1; 28]), Hash::new([2; 28]));
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.certificates = Some(vec![
+            Certificate::StakeRegistration(credential.clone()),
+            pool_registration(known),
+            pool_registration(new),
+            pool_registration(new),
+            Certificate::StakeDeregistration(credential),
+        ]);
+        body.withdrawals = Some(KeyValuePairs::from(vec![(vec![0xe0; 29].into(), 7)]));
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let delta = accounting_delta(&metx, &UTxOs::new(), &Pools(vec![known]), &prot_pps);
+
+        assert_eq!(
+            delta,
+            AccountingDelta {
+                deposits: 502_000_000,
+                refunds: 2_000_000,
+                fees: 200_000,
+                withdrawals: 7,
+            }
+        );
+        assert_eq!(delta.deposit_pot_change(), 500_000_000);
+    }
+
+    #[test]
+    fn pool_reregistration() {
+        let prot_pps = ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        };
+
+        let env = Environment {
+            prot_params: ProtocolParams::Shelley(prot_pps.clone()),
+            protocol_version: (2, 0),
+            genesis_delegates: GenesisDelegates {
+                delegates: BTreeMap::new(),
+                quorum: 0,
+            },
+            slot: None,
+            strictness: Strictness::Lenient,
+        };
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+        let operator = key_hash(&secret_key(2));
+
+        // the tx updates the params of the pool without paying any deposit
+        let mut body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        body.certificates = Some(vec![pool_registration(operator)]);
+        let bytes = alonzo_tx(body, &[secret_key(1), secret_key(2)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let registered = Ledger::new(utxos.clone(), Pools(vec![operator]), env.clone());
+        let delta = accounting_delta(&metx, registered.utxos(), registered.state(), &prot_pps);
+
+        assert_eq!(delta.deposit_pot_change(), 0);
+        assert_eq!(registered.validate_tx(&metx), Ok(()));
+
+        // while registering it anew takes the pool deposit
+        let unregistered = Ledger::new(utxos, Pools(vec![]), env);
+
+        assert_eq!(
+            unregistered.validate_tx(&metx),
+            Err(ValidationError::DepositAccountingMismatch {
+                expected: 500_000_000,
+                actual: 0
+            })
+        );
+    }
+
+    #[test]
+    fn delegation_targets() {
+        let credential = StakeCredential::AddrKeyhash(key_hash(&secret_key(1)));
+        let (known, new, unknown) = (Hash::new([1; 28]), Hash::new([2; 28]), Hash::new([3; 28]));
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.certificates = Some(vec![
+            Certificate::StakeDelegation(credential.clone(), known),
+            pool_registration(new),
+            Certificate::StakeDelegation(credential.clone(), new),
+        ]);
+        let bytes = alonzo_tx(body.clone(), &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(check_delegation_targets(&metx, &Pools(vec![known])), Ok(()));
+
+        body.certificates = Some(vec![
+            Certificate::StakeDelegation(credential.clone(), new),
+            pool_registration(new),
+            Certificate::StakeDelegation(credential, unknown),
+        ]);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_delegation_targets(&metx, &Pools(vec![known])),
+            Err(ValidationError::DelegationTargetNotRegistered { target: new })
+        );
+    }
+
+    #[test]
+    fn pointer_addresses() {
+        let pointer_address = |pointer| {
+            ShelleyAddress::new(
+                Network::Testnet,
+                ShelleyPaymentPart::Key(key_hash(&secret_key(1))),
+                ShelleyDelegationPart::Pointer(pointer),
+            )
+            .to_vec()
+        };
+
+        let registered = pointer_address(Pointer::new(10, 0, 0));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&registered, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&registered, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_pointer_addresses(&metx, &utxos, &Pools(vec![])),
+            Ok(())
+        );
+
+        let unknown = pointer_address(Pointer::new(10, 1, 0));
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&unknown, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_pointer_addresses(&metx, &utxos, &Pools(vec![])),
+            Err(ValidationError::PointerAddressUnresolved(Pointer::new(
+                10, 1, 0
+            )))
+        );
+    }
+
+    #[test]
+    fn ledger_evolution() {
+        let env = Environment {
+            prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+                min_utxo_value: 1_000_000,
+            }),
+            protocol_version: (2, 0),
+            genesis_delegates: GenesisDelegates {
+                delegates: BTreeMap::new(),
+                quorum: 0,
+            },
+            slot: None,
+            strictness: Strictness::Lenient,
+        };
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let mut ledger =
+            Ledger::new(utxos, Pools(vec![]), env).with_epoch_schedule(EpochSchedule {
+                first_slot: 4_492_800,
+                first_epoch: 208,
+                epoch_length: 432_000,
+            });
+
+        assert_eq!(ledger.validate_tx(&metx), Ok(()));
+        assert_eq!(ledger.apply_tx(&metx), Ok(()));
+        assert_eq!(ledger.utxos().len(), 1);
+        assert!(ledger.utxos().contains_key(&OutputRef::new(metx.hash(), 0)));
+        assert_eq!(
+            ledger.validate_tx(&metx),
+            Err(ValidationError::InputNotInUTxO(OutputRef::new(
+                tx_input(1, 0).transaction_id,
+                0
+            )))
+        );
+
+        // a Byron block doesn't go with Shelley params, so the tip stays put
+        let block = hex::decode(include_str!("../../test_data/byron2.block")).unwrap();
+
+        assert_eq!(
+            ledger.validate_and_apply_block(&block),
+            Err((0, ValidationError::EraMismatch(Era::Byron)))
+        );
+        assert_eq!(ledger.tip_slot(), None);
+        assert_eq!(ledger.current_epoch(), None);
+    }
+
+    #[test]
+    fn validity_at_tip() {
+        let env = Environment {
+            prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+                min_utxo_value: 1_000_000,
+            }),
+            protocol_version: (2, 0),
+            genesis_delegates: GenesisDelegates {
+                delegates: BTreeMap::new(),
+                quorum: 0,
+            },
+            slot: None,
+            strictness: Strictness::Lenient,
+        };
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let tx = |ttl| {
+            let mut body = alonzo_body(
+                vec![tx_input(1, 0)],
+                vec![alonzo_output(&address, 4_800_000)],
+                200_000,
+            );
+            body.ttl = ttl;
+            alonzo_tx(body, &[secret_key(1)], vec![])
+        };
+
+        // moving the tip with a block whose tx spends some other output
+        let other = alonzo_utxos(&[(tx_input(2, 0), alonzo_output(&address, 5_000_000))]);
+        let body = alonzo_body(
+            vec![tx_input(2, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let block = shelley_block(&[alonzo_tx(body, &[secret_key(1)], vec![])]);
+        let slot = MultiEraBlock::decode(&block).unwrap().slot();
+
+        let mut ledger = Ledger::new(utxos.into_iter().chain(other).collect(), Pools(vec![]), env);
+        let expiring = tx(Some(slot - 1));
+        let metx = MultiEraTx::decode(Era::Shelley, &expiring).unwrap();
+
+        assert_eq!(ledger.validate_tx(&metx), Ok(()));
+        assert_eq!(ledger.validate_and_apply_block(&block), Ok(()));
+        assert_eq!(ledger.tip_slot(), Some(slot));
+        assert_eq!(
+            ledger.validate_tx(&metx),
+            Err(ValidationError::TxExpired(slot, slot - 1))
+        );
+
+        let bytes = tx(Some(slot));
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(ledger.validate_tx(&metx), Ok(()));
+    }
+
+    #[test]
+    fn epoch_schedule() {
+        let mainnet = EpochSchedule {
+            first_slot: 4_492_800,
+            first_epoch: 208,
+            epoch_length: 432_000,
+        };
+
+        assert_eq!(mainnet.epoch(4_492_800), 208);
+        assert_eq!(mainnet.epoch(4_924_799), 208);
+        assert_eq!(mainnet.epoch(4_924_800), 209);
+    }
+}
@@ -0,0 +1,53 @@
+//! Logic for validating and applying new blocks and txs to the chain state
+//!
+//! Validation and application are kept apart. Validating a tx, be it through
+//! [Validator::validate], the per-era validators or
+//! [validate_with_resolved], only ever reads the UTxO set it is given, so any
+//! number of candidates can be checked independently against the same
+//! confirmed set, as a mempool filter would. Changing the set is up to
+//! [apply::apply_tx], or to [block::validate_block_bytes],
+//! [block::apply_block] and [mempool::validate_mempool], which apply each
+//! accepted tx before validating the next one. A [Ledger] does the same for a stateful
+//! validator, owning the UTxO set along with the rest of the ledger state.
+
+pub mod alonzo;
+pub mod apply;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod babbage;
+pub mod block;
+pub mod byron;
+pub mod cache;
+pub mod certs;
+pub mod environment;
+pub mod fee;
+pub mod ledger;
+pub mod mempool;
+pub mod metadata;
+pub mod mint;
+pub mod native_script;
+#[cfg(feature = "profile")]
+pub mod profile;
+pub mod report;
+pub mod script_data;
+pub mod shelley_ma;
+pub mod snapshot;
+pub mod structure;
+pub mod update;
+pub mod utils;
+pub mod validator;
+pub mod value;
+pub mod warnings;
+pub mod witnesses;
+
+#[cfg(test)]
+mod fixtures;
+
+pub use cache::ValidatorCache;
+pub use ledger::{Ledger, LedgerState};
+pub use report::ValidationReport;
+pub use utils::{
+    input_key, tx_hash, utxo_key, UTxOs, UtxoKey, UtxoStore, ValidationError, ValidationResult,
+    ValidationWarning,
+};
+pub use validator::{validate_against_forks, validate_with_resolved, Validator};
@@ -0,0 +1,213 @@
+//! Validation of a set of pending transactions which may spend each other's
+//! outputs
+
+use std::collections::{BTreeSet, HashMap};
+
+use pallas_crypto::hash::Hash;
+use pallas_traverse::{MultiEraTx, OutputRef};
+
+use crate::{
+    apply::apply_tx, environment::Environment, utils::input_key, UTxOs, ValidationError,
+    ValidationResult, Validator,
+};
+
+/// Validates pending transactions the way a mempool would accept them
+///
+/// Transactions spending or referencing the outputs of others in the set are
+/// validated after them, against a copy of `utxos` updated with the effects of
+/// every transaction accepted so far. Independent transactions keep their
+/// relative order. A transaction depending on one which got rejected, or
+/// caught in a dependency cycle, fails with
+/// [ValidationError::UnresolvableDependency] for the first input at fault.
+///
+/// Results are returned in the order of `txs`.
+pub fn validate_mempool<'b>(
+    txs: Vec<MultiEraTx<'b>>,
+    utxos: &UTxOs<'b>,
+    env: &Environment,
+) -> Vec<(MultiEraTx<'b>, ValidationResult)> {
+    let results = resolve_and_validate(&txs, utxos, env);
+
+    txs.into_iter().zip(results).collect()
+}
+
+fn resolve_and_validate<'b>(
+    txs: &[MultiEraTx<'b>],
+    utxos: &UTxOs<'b>,
+    env: &Environment,
+) -> Vec<ValidationResult> {
+    let indexes: HashMap<Hash<32>, usize> = txs
+        .iter()
+        .enumerate()
+        .map(|(index, metx)| (metx.hash(), index))
+        .collect();
+
+    let dependencies: Vec<_> = txs.iter().map(|x| dependencies(x, &indexes)).collect();
+
+    let mut dependents = vec![vec![]; txs.len()];
+    let mut waiting = vec![0; txs.len()];
+
+    for (index, deps) in dependencies.iter().enumerate() {
+        let producers: BTreeSet<_> = deps.iter().map(|(_, x)| *x).collect();
+        waiting[index] = producers.len();
+
+        for producer in producers {
+            dependents[producer].push(index);
+        }
+    }
+
+    let mut ready: BTreeSet<_> = (0..txs.len()).filter(|x| waiting[*x] == 0).collect();
+    let mut results: Vec<Option<ValidationResult>> = vec![None; txs.len()];
+    let mut working = utxos.clone();
+    let validator = Validator::default();
+
+    while let Some(index) = ready.pop_first() {
+        let metx = &txs[index];
+
+        let rejected = dependencies[index]
+            .iter()
+            .find(|(_, x)| results[*x] != Some(Ok(())));
+
+        let result = match rejected {
+            Some((input, _)) => Err(ValidationError::UnresolvableDependency(input.clone())),
+            None => validator
+                .validate(metx, &working, env)
+                .and_then(|_| apply_tx(metx, &mut working)),
+        };
+
+        results[index] = Some(result);
+
+        for dependent in dependents[index].iter() {
+            waiting[*dependent] -= 1;
+
+            if waiting[*dependent] == 0 {
+                ready.insert(*dependent);
+            }
+        }
+    }
+
+    // whatever is left never got ready, being part of a dependency cycle
+    results
+        .into_iter()
+        .zip(dependencies)
+        .map(|(result, deps)| {
+            result
+                .unwrap_or_else(|| Err(ValidationError::UnresolvableDependency(deps[0].0.clone())))
+        })
+        .collect()
+}
+
+/// Lists the inputs of a tx, of any kind, pointing at outputs of the pending
+/// txs, along with the index of the tx producing each of them
+fn dependencies(metx: &MultiEraTx, indexes: &HashMap<Hash<32>, usize>) -> Vec<(OutputRef, usize)> {
+    metx.inputs()
+        .into_iter()
+        .chain(metx.collateral())
+        .chain(metx.reference_inputs())
+        .filter_map(|input| {
+            let output_ref = input_key(&input)?;
+            let producer = *indexes.get(output_ref.hash())?;
+
+            Some((output_ref, producer))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::{GenesisDelegates, ProtocolParams, ShelleyProtParams, Strictness},
+        fixtures::*,
+    };
+
+    use std::collections::BTreeMap;
+
+    use pallas_primitives::alonzo::TransactionInput;
+    use pallas_traverse::Era;
+
+    const ENV: Environment = Environment {
+        prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        }),
+        protocol_version: (2, 0),
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+        slot: None,
+        strictness: Strictness::Lenient,
+    };
+
+    /// A tx moving `coin` lovelace, minus the fee, from the input to a single
+    /// output
+    fn transfer(input: TransactionInput, coin: u64, fee: u64) -> Vec<u8> {
+        let address = key_address(key_hash(&secret_key(1)));
+        let body = alonzo_body(vec![input], vec![alonzo_output(&address, coin - fee)], fee);
+
+        alonzo_tx(body, &[secret_key(1)], vec![])
+    }
+
+    fn spending(bytes: &[u8]) -> TransactionInput {
+        TransactionInput {
+            transaction_id: MultiEraTx::decode(Era::Shelley, bytes).unwrap().hash(),
+            index: 0,
+        }
+    }
+
+    fn results<'b>(txs: &[&'b [u8]], utxos: &UTxOs<'b>) -> Vec<ValidationResult> {
+        let txs = txs
+            .iter()
+            .map(|x| MultiEraTx::decode(Era::Shelley, x).unwrap())
+            .collect();
+
+        validate_mempool(txs, utxos, &ENV)
+            .into_iter()
+            .map(|(_, x)| x)
+            .collect()
+    }
+
+    #[test]
+    fn chained_txs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let parent = transfer(tx_input(1, 0), 5_000_000, 200_000);
+        let child = transfer(spending(&parent), 4_800_000, 200_000);
+        let grandchild = transfer(spending(&child), 4_600_000, 200_000);
+
+        assert_eq!(
+            results(&[&grandchild, &child, &parent], &utxos),
+            vec![Ok(()), Ok(()), Ok(())]
+        );
+    }
+
+    #[test]
+    fn unresolvable_dependencies() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        // rejected for paying no fee, taking its child down with it
+        let parent = transfer(tx_input(1, 0), 5_000_000, 0);
+        let child = transfer(spending(&parent), 5_000_000, 200_000);
+
+        let results = results(&[&child, &parent], &utxos);
+
+        assert!(matches!(
+            results[1],
+            Err(ValidationError::FeesBelowMin { .. })
+        ));
+        assert_eq!(
+            results[0],
+            Err(ValidationError::UnresolvableDependency(OutputRef::new(
+                spending(&parent).transaction_id,
+                0
+            )))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Structural rules of transaction metadata
+
+use pallas_codec::utils::Nullable;
+use pallas_primitives::alonzo::{AuxiliaryData, Metadata, Metadatum, MetadatumLabel};
+use pallas_traverse::MultiEraTx;
+
+use crate::{ValidationError, ValidationResult};
+
+/// Maximum length, in bytes, of a text or byte string within metadata
+const MAX_METADATUM_STRING_LEN: usize = 64;
+
+/// Checks that every text and byte string in the metadata fits in 64 bytes
+///
+/// Metadata labels are unsigned integers by construction, while nested maps
+/// may use any metadatum as key, so only the string lengths need checking.
+pub fn validate_metadata(aux: &AuxiliaryData) -> ValidationResult {
+    let metadata = match aux {
+        AuxiliaryData::Shelley(x) => Some(x),
+        AuxiliaryData::ShelleyMa(x) => Some(&x.transaction_metadata),
+        AuxiliaryData::PostAlonzo(x) => x.metadata.as_ref(),
+    };
+
+    match metadata {
+        Some(metadata) => check_metadata_strings(metadata),
+        None => Ok(()),
+    }
+}
+
+/// Validates the metadata of the auxiliary data of a tx, if any
+pub fn check_aux_data(metx: &MultiEraTx) -> ValidationResult {
+    let aux = match metx {
+        MultiEraTx::AlonzoCompatible(x, _) => &x.auxiliary_data,
+        MultiEraTx::Babbage(x) => &x.auxiliary_data,
+        _ => return Ok(()),
+    };
+
+    match aux {
+        Nullable::Some(aux) => validate_metadata(aux),
+        _ => Ok(()),
+    }
+}
+
+fn check_metadata_strings(metadata: &Metadata) -> ValidationResult {
+    metadata
+        .iter()
+        .try_for_each(|(label, metadatum)| check_metadatum(*label, metadatum))
+}
+
+fn check_metadatum(label: MetadatumLabel, metadatum: &Metadatum) -> ValidationResult {
+    let len = match metadatum {
+        Metadatum::Int(_) => 0,
+        Metadatum::Bytes(x) => x.len(),
+        Metadatum::Text(x) => x.len(),
+        Metadatum::Array(x) => {
+            return x.iter().try_for_each(|x| check_metadatum(label, x));
+        }
+        Metadatum::Map(x) => {
+            return x.iter().try_for_each(|(k, v)| {
+                check_metadatum(label, k)?;
+                check_metadatum(label, v)
+            });
+        }
+    };
+
+    match len <= MAX_METADATUM_STRING_LEN {
+        true => Ok(()),
+        false => Err(ValidationError::MetadataStringTooLong { label, len }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pallas_codec::utils::KeyValuePairs;
+
+    fn nested_text(len: usize) -> AuxiliaryData {
+        AuxiliaryData::Shelley(KeyValuePairs::from(vec![(
+            674,
+            Metadatum::Map(KeyValuePairs::from(vec![(
+                Metadatum::Text("msg".into()),
+                Metadatum::Array(vec![Metadatum::Text("a".repeat(len))]),
+            )])),
+        )]))
+    }
+
+    #[test]
+    fn string_length() {
+        assert_eq!(validate_metadata(&nested_text(64)), Ok(()));
+        assert_eq!(
+            validate_metadata(&nested_text(65)),
+            Err(ValidationError::MetadataStringTooLong {
+                label: 674,
+                len: 65
+            })
+        );
+    }
+}
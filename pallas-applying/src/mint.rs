@@ -0,0 +1,112 @@
+//! Checks over the assets minted and burnt by a transaction
+
+use std::collections::{BTreeMap, HashSet};
+
+use pallas_crypto::hash::Hash;
+use pallas_traverse::MultiEraTx;
+
+use crate::{ValidationError, ValidationResult};
+
+/// Net quantity minted (positive) or burnt (negative) of each asset, keyed by
+/// policy and asset name
+pub type MintedAssets = BTreeMap<(Hash<28>, Vec<u8>), i64>;
+
+/// Collects the single net quantity minted or burnt of each asset
+///
+/// A policy, or an asset name within a policy, appearing more than once in the
+/// mint field has no canonical meaning, so instead of adding the entries up
+/// it fails with [ValidationError::NonCanonicalMint].
+pub fn minted_assets(metx: &MultiEraTx) -> Result<MintedAssets, ValidationError> {
+    let mut policies = HashSet::new();
+    let mut assets = MintedAssets::new();
+
+    for policy in metx.mints() {
+        if !policies.insert(*policy.policy()) {
+            return Err(ValidationError::NonCanonicalMint);
+        }
+
+        for asset in policy.assets() {
+            let key = (*asset.policy(), asset.name().to_vec());
+            let quantity = asset.mint_coin().unwrap_or_default();
+
+            if assets.insert(key, quantity).is_some() {
+                return Err(ValidationError::NonCanonicalMint);
+            }
+        }
+    }
+
+    Ok(assets)
+}
+
+pub fn check_mint(metx: &MultiEraTx) -> ValidationResult {
+    minted_assets(metx).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_codec::utils::KeyValuePairs;
+    use pallas_primitives::alonzo::Multiasset;
+    use pallas_traverse::Era;
+
+    fn mint_tx(mint: Multiasset<i64>) -> Vec<u8> {
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.mint = Some(mint);
+
+        alonzo_tx(body, &[secret_key(1)], vec![])
+    }
+
+    #[test]
+    fn net_quantities() {
+        let policy = Hash::new([1; 28]);
+
+        let bytes = mint_tx(KeyValuePairs::from(vec![(
+            policy,
+            KeyValuePairs::from(vec![
+                (b"minted".to_vec().into(), 5),
+                (b"burnt".to_vec().into(), -3),
+            ]),
+        )]));
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            minted_assets(&metx),
+            Ok(MintedAssets::from([
+                ((policy, b"minted".to_vec()), 5),
+                ((policy, b"burnt".to_vec()), -3),
+            ]))
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let policy = Hash::new([1; 28]);
+
+        let bytes = mint_tx(KeyValuePairs::from(vec![(
+            policy,
+            KeyValuePairs::from(vec![
+                (b"token".to_vec().into(), 5),
+                (b"token".to_vec().into(), -5),
+            ]),
+        )]));
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(check_mint(&metx), Err(ValidationError::NonCanonicalMint));
+
+        let bytes = mint_tx(KeyValuePairs::from(vec![
+            (
+                policy,
+                KeyValuePairs::from(vec![(b"token".to_vec().into(), 5)]),
+            ),
+            (
+                policy,
+                KeyValuePairs::from(vec![(b"other".to_vec().into(), 5)]),
+            ),
+        ]));
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(check_mint(&metx), Err(ValidationError::NonCanonicalMint));
+    }
+}
@@ -0,0 +1,439 @@
+//! Evaluation of native (multisig and timelock) scripts
+
+use std::collections::{HashMap, HashSet};
+
+use pallas_addresses::{Address, ShelleyPaymentPart, StakePayload};
+use pallas_crypto::hash::{Hash, Hasher};
+use pallas_primitives::{
+    alonzo::{Certificate, NativeScript, StakeCredential},
+    babbage::Script,
+};
+use pallas_traverse::{ComputeHash, MultiEraTx};
+
+use crate::{UtxoStore, ValidationError, ValidationResult};
+
+/// The parts of a transaction a native script can observe
+///
+/// Signers are kept as a set of key hashes, so providing the same vkey witness
+/// more than once doesn't count as an extra signature, while a single witness
+/// satisfies every script (and therefore every input) requiring that key.
+#[derive(Debug, Clone, Default)]
+pub struct NativeScriptContext {
+    pub signers: HashSet<Hash<28>>,
+    pub validity_start: Option<u64>,
+    pub ttl: Option<u64>,
+}
+
+impl NativeScriptContext {
+    pub fn from_tx(metx: &MultiEraTx) -> Self {
+        Self {
+            signers: metx
+                .vkey_witnesses()
+                .iter()
+                .map(|wit| Hasher::<224>::hash(&wit.vkey))
+                .collect(),
+            validity_start: metx.validity_start(),
+            ttl: metx.ttl(),
+        }
+    }
+}
+
+/// Evaluates a native script against the given context
+///
+/// `ScriptNOfK(m, scripts)` (RequireMOf in the ledger spec) counts how many of
+/// its sub-scripts are satisfied, failing with
+/// [ValidationError::ScriptThresholdNotMet] if less than `m` are.
+pub fn eval_native_script(
+    script: &NativeScript,
+    context: &NativeScriptContext,
+) -> ValidationResult {
+    match script {
+        NativeScript::ScriptPubkey(hash) => match context.signers.contains(hash) {
+            true => Ok(()),
+            false => Err(ValidationError::ScriptKeyNotSigned(*hash)),
+        },
+        NativeScript::ScriptAll(scripts) => scripts
+            .iter()
+            .try_for_each(|x| eval_native_script(x, context)),
+        NativeScript::ScriptAny(scripts) => {
+            match scripts
+                .iter()
+                .any(|x| eval_native_script(x, context).is_ok())
+            {
+                true => Ok(()),
+                false => Err(ValidationError::ScriptThresholdNotMet {
+                    required: 1,
+                    satisfied: 0,
+                }),
+            }
+        }
+        NativeScript::ScriptNOfK(required, scripts) => {
+            let satisfied = scripts
+                .iter()
+                .filter(|x| eval_native_script(x, context).is_ok())
+                .count() as u32;
+
+            match satisfied >= *required {
+                true => Ok(()),
+                false => Err(ValidationError::ScriptThresholdNotMet {
+                    required: *required,
+                    satisfied,
+                }),
+            }
+        }
+        NativeScript::InvalidBefore(slot) => match context.validity_start {
+            Some(start) if *slot <= start => Ok(()),
+            _ => Err(ValidationError::ScriptTimelockNotMet),
+        },
+        NativeScript::InvalidHereafter(slot) => match context.ttl {
+            Some(ttl) if ttl <= *slot => Ok(()),
+            _ => Err(ValidationError::ScriptTimelockNotMet),
+        },
+    }
+}
+
+/// Evaluates the native scripts locking the inputs of a transaction
+///
+/// Each script is evaluated once, no matter how many inputs it guards. The
+/// policies of the mint field are required as well, even if their assets net
+/// out to zero. Scripts may be carried in the witness set or be the reference
+/// scripts of the outputs the tx spends or references, so a tx providing all
+/// of them by reference carries none. Inputs locked by a Plutus script only
+/// need the script to be present, its evaluation being left for phase-2
+/// validation.
+pub fn check_native_scripts<'b>(metx: &MultiEraTx, utxos: &impl UtxoStore<'b>) -> ValidationResult {
+    let context = NativeScriptContext::from_tx(metx);
+
+    let (inputs, reference_inputs) = (metx.inputs(), metx.reference_inputs());
+    let outputs: Vec<_> = inputs
+        .iter()
+        .chain(reference_inputs.iter())
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .collect();
+    let reference_scripts: Vec<_> = outputs
+        .iter()
+        .filter_map(|x| x.script_ref().map(|x| &x.0))
+        .collect();
+
+    let mut native_scripts: HashMap<Hash<28>, &NativeScript> = metx
+        .native_scripts()
+        .iter()
+        .map(|x| (x.compute_hash(), x))
+        .collect();
+
+    let mut plutus_scripts: HashSet<Hash<28>> = metx
+        .plutus_v1_scripts()
+        .iter()
+        .map(|x| x.compute_hash())
+        .chain(metx.plutus_v2_scripts().iter().map(|x| x.compute_hash()))
+        .collect();
+
+    for script in reference_scripts.iter() {
+        match script {
+            Script::NativeScript(x) => {
+                native_scripts.insert(x.compute_hash(), x);
+            }
+            Script::PlutusV1Script(x) => {
+                plutus_scripts.insert(x.compute_hash());
+            }
+            Script::PlutusV2Script(x) => {
+                plutus_scripts.insert(x.compute_hash());
+            }
+        }
+    }
+
+    let mut required = spent_scripts(metx, utxos)?;
+
+    for policy in metx.mints() {
+        required.insert(*policy.policy());
+    }
+
+    for hash in required {
+        match native_scripts.get(&hash) {
+            Some(script) => eval_native_script(script, &context)?,
+            None if plutus_scripts.contains(&hash) => (),
+            None => return Err(ValidationError::ScriptWitnessMissing(hash)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the hashes of the scripts a transaction needs to carry (or reference)
+///
+/// These come from the inputs locked by scripts, the policies of the mint
+/// field, the certificates for script credentials requiring a witness and the
+/// withdrawals from script reward accounts.
+pub fn required_scripts<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> Result<HashSet<Hash<28>>, ValidationError> {
+    let mut required = spent_scripts(metx, utxos)?;
+
+    for policy in metx.mints() {
+        required.insert(*policy.policy());
+    }
+
+    for cert in metx.certs() {
+        match cert.as_alonzo() {
+            Some(Certificate::StakeDeregistration(StakeCredential::Scripthash(hash)))
+            | Some(Certificate::StakeDelegation(StakeCredential::Scripthash(hash), _)) => {
+                required.insert(*hash);
+            }
+            _ => (),
+        }
+    }
+
+    for (account, _) in metx.withdrawals().collect::<Vec<_>>() {
+        if let Ok(Address::Stake(address)) = Address::from_bytes(account) {
+            if let StakePayload::Script(hash) = address.payload() {
+                required.insert(*hash);
+            }
+        }
+    }
+
+    Ok(required)
+}
+
+/// Collects the hashes of the scripts locking the inputs of a transaction
+fn spent_scripts<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> Result<HashSet<Hash<28>>, ValidationError> {
+    let mut scripts = HashSet::new();
+
+    for input in metx.inputs() {
+        let output = utxos
+            .resolve(&input.output_ref())
+            .ok_or_else(|| ValidationError::InputNotInUTxO(input.output_ref()))?;
+
+        if let Ok(Address::Shelley(address)) = output.address() {
+            if let ShelleyPaymentPart::Script(hash) = address.payment() {
+                scripts.insert(*hash);
+            }
+        }
+    }
+
+    Ok(scripts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use std::borrow::Cow;
+
+    use pallas_codec::{
+        minicbor,
+        utils::{CborWrap, KeyValuePairs},
+    };
+    use pallas_primitives::babbage::{self, MintedTransactionOutput, PlutusV2Script};
+    use pallas_traverse::{Era, MultiEraOutput, MultiEraTx, OutputRef};
+
+    fn multisig(keys: &[Hash<28>]) -> NativeScript {
+        NativeScript::ScriptNOfK(
+            2,
+            keys.iter()
+                .map(|x| NativeScript::ScriptPubkey(*x))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn threshold_met_across_inputs() {
+        let keys = [secret_key(1), secret_key(2), secret_key(3)];
+        let script = multisig(&keys.iter().map(key_hash).collect::<Vec<_>>());
+        let address = script_address(script.compute_hash());
+
+        let inputs = vec![tx_input(1, 0), tx_input(2, 0)];
+        let utxos = alonzo_utxos(&[
+            (inputs[0].clone(), alonzo_output(&address, 2_000_000)),
+            (inputs[1].clone(), alonzo_output(&address, 3_000_000)),
+        ]);
+
+        let body = alonzo_body(inputs, vec![], 0);
+        let bytes = alonzo_tx(body, &keys[..2], vec![script]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(check_native_scripts(&metx, &utxos), Ok(()));
+    }
+
+    #[test]
+    fn threshold_not_met() {
+        let keys = [secret_key(1), secret_key(2), secret_key(3)];
+        let script = multisig(&keys.iter().map(key_hash).collect::<Vec<_>>());
+        let address = script_address(script.compute_hash());
+
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&address, 2_000_000))]);
+
+        let body = alonzo_body(inputs, vec![], 0);
+        let bytes = alonzo_tx(body, &keys[..1], vec![script]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            check_native_scripts(&metx, &utxos),
+            Err(ValidationError::ScriptThresholdNotMet {
+                required: 2,
+                satisfied: 1
+            })
+        );
+    }
+
+    #[test]
+    fn repeated_witness_counts_once() {
+        let keys = [secret_key(1), secret_key(2), secret_key(3)];
+        let script = multisig(&keys.iter().map(key_hash).collect::<Vec<_>>());
+
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 0);
+        let signers = [secret_key(1), secret_key(1)];
+        let bytes = alonzo_tx(body, &signers, vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        let context = NativeScriptContext::from_tx(&metx);
+
+        assert_eq!(
+            eval_native_script(&script, &context),
+            Err(ValidationError::ScriptThresholdNotMet {
+                required: 2,
+                satisfied: 1
+            })
+        );
+    }
+
+    #[test]
+    fn missing_script() {
+        let script = NativeScript::ScriptPubkey(key_hash(&secret_key(1)));
+        let address = script_address(script.compute_hash());
+
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&address, 2_000_000))]);
+
+        let body = alonzo_body(inputs, vec![], 0);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            check_native_scripts(&metx, &utxos),
+            Err(ValidationError::ScriptWitnessMissing(script.compute_hash()))
+        );
+    }
+
+    #[test]
+    fn zero_mint_requires_policy() {
+        let script = NativeScript::ScriptPubkey(key_hash(&secret_key(1)));
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 0);
+        body.mint = Some(KeyValuePairs::from(vec![(
+            script.compute_hash(),
+            KeyValuePairs::from(vec![(b"token".to_vec().into(), 0)]),
+        )]));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&[], 2_000_000))]);
+
+        let bytes = alonzo_tx(body.clone(), &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            check_native_scripts(&metx, &utxos),
+            Err(ValidationError::ScriptWitnessMissing(script.compute_hash()))
+        );
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![script]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(check_native_scripts(&metx, &utxos), Ok(()));
+    }
+
+    #[test]
+    fn scripts_from_every_source() {
+        let hashes: Vec<_> = (1..=4).map(|x| Hash::<28>::new([x; 28])).collect();
+
+        let utxos = alonzo_utxos(&[
+            (
+                tx_input(1, 0),
+                alonzo_output(&script_address(hashes[0]), 2_000_000),
+            ),
+            (tx_input(2, 0), alonzo_output(&[], 2_000_000)),
+        ]);
+
+        let mut body = alonzo_body(vec![tx_input(1, 0), tx_input(2, 0)], vec![], 0);
+        body.mint = Some(KeyValuePairs::from(vec![(
+            hashes[1],
+            KeyValuePairs::from(vec![(b"token".to_vec().into(), 1)]),
+        )]));
+        body.certificates = Some(vec![
+            Certificate::StakeRegistration(StakeCredential::Scripthash(Hash::new([9; 28]))),
+            Certificate::StakeDeregistration(StakeCredential::Scripthash(hashes[2])),
+        ]);
+        body.withdrawals = Some(KeyValuePairs::from(vec![(
+            [&[0xf0], hashes[3].as_ref()].concat().into(),
+            1_000_000,
+        )]));
+
+        let bytes = alonzo_tx(body, &[], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            required_scripts(&metx, &utxos),
+            Ok(HashSet::from_iter(hashes))
+        );
+    }
+
+    #[test]
+    fn timelocks() {
+        let context = NativeScriptContext {
+            validity_start: Some(100),
+            ttl: Some(200),
+            ..Default::default()
+        };
+
+        assert!(eval_native_script(&NativeScript::InvalidBefore(100), &context).is_ok());
+        assert!(eval_native_script(&NativeScript::InvalidBefore(101), &context).is_err());
+        assert!(eval_native_script(&NativeScript::InvalidHereafter(200), &context).is_ok());
+        assert!(eval_native_script(&NativeScript::InvalidHereafter(199), &context).is_err());
+    }
+
+    #[test]
+    fn scripts_by_reference_only() {
+        let script = PlutusV2Script(vec![0x4e, 0x4d].into());
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let reference = match babbage_output(&address, 2_000_000) {
+            babbage::TransactionOutput::PostAlonzo(x) => {
+                babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+                    script_ref: Some(CborWrap(Script::PlutusV2Script(script.clone()))),
+                    ..x
+                })
+            }
+            x => x,
+        };
+        let reference_bytes = minicbor::to_vec(reference).unwrap();
+        let reference: MintedTransactionOutput = minicbor::decode(&reference_bytes).unwrap();
+
+        let mut utxos = alonzo_utxos(&[(
+            tx_input(1, 0),
+            alonzo_output(&script_address(script.compute_hash()), 2_000_000),
+        )]);
+        utxos.insert(
+            OutputRef::new(tx_input(2, 0).transaction_id, 0),
+            MultiEraOutput::Babbage(Box::new(Cow::Owned(reference))),
+        );
+
+        let mut body = babbage_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = babbage_tx(body.clone(), &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_native_scripts(&metx, &utxos),
+            Err(ValidationError::ScriptWitnessMissing(script.compute_hash()))
+        );
+
+        body.reference_inputs = Some(vec![tx_input(2, 0)]);
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert!(metx.plutus_v2_scripts().is_empty());
+        assert_eq!(check_native_scripts(&metx, &utxos), Ok(()));
+    }
+}
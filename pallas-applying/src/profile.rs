@@ -0,0 +1,184 @@
+//! Timing of the validation phases, for finding out which one dominates
+
+use std::time::{Duration, Instant};
+
+use pallas_traverse::MultiEraTx;
+
+use crate::{
+    certs::{
+        check_certificate_scripts, check_contradictory_certificates, check_pool_registrations,
+    },
+    environment::ShelleyProtParams,
+    fee::check_tx_size,
+    metadata::check_aux_data,
+    mint::check_mint,
+    native_script::check_native_scripts,
+    shelley_ma::{check_min_fee, check_preservation_of_value},
+    structure::{
+        check_current_slot, check_era_fields, check_inputs_not_empty, check_inputs_unique,
+        check_output_features, check_validity_interval,
+    },
+    value::{check_output_values, check_outputs_within_inputs},
+    witnesses::{
+        check_input_witness_eras, check_vkey_witnesses, check_withdrawals, verify_tx_witnesses,
+    },
+    UtxoStore, ValidationError, ValidationResult,
+};
+
+/// Time spent in each phase of the validation of a tx
+///
+/// Phases not reached, because an earlier one failed, stay at zero.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ValidationTiming {
+    /// Looking up the inputs in the UTxO set
+    pub resolve_inputs: Duration,
+
+    /// Matching witnesses to inputs and verifying their signatures
+    pub verify_witnesses: Duration,
+
+    /// Evaluating the native scripts and authorizing script credentials
+    pub eval_scripts: Duration,
+
+    /// Checking the fee and the balance between inputs and outputs
+    pub check_balance: Duration,
+
+    /// Every other, mostly structural, check
+    pub other: Duration,
+}
+
+impl ValidationTiming {
+    pub fn total(&self) -> Duration {
+        self.resolve_inputs
+            + self.verify_witnesses
+            + self.eval_scripts
+            + self.check_balance
+            + self.other
+    }
+}
+
+/// Validates a Shelley, Allegra or Mary tx, timing each phase
+///
+/// Runs the checks of
+/// [validate_shelley_ma_tx](crate::shelley_ma::validate_shelley_ma_tx) grouped
+/// by phase, plus the verification of the witness signatures, which that one
+/// leaves out but which is often the bottleneck.
+pub fn validate_profiled<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ShelleyProtParams,
+    slot: Option<u64>,
+) -> (ValidationResult, ValidationTiming) {
+    let mut timing = ValidationTiming::default();
+    let result = run_phases(metx, utxos, prot_pps, slot, &mut timing);
+
+    (result, timing)
+}
+
+fn run_phases<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ShelleyProtParams,
+    slot: Option<u64>,
+    timing: &mut ValidationTiming,
+) -> ValidationResult {
+    timed(&mut timing.other, || {
+        check_era_fields(metx)?;
+        check_inputs_not_empty(metx)?;
+        check_inputs_unique(metx)?;
+        check_output_features(metx)?;
+        check_validity_interval(metx)?;
+        check_current_slot(metx, slot)?;
+        check_output_values(metx.outputs(), |_| prot_pps.min_utxo_value)?;
+        check_aux_data(metx)?;
+        check_mint(metx)?;
+        check_pool_registrations(metx)?;
+        check_contradictory_certificates(metx)
+    })?;
+
+    timed(&mut timing.resolve_inputs, || {
+        match metx
+            .inputs()
+            .iter()
+            .find(|x| utxos.resolve(&x.output_ref()).is_none())
+        {
+            Some(input) => Err(ValidationError::InputNotInUTxO(input.output_ref())),
+            None => Ok(()),
+        }
+    })?;
+
+    timed(&mut timing.verify_witnesses, || {
+        check_input_witness_eras(metx, utxos)?;
+        verify_tx_witnesses(metx)?;
+        check_vkey_witnesses(metx, utxos)
+    })?;
+
+    timed(&mut timing.eval_scripts, || {
+        check_native_scripts(metx, utxos)?;
+        check_withdrawals(metx)?;
+        check_certificate_scripts(metx, utxos)
+    })?;
+
+    timed(&mut timing.check_balance, || {
+        check_tx_size(metx, prot_pps.max_tx_size)?;
+        check_min_fee(metx, prot_pps)?;
+        check_outputs_within_inputs(metx, utxos, prot_pps.key_deposit)?;
+        check_preservation_of_value(
+            metx,
+            utxos,
+            prot_pps.key_deposit,
+            prot_pps.pool_deposit,
+            |_| false,
+        )
+    })
+}
+
+fn timed(slot: &mut Duration, phase: impl FnOnce() -> ValidationResult) -> ValidationResult {
+    let start = Instant::now();
+    let result = phase();
+    *slot += start.elapsed();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::*, UTxOs};
+
+    use pallas_traverse::Era;
+
+    const PROT_PPS: ShelleyProtParams = ShelleyProtParams {
+        min_fee_a: 44,
+        min_fee_b: 155381,
+        max_tx_size: 16384,
+        key_deposit: 2_000_000,
+        pool_deposit: 500_000_000,
+        min_utxo_value: 1_000_000,
+    };
+
+    #[test]
+    fn phases_reached() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let (result, timing) = validate_profiled(&metx, &utxos, &PROT_PPS, None);
+
+        assert_eq!(result, Ok(()));
+        assert!(timing.verify_witnesses > Duration::ZERO);
+        assert!(timing.check_balance > Duration::ZERO);
+
+        let (result, timing) = validate_profiled(&metx, &UTxOs::new(), &PROT_PPS, None);
+
+        assert!(matches!(result, Err(ValidationError::InputNotInUTxO(_))));
+        assert_eq!(timing.verify_witnesses, Duration::ZERO);
+        assert_eq!(timing.check_balance, Duration::ZERO);
+    }
+}
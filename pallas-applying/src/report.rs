@@ -0,0 +1,102 @@
+//! Validation errors enriched with the context needed to display them
+
+use pallas_crypto::hash::Hash;
+use pallas_traverse::{Era, MultiEraTx};
+
+use crate::{
+    environment::ShelleyProtParams, shelley_ma::validate_shelley_ma_tx, UtxoStore, ValidationError,
+};
+
+/// A [ValidationError] together with the transaction it was raised for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub error: ValidationError,
+    pub tx_hash: Hash<32>,
+    pub era: Era,
+
+    /// Index of the input or output the error refers to, if any
+    pub offending_index: Option<usize>,
+}
+
+impl ValidationReport {
+    pub fn new(metx: &MultiEraTx, error: ValidationError) -> Self {
+        Self {
+            offending_index: offending_index(metx, &error),
+            tx_hash: metx.hash(),
+            era: metx.era(),
+            error,
+        }
+    }
+}
+
+/// Finds the input or output an error refers to
+///
+/// Output errors carry the index themselves, while input errors carry the
+/// output ref, which is looked up among the inputs of the transaction.
+fn offending_index(metx: &MultiEraTx, error: &ValidationError) -> Option<usize> {
+    match error {
+        ValidationError::OutputWithoutLovelace(index)
+        | ValidationError::OutputAddressMalformed(index)
+        | ValidationError::OutputFeatureFromFutureEra(index)
+        | ValidationError::OutputAddressAttrsTooLarge { index, .. }
+        | ValidationError::DatumOnKeyAddress(index)
+        | ValidationError::MalformedInlineDatum(index) => Some(*index),
+        ValidationError::InputNotInUTxO(output_ref)
+        | ValidationError::UnresolvableDependency(output_ref)
+        | ValidationError::WitnessKeyAddressMismatch(output_ref) => metx
+            .inputs()
+            .iter()
+            .position(|x| x.output_ref() == *output_ref),
+        _ => None,
+    }
+}
+
+/// Validates a transaction, reporting the failure with its context
+///
+/// Runs the same checks as
+/// [validate_shelley_ma_tx](crate::shelley_ma::validate_shelley_ma_tx).
+pub fn validate_reporting<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ShelleyProtParams,
+    slot: Option<u64>,
+) -> Result<(), ValidationReport> {
+    validate_shelley_ma_tx(metx, utxos, prot_pps, slot).map_err(|x| ValidationReport::new(metx, x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_traverse::OutputRef;
+
+    #[test]
+    fn missing_input_index() {
+        let prot_pps = ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        };
+
+        let inputs = vec![tx_input(1, 0), tx_input(2, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        let body = alonzo_body(inputs, vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            validate_reporting(&metx, &utxos, &prot_pps, None),
+            Err(ValidationReport {
+                error: ValidationError::InputNotInUTxO(OutputRef::new(Hash::new([2; 32]), 0)),
+                tx_hash: metx.hash(),
+                era: Era::Mary,
+                offending_index: Some(1),
+            })
+        );
+    }
+}
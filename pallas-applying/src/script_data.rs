@@ -0,0 +1,290 @@
+//! Recomputation of the script data hash of a transaction
+
+use pallas_codec::minicbor::{self, Encoder};
+use pallas_crypto::hash::{Hash, Hasher};
+use pallas_primitives::babbage::{CostMdls, CostModel, Script};
+use pallas_traverse::MultiEraTx;
+
+use crate::{UtxoStore, ValidationError, ValidationResult};
+
+/// Plutus languages used by a transaction, either through its witnesses or
+/// through the reference scripts of the outputs it spends or references
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Languages {
+    pub plutus_v1: bool,
+    pub plutus_v2: bool,
+}
+
+pub fn languages<'b>(metx: &MultiEraTx, utxos: &impl UtxoStore<'b>) -> Languages {
+    let mut languages = Languages {
+        plutus_v1: !metx.plutus_v1_scripts().is_empty(),
+        plutus_v2: !metx.plutus_v2_scripts().is_empty(),
+    };
+
+    let (inputs, reference_inputs) = (metx.inputs(), metx.reference_inputs());
+    let outputs: Vec<_> = inputs
+        .iter()
+        .chain(reference_inputs.iter())
+        .filter_map(|x| utxos.resolve(&x.output_ref()))
+        .collect();
+    let scripts = outputs.iter().filter_map(|x| x.script_ref().map(|x| &x.0));
+
+    for script in scripts {
+        match script {
+            Script::PlutusV1Script(_) => languages.plutus_v1 = true,
+            Script::PlutusV2Script(_) => languages.plutus_v2 = true,
+            Script::NativeScript(_) => (),
+        }
+    }
+
+    languages
+}
+
+/// Encodes the language views of the cost models of the given languages
+///
+/// Keys are sorted in canonical CBOR order, shorter encodings first, which
+/// puts PlutusV2 (`01`) ahead of PlutusV1 (`41 00`). Following the ledger, the
+/// PlutusV1 view keeps its historical quirk: both its key and its cost model,
+/// as an indefinite-length array, are wrapped in a bytestring. Languages
+/// without a cost model in the parameters are left out.
+pub fn language_views(languages: Languages, cost_models: &CostMdls) -> Vec<u8> {
+    let v1 = cost_models
+        .plutus_v1
+        .as_ref()
+        .filter(|_| languages.plutus_v1);
+    let v2 = cost_models
+        .plutus_v2
+        .as_ref()
+        .filter(|_| languages.plutus_v2);
+
+    let mut e = Encoder::new(Vec::new());
+    e.map(v1.is_some() as u64 + v2.is_some() as u64).unwrap();
+
+    if let Some(model) = v2 {
+        e.u8(1).unwrap();
+        e.array(model.len() as u64).unwrap();
+        for x in model {
+            e.i64(*x).unwrap();
+        }
+    }
+
+    if let Some(model) = v1 {
+        e.bytes(&minicbor::to_vec(0u8).unwrap()).unwrap();
+        e.bytes(&indefinite_cost_model(model)).unwrap();
+    }
+
+    e.into_writer()
+}
+
+fn indefinite_cost_model(model: &CostModel) -> Vec<u8> {
+    let mut e = Encoder::new(Vec::new());
+    e.begin_array().unwrap();
+    for x in model {
+        e.i64(*x).unwrap();
+    }
+    e.end().unwrap();
+
+    e.into_writer()
+}
+
+/// Computes the script data hash a transaction should declare, if any
+///
+/// The hash covers the redeemers, the datums of the witness set as they were
+/// encoded and the language views of the languages the tx uses. A tx without
+/// any of them declares no hash.
+pub fn script_data_hash<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    cost_models: &CostMdls,
+) -> Option<Hash<32>> {
+    let redeemers = metx.redeemers();
+    let datums = metx.plutus_data();
+    let languages = languages(metx, utxos);
+
+    if redeemers.is_empty() && datums.is_empty() && languages == Languages::default() {
+        return None;
+    }
+
+    let mut hasher = Hasher::<256>::new();
+    hasher.input(&minicbor::to_vec(redeemers).unwrap());
+
+    if !datums.is_empty() {
+        let mut e = Encoder::new(Vec::new());
+        e.array(datums.len() as u64).unwrap();
+        hasher.input(&e.into_writer());
+
+        for datum in datums {
+            hasher.input(datum.raw_cbor());
+        }
+    }
+
+    hasher.input(&language_views(languages, cost_models));
+
+    Some(hasher.finalize())
+}
+
+/// Checks the script data hash declared by the tx against the one computed
+/// from its witnesses and the cost models of the languages it uses
+pub fn check_script_data_hash<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    cost_models: &CostMdls,
+) -> ValidationResult {
+    let declared = match (metx.as_alonzo(), metx.as_babbage()) {
+        (Some(x), _) => x.transaction_body.script_data_hash,
+        (_, Some(x)) => x.transaction_body.script_data_hash,
+        _ => return Ok(()),
+    };
+
+    let computed = script_data_hash(metx, utxos, cost_models);
+
+    match declared == computed {
+        true => Ok(()),
+        false => Err(ValidationError::ScriptDataHashMismatch { computed }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::*, UTxOs};
+
+    use pallas_primitives::{
+        alonzo::{ExUnits, RedeemerTag},
+        babbage::{self, PlutusV2Script, Redeemer},
+    };
+    use pallas_traverse::{Era, MultiEraBlock};
+
+    const V1_MODEL: [i64; 3] = [100, 200, 300];
+
+    const V2_MODEL: [i64; 3] = [-1, 0, 24];
+
+    fn cost_models() -> CostMdls {
+        CostMdls {
+            plutus_v1: Some(V1_MODEL.to_vec()),
+            plutus_v2: Some(V2_MODEL.to_vec()),
+        }
+    }
+
+    fn v2_tx(script_data_hash: Option<Hash<32>>) -> Vec<u8> {
+        let mut body = babbage_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.script_data_hash = script_data_hash;
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+
+        let mut tx: babbage::Tx = minicbor::decode(&bytes).unwrap();
+        tx.transaction_witness_set.plutus_v2_script = Some(vec![PlutusV2Script(vec![0x4e].into())]);
+        tx.transaction_witness_set.redeemer = Some(vec![redeemer()]);
+
+        minicbor::to_vec(tx).unwrap()
+    }
+
+    fn redeemer() -> Redeemer {
+        Redeemer {
+            tag: RedeemerTag::Spend,
+            index: 0,
+            data: babbage::PlutusData::BoundedBytes(vec![].into()),
+            ex_units: ExUnits { mem: 1, steps: 2 },
+        }
+    }
+
+    #[test]
+    fn v2_only_tx() {
+        // redeemers ‖ { 1: [-1, 0, 24] }, without any trace of the V1 model
+        let preimage = [
+            minicbor::to_vec(vec![redeemer()]).unwrap(),
+            vec![0xa1, 0x01, 0x83, 0x20, 0x00, 0x18, 0x18],
+        ]
+        .concat();
+        let expected = Hasher::<256>::hash(&preimage);
+
+        let bytes = v2_tx(Some(expected));
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            languages(&metx, &UTxOs::new()),
+            Languages {
+                plutus_v1: false,
+                plutus_v2: true
+            }
+        );
+        assert_eq!(
+            check_script_data_hash(&metx, &UTxOs::new(), &cost_models()),
+            Ok(())
+        );
+
+        let all_models = [
+            minicbor::to_vec(vec![redeemer()]).unwrap(),
+            language_views(
+                Languages {
+                    plutus_v1: true,
+                    plutus_v2: true,
+                },
+                &cost_models(),
+            ),
+        ]
+        .concat();
+        let wrong = Hasher::<256>::hash(&all_models);
+
+        let bytes = v2_tx(Some(wrong));
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        assert_eq!(
+            check_script_data_hash(&metx, &UTxOs::new(), &cost_models()),
+            Err(ValidationError::ScriptDataHashMismatch {
+                computed: Some(expected)
+            })
+        );
+    }
+
+    #[test]
+    fn canonical_view_order() {
+        let views = language_views(
+            Languages {
+                plutus_v1: true,
+                plutus_v2: true,
+            },
+            &cost_models(),
+        );
+
+        assert_eq!(hex::encode(views), "a20183200018184100499f186418c819012cff");
+    }
+
+    #[test]
+    fn on_chain_plutus_v1_tx() {
+        // a block from the public testnet whose first tx runs a PlutusV1
+        // script, while its fourth one proposes updates restating the
+        // PlutusV1 cost model the script was run with
+        let bytes = hex::decode(include_str!("../../test_data/babbage4.block")).unwrap();
+        let block = MultiEraBlock::decode(&bytes).unwrap();
+        let txs = block.txs();
+
+        let update = txs[3]
+            .as_babbage()
+            .and_then(|x| x.transaction_body.update.as_ref())
+            .unwrap();
+        let (_, params) = &update.proposed_protocol_parameter_updates[0];
+        let cost_models = params.cost_models_for_script_languages.clone().unwrap();
+        assert!(cost_models.plutus_v2.is_none());
+
+        let metx = &txs[0];
+        let expected: Hash<32> = "8cb37a3dd81b016c5e030f512f9653e3eda7e160cccd3af23ee89a63423cbcd8"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            languages(metx, &UTxOs::new()),
+            Languages {
+                plutus_v1: true,
+                plutus_v2: false
+            }
+        );
+        assert_eq!(
+            script_data_hash(metx, &UTxOs::new(), &cost_models),
+            Some(expected)
+        );
+        assert_eq!(
+            check_script_data_hash(metx, &UTxOs::new(), &cost_models),
+            Ok(())
+        );
+    }
+}
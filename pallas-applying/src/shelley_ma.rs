@@ -0,0 +1,647 @@
+//! Validation of transactions from the Shelley, Allegra and Mary eras
+
+use std::collections::HashSet;
+
+use pallas_crypto::hash::Hash;
+use pallas_primitives::alonzo::{Certificate, MintedTx};
+use pallas_traverse::{Era, MultiEraTx};
+
+use crate::{
+    certs::{
+        check_certificate_scripts, check_contradictory_certificates, check_pool_registrations,
+    },
+    environment::ShelleyProtParams,
+    fee::{check_tx_size, FeeModel},
+    metadata::check_aux_data,
+    mint::{check_mint, minted_assets},
+    native_script::check_native_scripts,
+    structure::{
+        check_current_slot, check_era_fields, check_inputs_not_empty, check_inputs_unique,
+        check_output_features, check_validity_interval,
+    },
+    value::{check_output_values, check_outputs_within_inputs, Value},
+    witnesses::{
+        check_input_witness_eras, check_vkey_witnesses, check_withdrawals, verify_tx_witnesses,
+    },
+    UtxoStore, ValidationError, ValidationResult,
+};
+
+/// Validates a transaction of the Shelley era proper, decoded on its own
+///
+/// A shorthand for [validate_shelley_ma_tx] on the tx taken as a Shelley one,
+/// so that fields introduced by Allegra and Mary are rejected, at `block_slot`,
+/// the slot of the block including it.
+pub fn validate_shelley_tx<'b>(
+    mtx: &MintedTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ShelleyProtParams,
+    block_slot: u64,
+) -> ValidationResult {
+    let metx = MultiEraTx::from_alonzo_compatible(mtx, Era::Shelley);
+
+    validate_shelley_ma_tx(&metx, utxos, prot_pps, Some(block_slot))
+}
+
+/// Validates a Shelley, Allegra or Mary transaction
+///
+/// Its validity interval is only checked against the current `slot` when
+/// given.
+pub fn validate_shelley_ma_tx<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ShelleyProtParams,
+    slot: Option<u64>,
+) -> ValidationResult {
+    check_era_fields(metx)?;
+    check_inputs_not_empty(metx)?;
+    check_inputs_unique(metx)?;
+    check_output_features(metx)?;
+    check_validity_interval(metx)?;
+    check_current_slot(metx, slot)?;
+    check_output_values(metx.outputs(), |_| prot_pps.min_utxo_value)?;
+    check_aux_data(metx)?;
+    check_mint(metx)?;
+    check_native_scripts(metx, utxos)?;
+    check_input_witness_eras(metx, utxos)?;
+    verify_tx_witnesses(metx)?;
+    check_vkey_witnesses(metx, utxos)?;
+    check_withdrawals(metx)?;
+    check_certificate_scripts(metx, utxos)?;
+    check_pool_registrations(metx)?;
+    check_contradictory_certificates(metx)?;
+    check_tx_size(metx, prot_pps.max_tx_size)?;
+    check_min_fee(metx, prot_pps)?;
+    check_outputs_within_inputs(metx, utxos, prot_pps.key_deposit)?;
+    check_preservation_of_value(
+        metx,
+        utxos,
+        prot_pps.key_deposit,
+        prot_pps.pool_deposit,
+        |_| false,
+    )
+}
+
+/// Computes the minimum fee (`minFeeA * size + minFeeB`) for a transaction
+///
+/// The size is taken from the bytes the transaction was decoded from, never
+/// from a re-encoding, which might not reproduce non-canonical CBOR.
+pub fn compute_min_fee(
+    metx: &MultiEraTx,
+    prot_pps: &ShelleyProtParams,
+) -> Result<u64, ValidationError> {
+    prot_pps.min_fee(metx, None)
+}
+
+/// Checks that the declared fee covers the minimum required by the params
+///
+/// A declared fee of zero is reported like any other insufficient fee, with
+/// the error carrying both the declared and the required amounts.
+pub fn check_min_fee(metx: &MultiEraTx, prot_pps: &ShelleyProtParams) -> ValidationResult {
+    let fee = metx.fee().unwrap_or_default();
+    let min_fee = compute_min_fee(metx, prot_pps)?;
+
+    match fee >= min_fee {
+        true => Ok(()),
+        false => Err(ValidationError::FeesBelowMin { fee, min_fee }),
+    }
+}
+
+/// Computes the net deposit a transaction pays, in lovelace
+///
+/// New stake registrations pay `key_deposit` and pool registrations pay
+/// `pool_deposit`, unless `is_pool_registered` tells the pool is already
+/// registered or an earlier certificate of the tx registers it, in which case
+/// only its params get updated. Stake deregistrations get `key_deposit`
+/// refunded. A negative result means the tx takes more out of the deposit pot
+/// than it puts in. Takes the bare deposits since every era from Shelley
+/// onwards defines them.
+pub fn net_deposit(
+    metx: &MultiEraTx,
+    key_deposit: u64,
+    pool_deposit: u64,
+    is_pool_registered: impl Fn(&Hash<28>) -> bool,
+) -> i128 {
+    let (deposits, refunds) =
+        certificate_deposits(metx, key_deposit, pool_deposit, is_pool_registered);
+
+    deposits as i128 - refunds as i128
+}
+
+/// Adds up the deposits paid and refunded by the certificates of a tx, as
+/// described for [net_deposit]
+pub(crate) fn certificate_deposits(
+    metx: &MultiEraTx,
+    key_deposit: u64,
+    pool_deposit: u64,
+    is_pool_registered: impl Fn(&Hash<28>) -> bool,
+) -> (u64, u64) {
+    let (mut deposits, mut refunds) = (0u64, 0u64);
+    let mut registered = HashSet::new();
+
+    for cert in metx.certs() {
+        match cert.as_alonzo() {
+            Some(Certificate::StakeRegistration(_)) => deposits += key_deposit,
+            Some(Certificate::StakeDeregistration(_)) => refunds += key_deposit,
+            Some(Certificate::PoolRegistration { operator, .. })
+                if registered.insert(*operator) && !is_pool_registered(operator) =>
+            {
+                deposits += pool_deposit
+            }
+            _ => (),
+        }
+    }
+
+    (deposits, refunds)
+}
+
+fn pays_deposits(metx: &MultiEraTx) -> bool {
+    metx.certs().iter().any(|cert| {
+        matches!(
+            cert.as_alonzo(),
+            Some(Certificate::StakeRegistration(_))
+                | Some(Certificate::PoolRegistration { .. })
+                | Some(Certificate::StakeDeregistration(_))
+        )
+    })
+}
+
+/// Checks that the value consumed by a transaction equals the one it produces
+///
+/// Consumed value comes from the inputs, the withdrawals, the refunds of
+/// deregistered stake credentials and the minted assets, while produced value
+/// goes to the outputs, the fee, the deposits of new registrations and the
+/// burnt assets. The mint field is a multiasset keyed by policy id, so there
+/// is no way for it to carry lovelace and it never affects the lovelace
+/// balance.
+///
+/// For a tx with certificates paying or refunding deposits, a lovelace
+/// imbalance is reported as [ValidationError::DepositAccountingMismatch],
+/// comparing the [net_deposit] of its certificates with the one left over by
+/// the inputs and withdrawals once the outputs and the fee are paid. Without
+/// a ledger state to tell which pools are registered, `is_pool_registered`
+/// can be `|_| false`, charging every pool registration its deposit. A tx
+/// failing phase-2 validation only consumes its collateral, so there is no
+/// balance to check.
+pub fn check_preservation_of_value<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    key_deposit: u64,
+    pool_deposit: u64,
+    is_pool_registered: impl Fn(&Hash<28>) -> bool,
+) -> ValidationResult {
+    if !metx.is_valid() {
+        return Ok(());
+    }
+
+    let mut consumed = Value::default();
+    let mut produced = Value::from_coin(metx.fee().unwrap_or_default());
+
+    for input in metx.inputs() {
+        let output = utxos
+            .resolve(&input.output_ref())
+            .ok_or_else(|| ValidationError::InputNotInUTxO(input.output_ref()))?;
+
+        consumed = add_value(&consumed, &Value::from_output(&output))?;
+    }
+
+    for (_, amount) in metx.withdrawals().collect::<Vec<_>>() {
+        consumed = add_value(&consumed, &Value::from_coin(amount))?;
+    }
+
+    for output in metx.outputs() {
+        produced = add_value(&produced, &Value::from_output(&output))?;
+    }
+
+    for ((policy, name), quantity) in minted_assets(metx)? {
+        let value = Value::default().with_asset(policy, name, quantity.unsigned_abs());
+
+        match quantity > 0 {
+            true => consumed = add_value(&consumed, &value)?,
+            false => produced = add_value(&produced, &value)?,
+        }
+    }
+
+    let expected = net_deposit(metx, key_deposit, pool_deposit, is_pool_registered);
+    let actual = consumed.coin() as i128 - produced.coin() as i128;
+
+    if actual != expected {
+        return match pays_deposits(metx) {
+            true => Err(ValidationError::DepositAccountingMismatch { expected, actual }),
+            false => Err(ValidationError::ValueNotConserved(
+                consumed.coin(),
+                produced.coin(),
+            )),
+        };
+    }
+
+    match consumed.assets() == produced.assets() {
+        true => Ok(()),
+        false => Err(ValidationError::AssetsNotConserved),
+    }
+}
+
+fn add_value(total: &Value, value: &Value) -> Result<Value, ValidationError> {
+    total
+        .checked_add(value)
+        .ok_or(ValidationError::ValueOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fee::tx_fee_size, fixtures::*, UTxOs, UtxoKey};
+
+    use std::{borrow::Cow, collections::HashMap};
+
+    use pallas_codec::minicbor;
+    use pallas_codec::utils::KeyValuePairs;
+    use pallas_primitives::alonzo::StakeCredential;
+    use pallas_traverse::MultiEraOutput;
+
+    const PROT_PPS: ShelleyProtParams = ShelleyProtParams {
+        min_fee_a: 44,
+        min_fee_b: 155381,
+        max_tx_size: 16384,
+        key_deposit: 2_000_000,
+        pool_deposit: 500_000_000,
+        min_utxo_value: 1_000_000,
+    };
+
+    fn check_preservation(metx: &MultiEraTx, utxos: &UTxOs) -> ValidationResult {
+        check_preservation_of_value(
+            metx,
+            utxos,
+            PROT_PPS.key_deposit,
+            PROT_PPS.pool_deposit,
+            |_| false,
+        )
+    }
+
+    #[test]
+    fn fee_covers_min() {
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 1_000_000);
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![output], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(check_min_fee(&metx, &PROT_PPS), Ok(()));
+    }
+
+    #[test]
+    fn zero_fee() {
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 1_000_000);
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![output], 0);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_min_fee(&metx, &PROT_PPS),
+            Err(ValidationError::FeesBelowMin {
+                fee: 0,
+                min_fee: 44 * tx_fee_size(&metx) + 155381,
+            })
+        );
+    }
+
+    #[test]
+    fn non_canonical_body() {
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 1_000_000);
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![output], 60_000);
+        let canonical = alonzo_tx(body, &[secret_key(1)], vec![]);
+
+        // encode the fee (key 2) as a 4-byte uint instead of the minimal 2-byte one
+        let at = canonical
+            .windows(4)
+            .position(|x| x == [0x02, 0x19, 0xea, 0x60])
+            .unwrap();
+        let bytes = [
+            &canonical[..at],
+            &[0x02, 0x1a, 0x00, 0x00, 0xea, 0x60],
+            &canonical[at + 4..],
+        ]
+        .concat();
+
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+        let reencoded = MultiEraTx::decode(Era::Shelley, &canonical).unwrap();
+
+        assert_eq!(tx_fee_size(&metx), tx_fee_size(&reencoded) + 2);
+        assert_ne!(metx.hash(), reencoded.hash());
+        assert_eq!(
+            check_min_fee(&metx, &PROT_PPS),
+            Err(ValidationError::FeesBelowMin {
+                fee: 60_000,
+                min_fee: 44 * (tx_fee_size(&reencoded) + 2) + 155381,
+            })
+        );
+    }
+
+    #[test]
+    fn value_preserved_with_deposit() {
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 2_800_000);
+        let mut body = alonzo_body(inputs, vec![output], 200_000);
+        body.certificates = Some(vec![Certificate::StakeRegistration(
+            StakeCredential::AddrKeyhash(key_hash(&secret_key(1))),
+        )]);
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(check_preservation(&metx, &utxos), Ok(()));
+    }
+
+    fn stake_certs(registered: &[u8], deregistered: &[u8]) -> Vec<Certificate> {
+        let credential = |x: &u8| StakeCredential::AddrKeyhash(key_hash(&secret_key(*x)));
+
+        registered
+            .iter()
+            .map(|x| Certificate::StakeRegistration(credential(x)))
+            .chain(
+                deregistered
+                    .iter()
+                    .map(|x| Certificate::StakeDeregistration(credential(x))),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn mixed_registrations_and_refunds() {
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        // two registrations against a single refund, netting one deposit
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 2_800_000);
+        let mut body = alonzo_body(inputs.clone(), vec![output], 200_000);
+        body.certificates = Some(stake_certs(&[3, 4], &[5]));
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            net_deposit(&metx, PROT_PPS.key_deposit, PROT_PPS.pool_deposit, |_| {
+                false
+            }),
+            2_000_000
+        );
+        assert_eq!(check_preservation(&metx, &utxos), Ok(()));
+
+        // a single registration against two refunds, netting one refund
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 6_800_000);
+        let mut body = alonzo_body(inputs, vec![output], 200_000);
+        body.certificates = Some(stake_certs(&[3], &[4, 5]));
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            net_deposit(&metx, PROT_PPS.key_deposit, PROT_PPS.pool_deposit, |_| {
+                false
+            }),
+            -2_000_000
+        );
+        assert_eq!(check_preservation(&metx, &utxos), Ok(()));
+    }
+
+    #[test]
+    fn deposit_accounting_mismatch() {
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        // the outputs ignore the refund of the deregistration, leaving 2 ada
+        // of the refund unaccounted for
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 2_800_000);
+        let mut body = alonzo_body(inputs.clone(), vec![output], 200_000);
+        body.certificates = Some(stake_certs(&[3], &[4]));
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_preservation(&metx, &utxos),
+            Err(ValidationError::DepositAccountingMismatch {
+                expected: 0,
+                actual: 2_000_000,
+            })
+        );
+
+        // the outputs claim the refund of a deregistration never made
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 6_800_000);
+        let mut body = alonzo_body(inputs, vec![output], 200_000);
+        body.certificates = Some(stake_certs(&[3, 4], &[5]));
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_preservation(&metx, &utxos),
+            Err(ValidationError::DepositAccountingMismatch {
+                expected: 2_000_000,
+                actual: -2_000_000,
+            })
+        );
+    }
+
+    #[test]
+    fn mint_does_not_affect_lovelace() {
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 5_800_000);
+        let mut body = alonzo_body(inputs, vec![output], 200_000);
+        body.mint = Some(KeyValuePairs::from(vec![(
+            Hash::new([1; 28]),
+            KeyValuePairs::from(vec![
+                (b"burnt".to_vec().into(), -1_000_000),
+                (b"minted".to_vec().into(), 1_000_000),
+            ]),
+        )]));
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            check_preservation(&metx, &utxos),
+            Err(ValidationError::ValueNotConserved(5_000_000, 6_000_000))
+        );
+    }
+
+    #[test]
+    fn burnt_assets_not_conserved() {
+        let policy = Hash::new([1; 28]);
+
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 4_800_000);
+        let mut body = alonzo_body(inputs, vec![output], 200_000);
+        body.mint = Some(KeyValuePairs::from(vec![(
+            policy,
+            KeyValuePairs::from(vec![(b"burnt".to_vec().into(), -1)]),
+        )]));
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            check_preservation(&metx, &utxos),
+            Err(ValidationError::AssetsNotConserved)
+        );
+    }
+
+    #[test]
+    fn shelley_tx() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let tx = |inputs, coin, fee| {
+            let body = alonzo_body(inputs, vec![alonzo_output(&address, coin)], fee);
+            alonzo_tx(body, &[secret_key(1)], vec![])
+        };
+
+        let bytes = tx(vec![tx_input(1, 0)], 4_800_000, 200_000);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert_eq!(validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 0), Ok(()));
+
+        let bytes = tx(vec![], 4_800_000, 200_000);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert_eq!(
+            validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 0),
+            Err(ValidationError::TxInsEmpty)
+        );
+
+        let bytes = tx(vec![tx_input(2, 0)], 4_800_000, 200_000);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert!(matches!(
+            validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 0),
+            Err(ValidationError::InputNotInUTxO(_))
+        ));
+
+        let bytes = tx(vec![tx_input(1, 0)], 4_700_000, 200_000);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert_eq!(
+            validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 0),
+            Err(ValidationError::ValueNotConserved(5_000_000, 4_900_000))
+        );
+
+        let bytes = tx(vec![tx_input(1, 0)], 4_900_000, 100_000);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert!(matches!(
+            validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 0),
+            Err(ValidationError::FeesBelowMin { fee: 100_000, .. })
+        ));
+
+        let prot_pps = ShelleyProtParams {
+            max_tx_size: 64,
+            ..PROT_PPS
+        };
+        let bytes = tx(vec![tx_input(1, 0)], 4_800_000, 200_000);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert!(matches!(
+            validate_shelley_tx(&mtx, &utxos, &prot_pps, 0),
+            Err(ValidationError::MaxTxSizeExceeded { max: 64, .. })
+        ));
+    }
+
+    #[test]
+    fn output_below_min_utxo_value() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let outputs = vec![
+            alonzo_output(&address, 4_000_001),
+            alonzo_output(&address, 799_999),
+        ];
+        let body = alonzo_body(vec![tx_input(1, 0)], outputs, 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+
+        let prot_pps = ShelleyProtParams {
+            min_utxo_value: 800_000,
+            ..PROT_PPS
+        };
+
+        assert_eq!(
+            validate_shelley_tx(&mtx, &utxos, &prot_pps, 0),
+            Err(ValidationError::OutputTooSmall(799_999, 800_000))
+        );
+
+        let prot_pps = ShelleyProtParams {
+            min_utxo_value: 799_999,
+            ..PROT_PPS
+        };
+
+        assert_eq!(validate_shelley_tx(&mtx, &utxos, &prot_pps, 0), Ok(()));
+    }
+
+    #[test]
+    fn ttl() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let tx = |ttl| {
+            let mut body = alonzo_body(
+                vec![tx_input(1, 0)],
+                vec![alonzo_output(&address, 4_800_000)],
+                200_000,
+            );
+            body.ttl = ttl;
+            alonzo_tx(body, &[secret_key(1)], vec![])
+        };
+
+        let bytes = tx(Some(1_000));
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 1_000), Ok(()));
+        assert_eq!(
+            validate_shelley_tx(&mtx, &utxos, &PROT_PPS, 1_001),
+            Err(ValidationError::TxExpired(1_001, 1_000))
+        );
+
+        let bytes = tx(None);
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+
+        assert_eq!(
+            validate_shelley_tx(&mtx, &utxos, &PROT_PPS, u64::MAX),
+            Ok(())
+        );
+    }
+
+    /// Outputs kept encoded, as an on-disk store would, and decoded on lookup
+    struct EncodedUtxos<'b>(&'b HashMap<UtxoKey, Vec<u8>>);
+
+    impl<'b> UtxoStore<'b> for EncodedUtxos<'b> {
+        fn resolve(&self, output_ref: &UtxoKey) -> Option<Cow<'_, MultiEraOutput<'b>>> {
+            let bytes = self.0.get(output_ref)?;
+            let output = MultiEraOutput::decode(Era::Shelley, bytes).ok()?;
+
+            Some(Cow::Owned(output))
+        }
+    }
+
+    #[test]
+    fn encoded_utxo_store() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+        let encoded: HashMap<_, _> = utxos.iter().map(|(k, v)| (k.clone(), v.encode())).collect();
+        let store = EncodedUtxos(&encoded);
+
+        let tx = |input| {
+            let outputs = vec![alonzo_output(&address, 4_800_000)];
+            let body = alonzo_body(vec![input], outputs, 200_000);
+            alonzo_tx(body, &[secret_key(1)], vec![])
+        };
+
+        let bytes = tx(tx_input(1, 0));
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert_eq!(validate_shelley_tx(&mtx, &store, &PROT_PPS, 0), Ok(()));
+
+        let bytes = tx(tx_input(2, 0));
+        let mtx: MintedTx = minicbor::decode(&bytes).unwrap();
+        assert!(matches!(
+            validate_shelley_tx(&mtx, &store, &PROT_PPS, 0),
+            Err(ValidationError::InputNotInUTxO(_))
+        ));
+    }
+}
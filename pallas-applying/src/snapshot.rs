@@ -0,0 +1,127 @@
+//! Serialization of UTxO sets to and from CBOR snapshots
+//!
+//! A snapshot is a definite-length CBOR array with one entry per output,
+//! sorted by tx id and then by index so that equal sets always produce the
+//! same bytes. Each entry is itself a four-element array:
+//!
+//! ```text
+//! [ tx_id : bytes .size 32, index : uint, era : uint, output : bytes ]
+//! ```
+//!
+//! where `era` tells how the CBOR wrapped in `output` decodes: `0` for a
+//! Byron output, `1` for a Shelley to Alonzo one and `2` for a Babbage one.
+
+use pallas_codec::minicbor::{decode, Decoder, Encoder};
+use pallas_crypto::hash::Hash;
+use pallas_traverse::{Era, MultiEraOutput};
+
+use crate::{UTxOs, UtxoKey};
+
+const ERA_BYRON: u8 = 0;
+
+const ERA_ALONZO_COMPATIBLE: u8 = 1;
+
+const ERA_BABBAGE: u8 = 2;
+
+/// Encodes a UTxO set as a snapshot
+pub fn utxos_to_cbor(utxos: &UTxOs) -> Vec<u8> {
+    let mut entries: Vec<_> = utxos.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| (a.hash(), a.index()).cmp(&(b.hash(), b.index())));
+
+    // writing to a vec is infallible
+    let mut e = Encoder::new(Vec::new());
+    e.array(entries.len() as u64).unwrap();
+
+    for (key, output) in entries {
+        let era = match output {
+            MultiEraOutput::Byron(_) => ERA_BYRON,
+            MultiEraOutput::AlonzoCompatible(_) => ERA_ALONZO_COMPATIBLE,
+            MultiEraOutput::Babbage(_) => ERA_BABBAGE,
+            _ => unreachable!("no outputs beyond Babbage"),
+        };
+
+        e.array(4).unwrap();
+        e.bytes(key.hash().as_ref()).unwrap();
+        e.u64(key.index()).unwrap();
+        e.u8(era).unwrap();
+        e.bytes(&output.encode()).unwrap();
+    }
+
+    e.into_writer()
+}
+
+/// Decodes a snapshot produced by [utxos_to_cbor]
+///
+/// The outputs borrow from `bytes`, like those of a decoded block or tx.
+pub fn utxos_from_cbor_snapshot(bytes: &[u8]) -> Result<UTxOs<'_>, decode::Error> {
+    let mut d = Decoder::new(bytes);
+    let count = d
+        .array()?
+        .ok_or_else(|| decode::Error::message("snapshot must be a definite array"))?;
+
+    let mut utxos = UTxOs::new();
+
+    for _ in 0..count {
+        d.array()?;
+
+        let hash = <[u8; 32]>::try_from(d.bytes()?)
+            .map_err(|_| decode::Error::message("tx id must be 32 bytes long"))?;
+        let index = d.u64()?;
+
+        let era = match d.u8()? {
+            ERA_BYRON => Era::Byron,
+            ERA_ALONZO_COMPATIBLE => Era::Alonzo,
+            ERA_BABBAGE => Era::Babbage,
+            _ => return Err(decode::Error::message("unknown output era")),
+        };
+
+        let output = MultiEraOutput::decode(era, d.bytes()?)?;
+
+        utxos.insert(UtxoKey::new(Hash::new(hash), index), output);
+    }
+
+    Ok(utxos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use std::borrow::Cow;
+
+    use pallas_codec::minicbor;
+    use pallas_primitives::babbage::MintedTransactionOutput;
+
+    #[test]
+    fn round_trip() {
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let babbage_bytes = minicbor::to_vec(babbage_output(&address, 3_000_000)).unwrap();
+        let babbage: MintedTransactionOutput = minicbor::decode(&babbage_bytes).unwrap();
+
+        let mut utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 2_000_000))]);
+        utxos.insert(
+            UtxoKey::new(tx_input(2, 0).transaction_id, 1),
+            MultiEraOutput::Byron(Box::new(Cow::Owned(byron_output(&[], 1_000_000)))),
+        );
+        utxos.insert(
+            UtxoKey::new(tx_input(3, 0).transaction_id, 0),
+            MultiEraOutput::Babbage(Box::new(Cow::Owned(babbage))),
+        );
+
+        let bytes = utxos_to_cbor(&utxos);
+        let decoded = utxos_from_cbor_snapshot(&bytes).unwrap();
+
+        assert_eq!(decoded.len(), 3);
+        for (key, output) in utxos.iter() {
+            assert_eq!(decoded[key].encode(), output.encode());
+        }
+        assert!(decoded[&UtxoKey::new(tx_input(3, 0).transaction_id, 0)]
+            .as_babbage()
+            .is_some());
+
+        assert_eq!(utxos_to_cbor(&decoded), bytes);
+        assert!(utxos_from_cbor_snapshot(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
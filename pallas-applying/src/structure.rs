@@ -0,0 +1,384 @@
+//! Structural checks which don't depend on the UTxO set nor the params
+
+use std::collections::HashSet;
+
+use pallas_primitives::alonzo::{ProtocolVersion, Value};
+use pallas_traverse::{Era, MultiEraTx};
+
+use crate::{ValidationError, ValidationResult};
+
+/// Highest major protocol version whose rules are modelled
+const MAX_MAJOR_PROTOCOL_VERSION: u64 = 8;
+
+/// Checks that the protocol version is one the crate models and that it
+/// belongs to the era of the transaction
+///
+/// Byron spans majors 0 and 1, Shelley, Allegra and Mary take 2, 3 and 4,
+/// Alonzo 5 and 6 and Babbage 7 and 8. The intra-era hard forks at 1, 6 and 8
+/// changed nothing the validators check, so no rule branches on the minor
+/// version nor on the major one within an era. A major version past
+/// [MAX_MAJOR_PROTOCOL_VERSION] fails with
+/// [ValidationError::ProtocolVersionUnsupported], and one from another era
+/// with [ValidationError::EraMismatch].
+pub fn check_protocol_version(era: Era, version: ProtocolVersion) -> ValidationResult {
+    let (major, minor) = version;
+
+    if major > MAX_MAJOR_PROTOCOL_VERSION {
+        return Err(ValidationError::ProtocolVersionUnsupported { major, minor });
+    }
+
+    let matches = match era {
+        Era::Byron => major <= 1,
+        Era::Shelley => major == 2,
+        Era::Allegra => major == 3,
+        Era::Mary => major == 4,
+        Era::Alonzo => major == 5 || major == 6,
+        Era::Babbage => major == 7 || major == 8,
+        _ => false,
+    };
+
+    match matches {
+        true => Ok(()),
+        false => Err(ValidationError::EraMismatch(era)),
+    }
+}
+
+/// Checks that a transaction doesn't carry fields introduced after its era
+///
+/// Shelley, Allegra, Mary and Alonzo transactions share the same primitives,
+/// so a body decoded as, say, Shelley might still carry a mint field.
+pub fn check_era_fields(metx: &MultiEraTx) -> ValidationResult {
+    let era = metx.era();
+
+    let tx = match metx.as_alonzo() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let body = &tx.transaction_body;
+    let witness_set = &tx.transaction_witness_set;
+
+    let fields = [
+        (
+            "validity_interval_start",
+            Era::Allegra,
+            body.validity_interval_start.is_some(),
+        ),
+        ("mint", Era::Mary, body.mint.is_some()),
+        (
+            "script_data_hash",
+            Era::Alonzo,
+            body.script_data_hash.is_some(),
+        ),
+        ("collateral", Era::Alonzo, body.collateral.is_some()),
+        (
+            "required_signers",
+            Era::Alonzo,
+            body.required_signers.is_some(),
+        ),
+        ("network_id", Era::Alonzo, body.network_id.is_some()),
+        (
+            "plutus_script",
+            Era::Alonzo,
+            witness_set.plutus_script.is_some(),
+        ),
+        (
+            "plutus_data",
+            Era::Alonzo,
+            witness_set.plutus_data.is_some(),
+        ),
+        ("redeemer", Era::Alonzo, witness_set.redeemer.is_some()),
+    ];
+
+    for (field, since, present) in fields {
+        if present && era < since {
+            return Err(ValidationError::FieldNotAllowedInEra { field, era });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that no output of a transaction uses a feature introduced after its
+/// era
+///
+/// Outputs share the same primitives from Shelley up to Alonzo just like
+/// bodies do, so an output decoded as Shelley might still carry native assets
+/// (Mary onwards) or a datum hash (Alonzo onwards).
+pub fn check_output_features(metx: &MultiEraTx) -> ValidationResult {
+    let era = metx.era();
+
+    for (index, output) in metx.outputs().iter().enumerate() {
+        let output = match output.as_alonzo() {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let features = [
+            (Era::Mary, matches!(output.amount, Value::Multiasset(..))),
+            (Era::Alonzo, output.datum_hash.is_some()),
+        ];
+
+        if features
+            .iter()
+            .any(|(since, present)| *present && era < *since)
+        {
+            return Err(ValidationError::OutputFeatureFromFutureEra(index));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a transaction spends at least one input
+pub fn check_inputs_not_empty(metx: &MultiEraTx) -> ValidationResult {
+    match metx.inputs().is_empty() {
+        true => Err(ValidationError::TxInsEmpty),
+        false => Ok(()),
+    }
+}
+
+/// Checks that no output is spent twice by the same transaction, nor put up
+/// twice as collateral
+///
+/// The ledger reads the inputs as a set, so an input listed twice is spent
+/// only once; summing it twice into the balance would create value out of
+/// nothing.
+pub fn check_inputs_unique(metx: &MultiEraTx) -> ValidationResult {
+    for inputs in [metx.inputs(), metx.collateral()] {
+        let mut spent = HashSet::new();
+
+        for output_ref in inputs.iter().map(|x| x.output_ref()) {
+            if let Some(output_ref) = spent.replace(output_ref) {
+                return Err(ValidationError::DuplicateInput(output_ref));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the address of every output decodes
+pub fn check_output_addresses(metx: &MultiEraTx) -> ValidationResult {
+    for (index, output) in metx.outputs().iter().enumerate() {
+        if output.address().is_err() {
+            return Err(ValidationError::OutputAddressMalformed(index));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the validity interval isn't empty, i.e. that it doesn't start
+/// after it ends
+///
+/// Either bound may be absent, in which case there's nothing to compare.
+pub fn check_validity_interval(metx: &MultiEraTx) -> ValidationResult {
+    match (metx.validity_start(), metx.ttl()) {
+        (Some(start), Some(end)) if start > end => {
+            Err(ValidationError::InvalidValidityInterval { start, end })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that a transaction hasn't outlived its time to live, i.e. that the
+/// slot of the block including it doesn't exceed its TTL
+///
+/// A Shelley transaction remains valid in the very slot of its TTL, while from
+/// Allegra onwards the field is the exclusive upper bound of the validity
+/// interval, so the transaction already expires in that slot. Transactions
+/// without a TTL never expire.
+pub fn check_ttl(metx: &MultiEraTx, block_slot: u64) -> ValidationResult {
+    let expired = |ttl| match metx.era() {
+        Era::Shelley => block_slot > ttl,
+        _ => block_slot >= ttl,
+    };
+
+    match metx.ttl() {
+        Some(ttl) if expired(ttl) => Err(ValidationError::TxExpired(block_slot, ttl)),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the validity interval of a transaction, introduced in Allegra,
+/// has already started by the slot of the block including it
+pub fn check_validity_start(metx: &MultiEraTx, block_slot: u64) -> ValidationResult {
+    match metx.validity_start() {
+        Some(start) if block_slot < start => Err(ValidationError::TxNotYetValid(block_slot, start)),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the current slot, if known, falls within the validity interval
+/// of a transaction, as done by [check_validity_start] and [check_ttl]
+pub fn check_current_slot(metx: &MultiEraTx, slot: Option<u64>) -> ValidationResult {
+    match slot {
+        Some(slot) => {
+            check_validity_start(metx, slot)?;
+            check_ttl(metx, slot)
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_codec::utils::KeyValuePairs;
+    use pallas_crypto::hash::Hash;
+
+    #[test]
+    fn output_features() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let mut output = alonzo_output(&address, 2_000_000);
+        output.datum_hash = Some(Hash::new([1; 32]));
+
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![output], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+        assert_eq!(
+            check_output_features(&metx),
+            Err(ValidationError::OutputFeatureFromFutureEra(0))
+        );
+
+        let metx = MultiEraTx::decode(Era::Alonzo, &bytes).unwrap();
+        assert_eq!(check_output_features(&metx), Ok(()));
+
+        let mut output = alonzo_output(&address, 2_000_000);
+        output.amount = Value::Multiasset(2_000_000, KeyValuePairs::from(vec![]));
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 1_000_000), output],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+
+        let metx = MultiEraTx::decode(Era::Allegra, &bytes).unwrap();
+        assert_eq!(
+            check_output_features(&metx),
+            Err(ValidationError::OutputFeatureFromFutureEra(1))
+        );
+
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+        assert_eq!(check_output_features(&metx), Ok(()));
+    }
+
+    #[test]
+    fn mint_before_mary() {
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.mint = Some(KeyValuePairs::from(vec![(
+            Hash::new([1; 28]),
+            KeyValuePairs::from(vec![(b"token".to_vec().into(), 1)]),
+        )]));
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+
+        let metx = MultiEraTx::decode(Era::Allegra, &bytes).unwrap();
+
+        assert_eq!(
+            check_era_fields(&metx),
+            Err(ValidationError::FieldNotAllowedInEra {
+                field: "mint",
+                era: Era::Allegra
+            })
+        );
+
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(check_era_fields(&metx), Ok(()));
+    }
+
+    #[test]
+    fn validity_interval() {
+        let cases = [
+            (Some(100), Some(99), false),
+            (Some(100), Some(100), true),
+            (Some(100), None, true),
+            (None, Some(99), true),
+        ];
+
+        for (start, end, valid) in cases {
+            let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+            body.validity_interval_start = start;
+            body.ttl = end;
+            let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+            let metx = MultiEraTx::decode(Era::Allegra, &bytes).unwrap();
+
+            assert_eq!(check_validity_interval(&metx).is_ok(), valid);
+        }
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.validity_interval_start = Some(100);
+        body.ttl = Some(99);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Allegra, &bytes).unwrap();
+
+        assert_eq!(
+            check_validity_interval(&metx),
+            Err(ValidationError::InvalidValidityInterval {
+                start: 100,
+                end: 99
+            })
+        );
+    }
+
+    #[test]
+    fn current_slot() {
+        let tx = |era, start, ttl| {
+            let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+            body.validity_interval_start = start;
+            body.ttl = ttl;
+            let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+            (era, bytes)
+        };
+
+        let check = |(era, bytes): &(Era, Vec<u8>), slot| {
+            let metx = MultiEraTx::decode(*era, bytes).unwrap();
+            check_current_slot(&metx, slot)
+        };
+
+        // a Shelley TTL is inclusive, while the upper bound from Allegra
+        // onwards isn't
+        let shelley = tx(Era::Shelley, None, Some(100));
+        let allegra = tx(Era::Allegra, Some(50), Some(100));
+
+        assert_eq!(check(&shelley, Some(100)), Ok(()));
+        assert_eq!(
+            check(&shelley, Some(101)),
+            Err(ValidationError::TxExpired(101, 100))
+        );
+        assert_eq!(check(&allegra, Some(99)), Ok(()));
+        assert_eq!(
+            check(&allegra, Some(100)),
+            Err(ValidationError::TxExpired(100, 100))
+        );
+
+        assert_eq!(check(&allegra, Some(50)), Ok(()));
+        assert_eq!(
+            check(&allegra, Some(49)),
+            Err(ValidationError::TxNotYetValid(49, 50))
+        );
+
+        // nothing to compare against without a current slot
+        assert_eq!(check(&allegra, None), Ok(()));
+    }
+
+    #[test]
+    fn protocol_versions() {
+        assert_eq!(check_protocol_version(Era::Byron, (1, 0)), Ok(()));
+        assert_eq!(check_protocol_version(Era::Alonzo, (6, 0)), Ok(()));
+        assert_eq!(check_protocol_version(Era::Babbage, (8, 0)), Ok(()));
+        assert_eq!(
+            check_protocol_version(Era::Mary, (5, 0)),
+            Err(ValidationError::EraMismatch(Era::Mary))
+        );
+        assert_eq!(
+            check_protocol_version(Era::Babbage, (9, 1)),
+            Err(ValidationError::ProtocolVersionUnsupported { major: 9, minor: 1 })
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! Authorization of the protocol parameter updates proposed on chain
+
+use std::collections::HashSet;
+
+use pallas_codec::minicbor;
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::{PublicKey, Signature},
+};
+use pallas_primitives::byron;
+use pallas_traverse::MultiEraTx;
+
+use crate::{
+    byron::byron_signed_data, environment::GenesisDelegates, ledger::LedgerState, ValidationError,
+    ValidationResult,
+};
+
+/// Checks that the update proposed by a Shelley-era tx, if any, is signed by
+/// a quorum of genesis delegates
+///
+/// Each genesis key proposing an update counts once, provided it's one of the
+/// genesis keys of the environment and the key of its delegate signed the tx.
+pub fn check_update_quorum(metx: &MultiEraTx, genesis: &GenesisDelegates) -> ValidationResult {
+    let proposers: Vec<&[u8]> = match (metx.as_alonzo(), metx.as_babbage()) {
+        (Some(x), _) => match &x.transaction_body.update {
+            Some(update) => update
+                .proposed_protocol_parameter_updates
+                .iter()
+                .map(|(x, _)| x.as_slice())
+                .collect(),
+            None => return Ok(()),
+        },
+        (_, Some(x)) => match &x.transaction_body.update {
+            Some(update) => update
+                .proposed_protocol_parameter_updates
+                .iter()
+                .map(|(x, _)| x.as_slice())
+                .collect(),
+            None => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+
+    let signers: HashSet<_> = metx
+        .vkey_witnesses()
+        .iter()
+        .map(|x| Hasher::<224>::hash(&x.vkey))
+        .collect();
+
+    let authorized: HashSet<_> = proposers
+        .into_iter()
+        .filter_map(|x| <[u8; 28]>::try_from(x).ok().map(Hash::new))
+        .filter(|x| {
+            genesis
+                .delegates
+                .get(x)
+                .is_some_and(|delegate| signers.contains(delegate))
+        })
+        .collect();
+
+    check_quorum(authorized.len(), genesis.quorum)
+}
+
+/// Checks that the update payload of a Byron block, if it carries a proposal
+/// or votes, is backed by a quorum of genesis delegates
+///
+/// The issuer of the proposal and every voter in favour count once each,
+/// identified by the Blake2b-224 hash of their public key, which has to be one
+/// of the delegate key hashes of the environment.
+pub fn check_byron_update_quorum(
+    block: &byron::MintedBlock,
+    genesis: &GenesisDelegates,
+) -> ValidationResult {
+    let update = &block.body.upd_payload;
+
+    let issuer = update.proposal.iter().filter_map(|x| x.from.as_ref());
+    let voters = update.votes.iter().filter(|x| x.vote).map(|x| &x.voter);
+
+    let keys: Vec<_> = issuer.chain(voters).collect();
+
+    if keys.is_empty() {
+        return Ok(());
+    }
+
+    let delegates: HashSet<_> = genesis.delegates.values().collect();
+
+    let authorized: HashSet<_> = keys
+        .into_iter()
+        .map(|x| Hasher::<224>::hash(x))
+        .filter(|x| delegates.contains(x))
+        .collect();
+
+    check_quorum(authorized.len(), genesis.quorum)
+}
+
+/// Prefix of the messages signed by the issuers of Byron update proposals
+const SIGN_TAG_PROPOSAL: u8 = 0x04;
+
+/// Prefix of the messages signed by the voters on Byron update proposals
+const SIGN_TAG_VOTE: u8 = 0x06;
+
+/// Id of a Byron update proposal, the hash its votes refer to it by
+pub fn byron_proposal_id(proposal: &byron::UpProp) -> Hash<32> {
+    Hasher::<256>::hash_cbor(proposal)
+}
+
+/// Checks that a Byron update proposal is complete, issued by a genesis
+/// delegate and signed by it
+///
+/// The issuer signs, under the protocol magic, the proposal minus the issuer
+/// and signature fields. A missing field, a key or signature of the wrong
+/// size and an issuer other than the delegates of the environment fail with
+/// [ValidationError::InvalidByronUpdatePayload], while a signature not
+/// matching the key fails with [ValidationError::WrongSignature].
+pub fn validate_byron_update_proposal(
+    proposal: &byron::UpProp,
+    genesis: &GenesisDelegates,
+    protocol_magic: u32,
+) -> ValidationResult {
+    let invalid = |reason| ValidationError::InvalidByronUpdatePayload { reason };
+
+    let signed = (
+        proposal.block_version.as_ref(),
+        proposal.block_version_mod.as_ref(),
+        proposal.software_version.as_ref(),
+    );
+
+    let (block_version, block_version_mod, software_version) = match signed {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => return Err(invalid("proposal missing a version")),
+    };
+
+    let (issuer, signature) = match (&proposal.from, &proposal.signature) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Err(invalid("proposal missing its issuer or signature")),
+    };
+
+    if !genesis
+        .delegates
+        .values()
+        .any(|x| *x == Hasher::<224>::hash(issuer))
+    {
+        return Err(invalid("proposal not issued by a genesis delegate"));
+    }
+
+    let body = (
+        block_version,
+        block_version_mod,
+        software_version,
+        &proposal.data,
+        &proposal.attributes,
+    );
+
+    verify_byron_signature(issuer, signature, SIGN_TAG_PROPOSAL, protocol_magic, body)
+}
+
+/// Checks that a vote on a Byron update proposal is signed by the voter and
+/// refers to a proposal still open to votes
+///
+/// The voter signs, under the protocol magic, the id of the proposal together
+/// with the decision. Votes on a proposal the ledger state doesn't know of
+/// fail with [ValidationError::InvalidByronUpdatePayload].
+pub fn validate_byron_update_vote(
+    vote: &byron::UpVote,
+    state: &impl LedgerState,
+    protocol_magic: u32,
+) -> ValidationResult {
+    if !state.is_update_proposal_active(&vote.proposal_id) {
+        return Err(ValidationError::InvalidByronUpdatePayload {
+            reason: "vote on a proposal not open to votes",
+        });
+    }
+
+    let body = (&vote.proposal_id, vote.vote);
+
+    verify_byron_signature(
+        &vote.voter,
+        &vote.signature,
+        SIGN_TAG_VOTE,
+        protocol_magic,
+        body,
+    )
+}
+
+/// Verifies the signature of a Byron extended public key over
+/// `tag ‖ cbor(protocol_magic) ‖ cbor(body)`
+fn verify_byron_signature(
+    xpub: &[u8],
+    signature: &[u8],
+    tag: u8,
+    protocol_magic: u32,
+    body: impl minicbor::Encode<()>,
+) -> ValidationResult {
+    let (public_key, signature) = match (xpub.get(..32), xpub.len(), signature.len()) {
+        (Some(key), 64, 64) => (PublicKey::try_from(key), Signature::try_from(signature)),
+        _ => {
+            return Err(ValidationError::InvalidByronUpdatePayload {
+                reason: "key or signature of the wrong size",
+            })
+        }
+    };
+
+    let message = byron_signed_data(tag, protocol_magic, body);
+
+    match (public_key, signature) {
+        (Ok(public_key), Ok(signature)) if public_key.verify(&message, &signature) => Ok(()),
+        _ => Err(ValidationError::WrongSignature(Hasher::<224>::hash(xpub))),
+    }
+}
+
+fn check_quorum(have: usize, need: usize) -> ValidationResult {
+    match have >= need {
+        true => Ok(()),
+        false => Err(ValidationError::UpdateQuorumNotMet { have, need }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use std::collections::BTreeMap;
+
+    use pallas_addresses::Pointer;
+    use pallas_codec::utils::{EmptyMap, KeyValuePairs};
+    use pallas_crypto::key::ed25519::SecretKey;
+    use pallas_primitives::alonzo::{ProtocolParamUpdate, StakeCredential, Update};
+    use pallas_traverse::Era;
+
+    const MAGIC: u32 = 764824073;
+
+    struct Proposals(HashSet<Hash<32>>);
+
+    impl LedgerState for Proposals {
+        fn is_pool_registered(&self, _: &Hash<28>) -> bool {
+            false
+        }
+
+        fn register_pool(&mut self, _: Hash<28>) {}
+
+        fn resolve_pointer(&self, _: &Pointer) -> Option<StakeCredential> {
+            None
+        }
+
+        fn is_update_proposal_active(&self, proposal: &Hash<32>) -> bool {
+            self.0.contains(proposal)
+        }
+
+        fn register_update_proposal(&mut self, proposal: Hash<32>) {
+            self.0.insert(proposal);
+        }
+    }
+
+    fn byron_sign(key: &SecretKey, tag: u8, body: impl minicbor::Encode<()>) -> Vec<u8> {
+        let message = [
+            vec![tag],
+            minicbor::to_vec(MAGIC).unwrap(),
+            minicbor::to_vec(body).unwrap(),
+        ]
+        .concat();
+
+        key.sign(message).as_ref().to_vec()
+    }
+
+    fn byron_proposal(key: &SecretKey) -> byron::UpProp {
+        let mut proposal = byron::UpProp {
+            block_version: Some((0, 1, 0)),
+            // no parameter modified at all
+            block_version_mod: Some(
+                minicbor::decode(&[[0x8e].as_slice(), &[0x80; 14]].concat()).unwrap(),
+            ),
+            software_version: Some(("cardano-sl".into(), 1)),
+            data: KeyValuePairs::from(vec![]),
+            attributes: Some(EmptyMap),
+            from: Some(byron_xpub(key).into()),
+            signature: None,
+        };
+
+        let body = (
+            proposal.block_version.as_ref(),
+            proposal.block_version_mod.as_ref(),
+            proposal.software_version.as_ref(),
+            &proposal.data,
+            &proposal.attributes,
+        );
+        proposal.signature = Some(byron_sign(key, SIGN_TAG_PROPOSAL, body).into());
+
+        proposal
+    }
+
+    fn fee_update() -> ProtocolParamUpdate {
+        ProtocolParamUpdate {
+            minfee_a: Some(44),
+            minfee_b: None,
+            max_block_body_size: None,
+            max_transaction_size: None,
+            max_block_header_size: None,
+            key_deposit: None,
+            pool_deposit: None,
+            maximum_epoch: None,
+            desired_number_of_stake_pools: None,
+            pool_pledge_influence: None,
+            expansion_rate: None,
+            treasury_growth_rate: None,
+            decentralization_constant: None,
+            extra_entropy: None,
+            protocol_version: None,
+            min_pool_cost: None,
+            ada_per_utxo_byte: None,
+            cost_models_for_script_languages: None,
+            execution_costs: None,
+            max_tx_ex_units: None,
+            max_block_ex_units: None,
+            max_value_size: None,
+            collateral_percentage: None,
+            max_collateral_inputs: None,
+        }
+    }
+
+    #[test]
+    fn shelley_quorum() {
+        let genesis = GenesisDelegates {
+            delegates: BTreeMap::from([
+                (Hash::new([1; 28]), key_hash(&secret_key(1))),
+                (Hash::new([2; 28]), key_hash(&secret_key(2))),
+                (Hash::new([3; 28]), key_hash(&secret_key(3))),
+            ]),
+            quorum: 2,
+        };
+
+        let proposal = |seed| (vec![seed; 28].into(), fee_update());
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.update = Some(Update {
+            proposed_protocol_parameter_updates: KeyValuePairs::from(vec![
+                proposal(1),
+                proposal(2),
+                proposal(4),
+            ]),
+            epoch: 200,
+        });
+
+        let check = |keys: &[u8]| {
+            let keys: Vec<_> = keys.iter().map(|x| secret_key(*x)).collect();
+            let bytes = alonzo_tx(body.clone(), &keys, vec![]);
+            let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+            check_update_quorum(&metx, &genesis)
+        };
+
+        assert_eq!(check(&[1, 2]), Ok(()));
+
+        // the delegate of the third genesis key didn't propose anything, and
+        // the fourth one isn't a genesis key at all
+        assert_eq!(
+            check(&[1, 3, 4]),
+            Err(ValidationError::UpdateQuorumNotMet { have: 1, need: 2 })
+        );
+    }
+
+    #[test]
+    fn byron_update_payloads() {
+        let genesis = GenesisDelegates {
+            delegates: BTreeMap::from([(
+                Hash::new([1; 28]),
+                Hasher::<224>::hash(&byron_xpub(&secret_key(1))),
+            )]),
+            quorum: 1,
+        };
+
+        let proposal = byron_proposal(&secret_key(1));
+        assert_eq!(
+            validate_byron_update_proposal(&proposal, &genesis, MAGIC),
+            Ok(())
+        );
+        assert_eq!(
+            validate_byron_update_proposal(&proposal, &genesis, 1),
+            Err(ValidationError::WrongSignature(Hasher::<224>::hash(
+                &byron_xpub(&secret_key(1))
+            )))
+        );
+        assert!(matches!(
+            validate_byron_update_proposal(&byron_proposal(&secret_key(2)), &genesis, MAGIC),
+            Err(ValidationError::InvalidByronUpdatePayload { .. })
+        ));
+
+        let id = byron_proposal_id(&proposal);
+        let vote = byron::UpVote {
+            voter: byron_xpub(&secret_key(1)).into(),
+            proposal_id: id,
+            vote: true,
+            signature: byron_sign(&secret_key(1), SIGN_TAG_VOTE, (id, true)).into(),
+        };
+
+        let mut state = Proposals(HashSet::new());
+        assert!(matches!(
+            validate_byron_update_vote(&vote, &state, MAGIC),
+            Err(ValidationError::InvalidByronUpdatePayload { .. })
+        ));
+
+        state.register_update_proposal(id);
+        assert_eq!(validate_byron_update_vote(&vote, &state, MAGIC), Ok(()));
+
+        let flipped = byron::UpVote {
+            vote: false,
+            ..vote
+        };
+        assert!(matches!(
+            validate_byron_update_vote(&flipped, &state, MAGIC),
+            Err(ValidationError::WrongSignature(_))
+        ));
+    }
+}
@@ -0,0 +1,299 @@
+//! Base types used across the validation of every era
+
+use std::{borrow::Cow, collections::HashMap};
+
+use pallas_addresses::{byron::AddrType, Pointer};
+use pallas_codec::utils::CborWrap;
+use pallas_crypto::hash::Hash;
+use pallas_primitives::{alonzo::RedeemerTag, byron::TxIn};
+use pallas_traverse::{Era, MultiEraInput, MultiEraOutput, MultiEraTx, OutputRef};
+use thiserror::Error;
+
+/// Key of an entry of the UTxO set: the id of the tx which produced the output
+/// and the index of the output within it
+pub type UtxoKey = OutputRef;
+
+/// The set of unspent outputs a transaction is validated against
+pub type UTxOs<'b> = HashMap<UtxoKey, MultiEraOutput<'b>>;
+
+/// Read access to a UTxO set, wherever it is kept
+///
+/// The per-era validators and the checks they run only ever look outputs up,
+/// so they take any store, be it the in-memory [UTxOs] or one backed by disk.
+/// Whatever changes the set, like [apply_tx](crate::apply::apply_tx), block
+/// validation or a [Ledger](crate::Ledger), still works on [UTxOs], and so do
+/// the rules of a [Validator](crate::Validator), being trait objects which
+/// can't be generic.
+pub trait UtxoStore<'b> {
+    /// The unspent output under the key, if any
+    ///
+    /// A store decoding outputs on demand hands them out owned, one holding
+    /// them decoded already lends them.
+    fn resolve(&self, output_ref: &UtxoKey) -> Option<Cow<'_, MultiEraOutput<'b>>>;
+}
+
+impl<'b> UtxoStore<'b> for UTxOs<'b> {
+    fn resolve(&self, output_ref: &UtxoKey) -> Option<Cow<'_, MultiEraOutput<'b>>> {
+        self.get(output_ref).map(Cow::Borrowed)
+    }
+}
+
+/// Normalizes a Byron tx input into the key of the output it spends
+///
+/// Both inserting into and looking up the UTxO set should go through here.
+/// Only the standard `[0, [txid, index]]` variant refers to an output, so any
+/// other one yields `None`.
+pub fn utxo_key(input: &TxIn) -> Option<UtxoKey> {
+    match input {
+        TxIn::Variant0(CborWrap((tx, index))) => Some(UtxoKey::new(*tx, *index as u64)),
+        TxIn::Other(..) => None,
+    }
+}
+
+/// Key of the output spent by an input of any era, if it refers to one
+///
+/// Unlike [MultiEraInput::output_ref], this doesn't panic on the Byron input
+/// variants other than the standard one, yielding `None` as [utxo_key] does.
+pub fn input_key(input: &MultiEraInput) -> Option<UtxoKey> {
+    match input {
+        MultiEraInput::Byron(x) => utxo_key(x),
+        _ => Some(input.output_ref()),
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    #[error("field {field} not allowed in the {era} era")]
+    FieldNotAllowedInEra { field: &'static str, era: Era },
+    #[error("transaction inputs set is empty")]
+    TxInsEmpty,
+    #[error("transaction outputs set is empty")]
+    TxOutsEmpty,
+    #[error("input {0} not in the UTxO set")]
+    InputNotInUTxO(OutputRef),
+    #[error("input {0} spent more than once")]
+    DuplicateInput(OutputRef),
+    #[error("input of unsupported variant {0}")]
+    UnsupportedTxInVariant(u8),
+    #[error("input {0:?} resolves to an amount beyond the total supply")]
+    ResolvedInputOutOfRange(TxIn),
+    #[error("{field} encoded with an unexpected array length encoding")]
+    UnexpectedArrayEncoding { field: &'static str },
+    #[error("input {0} depends on a rejected or unresolvable transaction")]
+    UnresolvableDependency(OutputRef),
+    #[error("output {0} already in the UTxO set")]
+    OutputCollision(OutputRef),
+    #[error("no witness for script {0}")]
+    ScriptWitnessMissing(Hash<28>),
+    #[error("key {0} required by a native script did not sign")]
+    ScriptKeyNotSigned(Hash<28>),
+    #[error("native script satisfied {satisfied} of the {required} required scripts")]
+    ScriptThresholdNotMet { required: u32, satisfied: u32 },
+    #[error("native script timelock not met by the validity interval")]
+    ScriptTimelockNotMet,
+    #[error("validity interval starting at slot {start} past its end at slot {end}")]
+    InvalidValidityInterval { start: u64, end: u64 },
+    #[error("transaction expired at slot {1}, current slot is {0}")]
+    TxExpired(u64, u64),
+    #[error("transaction valid from slot {1}, current slot is {0}")]
+    TxNotYetValid(u64, u64),
+    #[error("fees {fee} below minimum {min_fee}")]
+    FeesBelowMin { fee: u64, min_fee: u64 },
+    #[error("minimum fee overflows")]
+    FeeOverflow,
+    #[error("transaction size {size} above maximum {max}")]
+    MaxTxSizeExceeded { size: u64, max: u64 },
+    #[error("reference scripts size {size} above maximum {max}")]
+    RefScriptsTooLarge { size: u64, max: u64 },
+    #[error("block body size {size} above maximum {max}")]
+    MaxBlockBodySizeExceeded { size: u64, max: u64 },
+    #[error("block execution units of {used_mem} mem and {used_steps} steps above maximum of {max_mem} mem and {max_steps} steps")]
+    BlockExUnitsExceeded {
+        used_mem: u64,
+        used_steps: u64,
+        max_mem: u64,
+        max_steps: u64,
+    },
+    #[error("{count} outputs above maximum {max}")]
+    TooManyOutputs { count: u32, max: u32 },
+    #[error("value not conserved: {0} consumed against {1} produced")]
+    ValueNotConserved(u64, u64),
+    #[error("certificates take a net deposit of {expected} while the transaction leaves {actual}")]
+    DepositAccountingMismatch { expected: i128, actual: i128 },
+    #[error("outputs of {outputs} exceed inputs of {inputs}")]
+    OutputsExceedInputs { inputs: u64, outputs: u64 },
+    #[error("value overflow")]
+    ValueOverflow,
+    #[error("native assets not conserved")]
+    AssetsNotConserved,
+    #[error("mint field not in canonical form")]
+    NonCanonicalMint,
+    #[error("wrong signature by key {0}")]
+    WrongSignature(Hash<28>),
+    #[error("no witness for input {0}")]
+    MissingWitness(OutputRef),
+    #[error("no vkey witness for required key {0}")]
+    MissingVKeyWitness(Hash<28>),
+    #[error("witness key of input {0} does not match its address")]
+    WitnessKeyAddressMismatch(OutputRef),
+    #[error("witness of input {0} of the wrong type for its address")]
+    WitnessTypeMismatch(OutputRef),
+    #[error("witness of input {0} of the wrong kind for the era of its address")]
+    InputEraWitnessMismatch(OutputRef),
+    #[error("{witnesses} witnesses for {inputs} inputs")]
+    TooManyWitnesses { inputs: usize, witnesses: usize },
+    #[error("output {0} without lovelace")]
+    OutputWithoutLovelace(usize),
+    #[error("output of {0} below minimum {1}")]
+    OutputTooSmall(u64, u64),
+    #[error("address of output {0} malformed")]
+    OutputAddressMalformed(usize),
+    #[error("output {0} uses a feature from a later era")]
+    OutputFeatureFromFutureEra(usize),
+    #[error("address of output {0} fails its CRC")]
+    AddressCrcMismatch(usize),
+    #[error("unknown address attributes of output {index} take {size} bytes")]
+    OutputAddressAttrsTooLarge { index: usize, size: usize },
+    #[error("output {index} locked by an address of type {addr_type:?}")]
+    UnexpectedByronAddressType { index: usize, addr_type: AddrType },
+    #[error("output {0} locked by a key carries a datum hash")]
+    DatumOnKeyAddress(usize),
+    #[error("datum {0} missing")]
+    DatumMissing(Hash<32>),
+    #[error("datum {0} not required by any input")]
+    OrphanDatum(Hash<32>),
+    #[error("{count} collateral inputs above maximum {max}")]
+    TooManyCollateralInputs { count: u32, max: u32 },
+    #[error("transaction failing phase-2 validation without collateral")]
+    NoCollateralForFailedTx,
+    #[error("collateral input {0} locked by a script")]
+    CollateralIsScriptLocked(OutputRef),
+    #[error("collateral {provided} below required {required}")]
+    CollateralInsufficient { provided: u64, required: u64 },
+    #[error("collateral return of {actual} below minimum {min}")]
+    CollateralReturnTooSmall { min: u64, actual: u64 },
+    #[error("inline datum of output {0} malformed")]
+    MalformedInlineDatum(usize),
+    #[error("PlutusV1 script alongside {feature}")]
+    PlutusV1FeatureConflict { feature: &'static str },
+    #[error("redeemer {tag:?} {index} either missing or not required by any script")]
+    RedeemerPurposeMismatch { tag: RedeemerTag, index: u32 },
+    #[error("redeemer {tag:?} {index} given more than once")]
+    DuplicateRedeemer { tag: RedeemerTag, index: u32 },
+    #[error("script data hash mismatch, computed {computed:?}")]
+    ScriptDataHashMismatch { computed: Option<Hash<32>> },
+    #[error("metadata string of {len} bytes under label {label} too long")]
+    MetadataStringTooLong { label: u64, len: usize },
+    #[error("pointer {0:?} does not point at a stake registration")]
+    PointerAddressUnresolved(Pointer),
+    #[error("delegation to unregistered pool {target}")]
+    DelegationTargetNotRegistered { target: Hash<28> },
+    #[error("no witness for certificate script {0}")]
+    CertificateScriptMissing(Hash<28>),
+    #[error("certificates {index_a} and {index_b} contradict each other")]
+    ContradictoryCertificates { index_a: usize, index_b: usize },
+    #[error("relay {index} of pool {pool} invalid")]
+    PoolRelayInvalid { pool: Hash<28>, index: usize },
+    #[error("metadata url of {len} bytes of pool {pool} too long")]
+    PoolMetadataUrlTooLong { pool: Hash<28>, len: usize },
+    #[error("withdrawal from account {account:02x?} not authorized")]
+    WithdrawalNotAuthorized { account: Vec<u8> },
+    #[error("invalid update payload: {reason}")]
+    InvalidByronUpdatePayload { reason: &'static str },
+    #[error("update authorized by {have} delegates out of the {need} required")]
+    UpdateQuorumNotMet { have: usize, need: usize },
+    #[error("decoding failed: {0}")]
+    Decode(String),
+    #[error("transaction of the {0} era against params of another era")]
+    EraMismatch(Era),
+    #[error("protocol version {major}.{minor} unsupported")]
+    ProtocolVersionUnsupported { major: u64, minor: u64 },
+}
+
+/// Conditions which don't make a transaction invalid but usually point to a
+/// poorly constructed one
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationWarning {
+    FeeFarAboveMin { fee: u64, min_fee: u64 },
+    OrphanDatum(Hash<32>),
+}
+
+pub type ValidationResult = Result<(), ValidationError>;
+
+/// Computes the id of a transaction of any era
+///
+/// The id is the blake2b-256 hash of the tx structure for Byron and of the tx
+/// body from Shelley onwards, in both cases over the bytes the transaction was
+/// decoded from.
+pub fn tx_hash(metx: &MultiEraTx) -> Hash<32> {
+    metx.hash()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_codec::minicbor;
+    use pallas_crypto::hash::Hasher;
+    use pallas_primitives::byron::MintedTxPayload;
+
+    #[test]
+    fn error_messages() {
+        assert_eq!(
+            ValidationError::TxInsEmpty.to_string(),
+            "transaction inputs set is empty"
+        );
+        assert_eq!(
+            ValidationError::FeesBelowMin {
+                fee: 42,
+                min_fee: 909
+            }
+            .to_string(),
+            "fees 42 below minimum 909"
+        );
+        assert_eq!(
+            ValidationError::MaxTxSizeExceeded {
+                size: 16385,
+                max: 16384
+            }
+            .to_string(),
+            "transaction size 16385 above maximum 16384"
+        );
+
+        let error: Box<dyn std::error::Error> = Box::new(ValidationError::ValueOverflow);
+        assert_eq!(error.to_string(), "value overflow");
+    }
+
+    #[test]
+    fn byron_utxo_keys() {
+        assert_eq!(
+            utxo_key(&byron_input(1, 3)),
+            Some(UtxoKey::new(Hash::new([1; 32]), 3))
+        );
+        assert_eq!(utxo_key(&TxIn::Other(1, vec![0x80].into())), None);
+
+        let input = TxIn::Other(1, vec![0x80].into());
+        assert_eq!(input_key(&MultiEraInput::from_byron(&input)), None);
+    }
+
+    #[test]
+    fn hash_across_eras() {
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body.clone(), &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(tx_hash(&metx), Hasher::<256>::hash_cbor(&body));
+
+        let bytes = byron_tx(vec![byron_input(1, 0)], vec![byron_output(&[], 1)]);
+        let mtxp: MintedTxPayload = minicbor::decode(&bytes).unwrap();
+        let metx = MultiEraTx::from_byron(&mtxp);
+
+        assert_eq!(
+            tx_hash(&metx),
+            Hasher::<256>::hash(mtxp.transaction.raw_cbor())
+        );
+    }
+}
@@ -0,0 +1,937 @@
+//! A configurable set of validation rules
+
+use std::rc::Rc;
+
+use pallas_traverse::{Era, MultiEraOutput, MultiEraTx, OutputRef};
+
+use crate::{
+    alonzo::{
+        check_collateral_addresses, check_collateral_amount, check_collateral_inputs,
+        check_datums_on_key_addresses, check_datums_supplied, check_failed_tx_collateral,
+        check_orphan_datums, check_redeemer_purposes,
+    },
+    babbage::{
+        check_collateral_return, check_inline_datums, check_plutus_v1_features,
+        compute_min_lovelace,
+    },
+    byron::{check_byron_structure, validate_byron_tx},
+    cache::ValidatorCache,
+    certs::{
+        check_certificate_scripts, check_contradictory_certificates, check_pool_registrations,
+    },
+    environment::{Environment, ProtocolParams},
+    fee::{check_fee, check_tx_size},
+    metadata::check_aux_data,
+    mint::check_mint,
+    native_script::check_native_scripts,
+    script_data::check_script_data_hash,
+    shelley_ma::{check_min_fee, check_preservation_of_value},
+    structure::{
+        check_current_slot, check_era_fields, check_inputs_not_empty, check_inputs_unique,
+        check_output_addresses, check_output_features, check_protocol_version,
+        check_validity_interval,
+    },
+    update::check_update_quorum,
+    utils::input_key,
+    value::{check_output_values, check_outputs_within_inputs},
+    witnesses::{
+        check_input_witness_eras, check_vkey_witnesses, check_withdrawals,
+        verify_tx_witnesses_with, Ed25519Verifier, SignatureVerifier,
+    },
+    UTxOs, ValidationError, ValidationResult,
+};
+
+/// A single validation rule, run against a tx, its resolved inputs and the
+/// environment
+pub type Rule = Box<dyn Fn(&MultiEraTx, &UTxOs, &Environment) -> ValidationResult>;
+
+/// Names of the built-in rules which don't depend on the UTxO set, nor on the
+/// fees and balance of a tx
+const STRUCTURAL_RULES: [&str; 17] = [
+    "era_params",
+    "protocol_version",
+    "era_fields",
+    "output_features",
+    "inputs_not_empty",
+    "inputs_unique",
+    "output_addresses",
+    "validity_interval",
+    "max_tx_size",
+    "output_values",
+    "aux_data",
+    "mint",
+    "withdrawals",
+    "pool_registrations",
+    "contradictory_certificates",
+    "collateral_inputs",
+    "inline_datums",
+];
+
+/// Runs a tx through an ordered list of named rules
+///
+/// The default validator holds the built-in rules, which together perform the
+/// same checks as the per-era validators, plus the ones only enabled by the
+/// [strictness](Environment::strictness) of the environment. Each of them
+/// only applies to the eras it's meaningful for, so a rule set can be trimmed
+/// or extended by name without caring about the era of the txs it will see.
+pub struct Validator {
+    rules: Vec<(&'static str, Rule)>,
+}
+
+impl Validator {
+    /// A validator with no rules at all, accepting every tx
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    /// Appends a rule, run after the ones already in the set
+    pub fn with_rule(
+        mut self,
+        name: &'static str,
+        rule: impl Fn(&MultiEraTx, &UTxOs, &Environment) -> ValidationResult + 'static,
+    ) -> Self {
+        self.rules.push((name, Box::new(rule)));
+        self
+    }
+
+    /// Removes every rule with the given name
+    pub fn without_rule(mut self, name: &str) -> Self {
+        self.rules.retain(|(x, _)| *x != name);
+        self
+    }
+
+    /// Has the `byron` and `signatures` rules verify signatures with the given
+    /// backend rather than with [Ed25519Verifier]
+    ///
+    /// The rules keep their place in the set; any of them which was removed
+    /// stays so.
+    pub fn with_verifier(mut self, verifier: impl SignatureVerifier + 'static) -> Self {
+        let verifier = Rc::new(verifier);
+
+        for (name, rule) in self.rules.iter_mut() {
+            match *name {
+                "byron" => *rule = byron_rule(verifier.clone()),
+                "signatures" => *rule = signatures_rule(verifier.clone()),
+                _ => (),
+            }
+        }
+
+        self
+    }
+
+    /// Names of the rules in the set, in the order they run
+    pub fn rule_names(&self) -> Vec<&'static str> {
+        self.rules.iter().map(|(x, _)| *x).collect()
+    }
+
+    /// Runs the rules in order, stopping at the first one failing
+    ///
+    /// The UTxO set is only read, never updated, whatever the outcome; see
+    /// [apply_tx](crate::apply::apply_tx) for applying an accepted tx.
+    pub fn validate(
+        &self,
+        metx: &MultiEraTx,
+        utxos: &UTxOs,
+        env: &Environment,
+    ) -> ValidationResult {
+        self.rules
+            .iter()
+            .try_for_each(|(_, rule)| rule(metx, utxos, env))
+    }
+
+    /// Runs the rules in order like [validate](Self::validate), resolving
+    /// the inputs of the tx through the given cache
+    ///
+    /// The rules only see the outputs the tx spends or references. See
+    /// [ValidatorCache] for when the cache must be invalidated.
+    pub fn validate_cached<'b>(
+        &self,
+        metx: &MultiEraTx,
+        utxos: &UTxOs<'b>,
+        env: &Environment,
+        cache: &mut ValidatorCache<'b>,
+    ) -> ValidationResult {
+        let resolved = cache.resolve(metx, utxos);
+
+        self.validate(metx, &resolved, env)
+    }
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        use ProtocolParams::*;
+
+        Self::empty()
+            .with_rule("era_params", |metx, _, env| {
+                match (metx.era(), &env.prot_params) {
+                    (Era::Byron, Byron(_))
+                    | (Era::Shelley | Era::Allegra | Era::Mary, Shelley(_))
+                    | (Era::Alonzo, Alonzo(_))
+                    | (Era::Babbage, Babbage(_)) => Ok(()),
+                    (era, _) => Err(ValidationError::EraMismatch(era)),
+                }
+            })
+            .with_rule("protocol_version", |metx, _, env| {
+                check_protocol_version(metx.era(), env.protocol_version)
+            })
+            .with_rule("byron", byron_rule(Rc::new(Ed25519Verifier)))
+            .with_rule("era_fields", |metx, _, env| match &env.prot_params {
+                Shelley(_) => check_era_fields(metx),
+                _ => Ok(()),
+            })
+            .with_rule("output_features", |metx, _, env| match &env.prot_params {
+                Shelley(_) => check_output_features(metx),
+                _ => Ok(()),
+            })
+            .with_rule("inputs_not_empty", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_inputs_not_empty(metx),
+            })
+            .with_rule("inputs_unique", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_inputs_unique(metx),
+            })
+            .with_rule("output_addresses", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_output_addresses(metx),
+            })
+            .with_rule("validity_interval", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_validity_interval(metx),
+            })
+            .with_rule("current_slot", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_current_slot(metx, env.slot),
+            })
+            .with_rule("output_values", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                Babbage(prot_pps) => {
+                    check_output_values(metx.outputs(), |x| compute_min_lovelace(x, prot_pps))
+                }
+                Shelley(prot_pps) => {
+                    check_output_values(metx.outputs(), |_| prot_pps.min_utxo_value)
+                }
+                _ => check_output_values(metx.outputs(), |_| 0),
+            })
+            .with_rule("aux_data", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_aux_data(metx),
+            })
+            .with_rule("mint", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_mint(metx),
+            })
+            .with_rule("native_scripts", |metx, utxos, env| {
+                match &env.prot_params {
+                    Shelley(_) | Alonzo(_) | Babbage(_) => check_native_scripts(metx, utxos),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("input_witness_eras", |metx, utxos, env| {
+                match &env.prot_params {
+                    Byron(_) => Ok(()),
+                    _ => check_input_witness_eras(metx, utxos),
+                }
+            })
+            .with_rule("signatures", signatures_rule(Rc::new(Ed25519Verifier)))
+            .with_rule("vkey_witnesses", |metx, utxos, env| {
+                match &env.prot_params {
+                    Byron(_) => Ok(()),
+                    _ => check_vkey_witnesses(metx, utxos),
+                }
+            })
+            .with_rule("withdrawals", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_withdrawals(metx),
+            })
+            .with_rule("certificate_scripts", |metx, utxos, env| {
+                match &env.prot_params {
+                    Byron(_) => Ok(()),
+                    _ => check_certificate_scripts(metx, utxos),
+                }
+            })
+            .with_rule("pool_registrations", |metx, _, env| {
+                match &env.prot_params {
+                    Byron(_) => Ok(()),
+                    _ => check_pool_registrations(metx),
+                }
+            })
+            .with_rule("contradictory_certificates", |metx, _, env| {
+                match &env.prot_params {
+                    Byron(_) => Ok(()),
+                    _ => check_contradictory_certificates(metx),
+                }
+            })
+            .with_rule("update_quorum", |metx, _, env| match &env.prot_params {
+                Byron(_) => Ok(()),
+                _ => check_update_quorum(metx, &env.genesis_delegates),
+            })
+            .with_rule("max_tx_size", |metx, _, env| match &env.prot_params {
+                Shelley(x) => check_tx_size(metx, x.max_tx_size),
+                Alonzo(x) => check_tx_size(metx, x.max_tx_size),
+                Babbage(x) => check_tx_size(metx, x.max_tx_size),
+                _ => Ok(()),
+            })
+            .with_rule("min_fee", |metx, _, env| match &env.prot_params {
+                Shelley(prot_pps) => check_min_fee(metx, prot_pps),
+                Alonzo(prot_pps) => check_fee(metx, prot_pps),
+                Babbage(prot_pps) => check_fee(metx, prot_pps),
+                _ => Ok(()),
+            })
+            .with_rule("outputs_within_inputs", |metx, utxos, env| {
+                match &env.prot_params {
+                    Shelley(x) => check_outputs_within_inputs(metx, utxos, x.key_deposit),
+                    Alonzo(x) => check_outputs_within_inputs(metx, utxos, x.key_deposit),
+                    Babbage(x) => check_outputs_within_inputs(metx, utxos, x.key_deposit),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("preservation_of_value", |metx, utxos, env| {
+                match env.prot_params.deposits() {
+                    Some((key_deposit, pool_deposit)) => {
+                        check_preservation_of_value(metx, utxos, key_deposit, pool_deposit, |_| {
+                            false
+                        })
+                    }
+                    None => Ok(()),
+                }
+            })
+            .with_rule("failed_tx_collateral", |metx, _, env| {
+                match &env.prot_params {
+                    Alonzo(_) | Babbage(_) => check_failed_tx_collateral(metx),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("collateral_inputs", |metx, _, env| match &env.prot_params {
+                Alonzo(x) => check_collateral_inputs(metx, x.max_collateral_inputs),
+                Babbage(x) => check_collateral_inputs(metx, x.max_collateral_inputs),
+                _ => Ok(()),
+            })
+            .with_rule("collateral_addresses", |metx, utxos, env| {
+                match &env.prot_params {
+                    Alonzo(_) | Babbage(_) => check_collateral_addresses(metx, utxos),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("collateral_amount", |metx, utxos, env| {
+                match &env.prot_params {
+                    Alonzo(x) => check_collateral_amount(metx, utxos, x.collateral_percentage),
+                    Babbage(x) => check_collateral_amount(metx, utxos, x.collateral_percentage),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("collateral_return", |metx, _, env| match &env.prot_params {
+                Babbage(prot_pps) => check_collateral_return(metx, prot_pps),
+                _ => Ok(()),
+            })
+            .with_rule("plutus_v1_features", |metx, utxos, env| {
+                match &env.prot_params {
+                    Babbage(_) => check_plutus_v1_features(metx, utxos),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("inline_datums", |metx, _, env| match &env.prot_params {
+                Babbage(_) => check_inline_datums(metx),
+                _ => Ok(()),
+            })
+            .with_rule("datums_supplied", |metx, utxos, env| {
+                match &env.prot_params {
+                    Alonzo(_) | Babbage(_) => check_datums_supplied(metx, utxos),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("datums_on_key_addresses", |metx, _, env| {
+                match &env.prot_params {
+                    Alonzo(_) | Babbage(_) => check_datums_on_key_addresses(metx, env.strictness),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("orphan_datums", |metx, utxos, env| match &env.prot_params {
+                Alonzo(_) | Babbage(_) => check_orphan_datums(metx, utxos, env.strictness),
+                _ => Ok(()),
+            })
+            .with_rule("redeemer_purposes", |metx, utxos, env| {
+                match &env.prot_params {
+                    Alonzo(_) | Babbage(_) => check_redeemer_purposes(metx, utxos),
+                    _ => Ok(()),
+                }
+            })
+            .with_rule("script_data_hash", |metx, utxos, env| {
+                match &env.prot_params {
+                    Alonzo(x) => check_script_data_hash(metx, utxos, &x.cost_models),
+                    Babbage(x) => check_script_data_hash(metx, utxos, &x.cost_models),
+                    _ => Ok(()),
+                }
+            })
+    }
+}
+
+/// Validates Byron txs, verifying their witnesses with the given backend
+fn byron_rule(verifier: Rc<impl SignatureVerifier + 'static>) -> Rule {
+    Box::new(
+        move |metx, utxos, env| match (metx.as_byron(), &env.prot_params) {
+            (Some(mtxp), ProtocolParams::Byron(prot_pps)) => {
+                validate_byron_tx(mtxp, utxos, prot_pps, env.strictness, &*verifier)
+            }
+            _ => Ok(()),
+        },
+    )
+}
+
+/// Verifies the vkey and bootstrap witnesses of non-Byron txs with the given
+/// backend
+fn signatures_rule(verifier: Rc<impl SignatureVerifier + 'static>) -> Rule {
+    Box::new(move |metx, _, env| match &env.prot_params {
+        ProtocolParams::Byron(_) => Ok(()),
+        _ => verify_tx_witnesses_with(metx, &*verifier),
+    })
+}
+
+/// Validates just the structure of a tx, without resolving any of its inputs
+///
+/// Only the built-in rules which need neither the UTxO set nor the balance of
+/// the tx are run: well-formed inputs, outputs and addresses, canonical
+/// values, a consistent validity interval and the like. Fees, witnesses and
+/// everything else tied to the spent outputs are left out, so a tx passing
+/// this may still fail [Validator::validate], but never the other way round.
+pub fn validate_structure(metx: &MultiEraTx, env: &Environment) -> ValidationResult {
+    let mut validator = Validator::default();
+    validator
+        .rules
+        .retain(|(x, _)| STRUCTURAL_RULES.contains(x));
+
+    validator
+        .with_rule("byron_structure", |metx, _, env| {
+            match (metx.as_byron(), &env.prot_params) {
+                (Some(mtxp), ProtocolParams::Byron(prot_pps)) => {
+                    check_byron_structure(mtxp, prot_pps)
+                }
+                _ => Ok(()),
+            }
+        })
+        .validate(metx, &UTxOs::new(), env)
+}
+
+/// Validates a tx with the default [Validator] against just the outputs it
+/// resolves, given as pairs of output reference and output
+///
+/// Every input of the tx, including collateral and reference inputs, must be
+/// among the pairs; the first one which isn't fails with
+/// [ValidationError::InputNotInUTxO].
+pub fn validate_with_resolved<'b>(
+    metx: &MultiEraTx,
+    resolved: &[(OutputRef, MultiEraOutput<'b>)],
+    env: &Environment,
+) -> ValidationResult {
+    let utxos: UTxOs<'b> = resolved.iter().cloned().collect();
+
+    let missing = metx
+        .inputs()
+        .into_iter()
+        .chain(metx.collateral())
+        .chain(metx.reference_inputs())
+        .filter_map(|x| input_key(&x))
+        .find(|x| !utxos.contains_key(x));
+
+    match missing {
+        Some(output_ref) => Err(ValidationError::InputNotInUTxO(output_ref)),
+        None => Validator::default().validate(metx, &utxos, env),
+    }
+}
+
+/// Validates a tx with the default [Validator] against each of the given UTxO
+/// sets, one per candidate fork
+///
+/// Every set is taken as the whole UTxO of its fork: inputs are resolved in
+/// that set alone, never falling back to the others, so an input produced on
+/// just one fork fails with [ValidationError::InputNotInUTxO] on the rest.
+/// Results come in the order of `utxo_sets`, and none of the sets is changed.
+pub fn validate_against_forks(
+    metx: &MultiEraTx,
+    utxo_sets: &[&UTxOs],
+    env: &Environment,
+) -> Vec<ValidationResult> {
+    let validator = Validator::default();
+
+    utxo_sets
+        .iter()
+        .map(|utxos| validator.validate(metx, utxos, env))
+        .collect()
+}
+
+/// Decodes a tx of the given era and validates it with the default
+/// [Validator]
+///
+/// Bytes which don't decode as a tx of the era fail with
+/// [ValidationError::Decode]; no input, however malformed, makes this panic.
+pub fn validate_tx_bytes(
+    era: Era,
+    bytes: &[u8],
+    utxos: &UTxOs,
+    env: &Environment,
+) -> ValidationResult {
+    let metx = MultiEraTx::decode(era, bytes)
+        .map_err(|error| ValidationError::Decode(error.to_string()))?;
+
+    Validator::default().validate(&metx, utxos, env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::{
+            BabbageProtParams, ByronProtParams, GenesisDelegates, ShelleyProtParams, Strictness,
+        },
+        fixtures::*,
+    };
+
+    use std::collections::BTreeMap;
+
+    use pallas_codec::minicbor;
+    use pallas_crypto::hash::{Hash, Hasher};
+    use pallas_primitives::{
+        alonzo::{ExUnits, PlutusData, RationalNumber},
+        babbage::{self, CostMdls, PseudoDatumOption},
+    };
+
+    const ENV: Environment = Environment {
+        prot_params: ProtocolParams::Shelley(ShelleyProtParams {
+            min_fee_a: 44,
+            min_fee_b: 155381,
+            max_tx_size: 16384,
+            key_deposit: 2_000_000,
+            pool_deposit: 500_000_000,
+            min_utxo_value: 1_000_000,
+        }),
+        protocol_version: (2, 0),
+        genesis_delegates: GenesisDelegates {
+            delegates: BTreeMap::new(),
+            quorum: 0,
+        },
+        slot: None,
+        strictness: Strictness::Lenient,
+    };
+
+    #[test]
+    fn custom_rule_set() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 2_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 2_000_000)],
+            0,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert!(matches!(
+            Validator::default().validate(&metx, &utxos, &ENV),
+            Err(ValidationError::FeesBelowMin { fee: 0, .. })
+        ));
+
+        let validator = Validator::default().without_rule("min_fee");
+        assert_eq!(validator.validate(&metx, &utxos, &ENV), Ok(()));
+
+        let validator = validator.with_rule("outputs", |_, _, _| Err(ValidationError::TxOutsEmpty));
+        assert_eq!(
+            validator.validate(&metx, &utxos, &ENV),
+            Err(ValidationError::TxOutsEmpty)
+        );
+    }
+
+    #[test]
+    fn duplicate_input() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        // the one input listed twice would fund twice its amount
+        let body = alonzo_body(
+            vec![tx_input(1, 0), tx_input(1, 0)],
+            vec![alonzo_output(&address, 9_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            Validator::default().validate(&metx, &utxos, &ENV),
+            Err(ValidationError::DuplicateInput(OutputRef::new(
+                tx_input(1, 0).transaction_id,
+                0
+            )))
+        );
+        assert_eq!(
+            validate_structure(&metx, &ENV),
+            Err(ValidationError::DuplicateInput(OutputRef::new(
+                tx_input(1, 0).transaction_id,
+                0
+            )))
+        );
+    }
+
+    #[test]
+    fn custom_verifier() {
+        struct Rejecting;
+
+        impl SignatureVerifier for Rejecting {
+            fn verify(&self, _: &[u8], _: &[u8], _: &[u8]) -> bool {
+                false
+            }
+        }
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(Validator::default().validate(&metx, &utxos, &ENV), Ok(()));
+        assert_eq!(
+            Validator::default()
+                .with_verifier(Rejecting)
+                .validate(&metx, &utxos, &ENV),
+            Err(ValidationError::WrongSignature(key_hash(&secret_key(1))))
+        );
+        assert_eq!(
+            Validator::default()
+                .without_rule("signatures")
+                .with_verifier(Rejecting)
+                .validate(&metx, &utxos, &ENV),
+            Ok(())
+        );
+
+        let byron_env = Environment {
+            prot_params: ProtocolParams::Byron(ByronProtParams {
+                summand: 155381,
+                multiplier: 44,
+                max_tx_size: 4096,
+                max_tx_outputs: None,
+                protocol_magic: 764824073,
+            }),
+            protocol_version: (1, 0),
+            ..ENV
+        };
+
+        let inputs = vec![byron_input(1, 0)];
+        let utxos = byron_utxos(&[(
+            inputs[0].clone(),
+            byron_key_output(&secret_key(1), 2_000_000),
+        )]);
+
+        let bytes = byron_signed_tx(inputs, vec![byron_output(&[], 1_800_000)], &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Byron, &bytes).unwrap();
+
+        assert_eq!(
+            Validator::default().validate(&metx, &utxos, &byron_env),
+            Ok(())
+        );
+        assert!(matches!(
+            Validator::default()
+                .with_verifier(Rejecting)
+                .validate(&metx, &utxos, &byron_env),
+            Err(ValidationError::WrongSignature(_))
+        ));
+    }
+
+    #[test]
+    fn resolved_outputs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let resolved: Vec<_> =
+            alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))])
+                .into_iter()
+                .collect();
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(validate_with_resolved(&metx, &resolved, &ENV), Ok(()));
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0), tx_input(2, 1)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            validate_with_resolved(&metx, &resolved, &ENV),
+            Err(ValidationError::InputNotInUTxO(OutputRef::new(
+                tx_input(2, 1).transaction_id,
+                1
+            )))
+        );
+    }
+
+    #[test]
+    fn structure_only() {
+        let address = key_address(key_hash(&secret_key(1)));
+
+        // fine structurally, although nothing resolves its input nor pays for
+        // its fee
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            0,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(validate_structure(&metx, &ENV), Ok(()));
+
+        let body = alonzo_body(vec![], vec![alonzo_output(&address, 4_800_000)], 0);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            validate_structure(&metx, &ENV),
+            Err(ValidationError::TxInsEmpty)
+        );
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&[0x61, 0x00], 4_800_000)],
+            0,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            validate_structure(&metx, &ENV),
+            Err(ValidationError::OutputAddressMalformed(0))
+        );
+
+        // every structural rule is one of the default rules
+        let names = Validator::default().rule_names();
+        assert!(STRUCTURAL_RULES.iter().all(|x| names.contains(x)));
+    }
+
+    #[test]
+    fn forks() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let left = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+        let right = alonzo_utxos(&[(tx_input(2, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            validate_against_forks(&metx, &[&left, &right], &ENV),
+            vec![
+                Ok(()),
+                Err(ValidationError::InputNotInUTxO(OutputRef::new(
+                    tx_input(1, 0).transaction_id,
+                    0
+                )))
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_bytes() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+
+        assert_eq!(
+            validate_tx_bytes(Era::Shelley, &bytes, &utxos, &ENV),
+            Ok(())
+        );
+        assert!(matches!(
+            validate_tx_bytes(Era::Shelley, &bytes[..bytes.len() - 1], &utxos, &ENV),
+            Err(ValidationError::Decode(_))
+        ));
+
+        // a cheap stand-in for the fuzz target: truncations and single byte
+        // corruptions of a valid tx, decoded as either era, never panic
+        for era in [Era::Byron, Era::Shelley] {
+            for end in 0..bytes.len() {
+                let _ = validate_tx_bytes(era, &bytes[..end], &utxos, &ENV);
+            }
+
+            for index in 0..bytes.len() {
+                for x in [0x00, 0x1b, 0x5f, 0x9f, 0xbf, 0xff] {
+                    let mut corrupted = bytes.clone();
+                    corrupted[index] = x;
+                    let _ = validate_tx_bytes(era, &corrupted, &utxos, &ENV);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validation_leaves_utxos_untouched() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+        let snapshot: Vec<_> = utxos.iter().map(|(k, v)| (k.clone(), v.encode())).collect();
+
+        let body = alonzo_body(
+            vec![tx_input(1, 0)],
+            vec![alonzo_output(&address, 4_800_000)],
+            200_000,
+        );
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        // the same tx validates twice, as nothing got spent the first time
+        assert_eq!(Validator::default().validate(&metx, &utxos, &ENV), Ok(()));
+        assert_eq!(Validator::default().validate(&metx, &utxos, &ENV), Ok(()));
+
+        let after: Vec<_> = utxos.iter().map(|(k, v)| (k.clone(), v.encode())).collect();
+        assert_eq!(after, snapshot);
+        assert!(!utxos.contains_key(&OutputRef::new(metx.hash(), 0)));
+    }
+
+    fn babbage_env() -> Environment {
+        Environment {
+            prot_params: ProtocolParams::Babbage(BabbageProtParams {
+                min_fee_a: 44,
+                min_fee_b: 155381,
+                max_tx_size: 16384,
+                key_deposit: 2_000_000,
+                pool_deposit: 500_000_000,
+                mem_price: RationalNumber {
+                    numerator: 577,
+                    denominator: 10000,
+                },
+                step_price: RationalNumber {
+                    numerator: 721,
+                    denominator: 10000000,
+                },
+                max_block_ex_units: ExUnits {
+                    mem: 62_000_000,
+                    steps: 40_000_000_000,
+                },
+                max_collateral_inputs: 3,
+                collateral_percentage: 150,
+                coins_per_utxo_byte: 4310,
+                cost_models: CostMdls {
+                    plutus_v1: None,
+                    plutus_v2: None,
+                },
+            }),
+            protocol_version: (7, 0),
+            ..ENV
+        }
+    }
+
+    #[test]
+    fn babbage_balance() {
+        let env = babbage_env();
+
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let check = |coin| {
+            let body = babbage_body(
+                vec![tx_input(1, 0)],
+                vec![babbage_output(&address, coin)],
+                200_000,
+            );
+            let bytes = babbage_tx(body, &[secret_key(1)]);
+            let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+            Validator::default().validate(&metx, &utxos, &env)
+        };
+
+        assert_eq!(check(4_800_000), Ok(()));
+        assert_eq!(
+            check(4_900_000),
+            Err(ValidationError::OutputsExceedInputs {
+                inputs: 5_000_000,
+                outputs: 5_100_000
+            })
+        );
+        assert_eq!(
+            check(4_700_000),
+            Err(ValidationError::ValueNotConserved(5_000_000, 4_900_000))
+        );
+    }
+
+    #[test]
+    fn strict_datums_on_key_addresses() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let output = babbage::TransactionOutput::PostAlonzo(babbage::PostAlonzoTransactionOutput {
+            address: address.clone().into(),
+            value: babbage::Value::Coin(4_800_000),
+            datum_option: Some(PseudoDatumOption::Hash(Hash::new([7; 32]))),
+            script_ref: None,
+        });
+        let body = babbage_body(vec![tx_input(1, 0)], vec![output], 200_000);
+        let bytes = babbage_tx(body, &[secret_key(1)]);
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        let lenient = babbage_env();
+        let strict = Environment {
+            strictness: Strictness::Strict,
+            ..babbage_env()
+        };
+
+        assert_eq!(
+            Validator::default().validate(&metx, &utxos, &lenient),
+            Ok(())
+        );
+        assert_eq!(
+            Validator::default().validate(&metx, &utxos, &strict),
+            Err(ValidationError::DatumOnKeyAddress(0))
+        );
+    }
+
+    #[test]
+    fn strict_orphan_datums() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let orphan = PlutusData::BoundedBytes(vec![2].into());
+        let orphan_hash = Hasher::<256>::hash_cbor(&orphan);
+
+        let body = babbage_body(
+            vec![tx_input(1, 0)],
+            vec![babbage_output(&address, 4_800_000)],
+            200_000,
+        );
+        let mut tx: babbage::Tx = minicbor::decode(&babbage_tx(body, &[secret_key(1)])).unwrap();
+        tx.transaction_witness_set.plutus_data = Some(vec![orphan]);
+        let bytes = minicbor::to_vec(&tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Babbage, &bytes).unwrap();
+
+        let strict = Environment {
+            strictness: Strictness::Strict,
+            ..babbage_env()
+        };
+
+        // the datum changes the script data hash, which is left out here
+        let validator = Validator::default().without_rule("script_data_hash");
+
+        assert_eq!(validator.validate(&metx, &utxos, &babbage_env()), Ok(()));
+        assert_eq!(
+            validator.validate(&metx, &utxos, &strict),
+            Err(ValidationError::OrphanDatum(orphan_hash))
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Amounts of lovelace and native assets handled by the balance checks
+
+use std::collections::BTreeMap;
+
+use pallas_crypto::hash::Hash;
+use pallas_primitives::alonzo::Certificate;
+use pallas_traverse::{MultiEraOutput, MultiEraTx};
+
+use crate::{UtxoStore, ValidationError, ValidationResult};
+
+/// Quantity of each native asset, keyed by policy id and asset name
+pub type Assets = BTreeMap<(Hash<28>, Vec<u8>), u64>;
+
+/// An amount of lovelace together with an amount of native assets
+///
+/// Assets with a zero quantity are never stored, so two values holding the
+/// same amounts always compare equal. Byron values are coin-only.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Value {
+    coin: u64,
+    assets: Assets,
+}
+
+impl Value {
+    pub fn from_coin(coin: u64) -> Self {
+        Self {
+            coin,
+            assets: Assets::new(),
+        }
+    }
+
+    pub fn from_output(output: &MultiEraOutput) -> Self {
+        let mut value = Self::from_coin(output.lovelace_amount());
+
+        for policy in output.non_ada_assets() {
+            for asset in policy.assets() {
+                let quantity = asset.output_coin().unwrap_or_default();
+                value = value.with_asset(*asset.policy(), asset.name().to_vec(), quantity);
+            }
+        }
+
+        value
+    }
+
+    /// Adds `quantity` units of an asset, saturating on overflow
+    ///
+    /// Only meant for building values out of a single output or mint entry,
+    /// where an asset can't appear twice; use [Value::checked_add] otherwise.
+    pub fn with_asset(mut self, policy: Hash<28>, name: Vec<u8>, quantity: u64) -> Self {
+        if quantity > 0 {
+            let entry = self.assets.entry((policy, name)).or_default();
+            *entry = entry.saturating_add(quantity);
+        }
+
+        self
+    }
+
+    pub fn coin(&self) -> u64 {
+        self.coin
+    }
+
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    /// Adds two values, returning `None` if any of the amounts overflows
+    pub fn checked_add(&self, other: &Value) -> Option<Value> {
+        let mut result = self.clone();
+        result.coin = self.coin.checked_add(other.coin)?;
+
+        for (key, quantity) in other.assets.iter() {
+            let entry = result.assets.entry(key.clone()).or_default();
+            *entry = entry.checked_add(*quantity)?;
+        }
+
+        Some(result)
+    }
+
+    /// Subtracts a value, returning `None` if any of the amounts would end up
+    /// negative
+    pub fn checked_sub(&self, other: &Value) -> Option<Value> {
+        let mut result = self.clone();
+        result.coin = self.coin.checked_sub(other.coin)?;
+
+        for (key, quantity) in other.assets.iter() {
+            let remaining = result.assets.get(key).copied().unwrap_or_default();
+
+            match remaining.checked_sub(*quantity)? {
+                0 => result.assets.remove(key),
+                x => result.assets.insert(key.clone(), x),
+            };
+        }
+
+        Some(result)
+    }
+}
+
+/// Checks that every output holds some lovelace, and at least the minimum
+/// returned by `min_lovelace` for outputs from Shelley onwards
+///
+/// Byron outputs only need to be non-empty, failing with
+/// [ValidationError::OutputWithoutLovelace] otherwise, while the rest fail
+/// with [ValidationError::OutputTooSmall] below the greater of one lovelace
+/// and their minimum.
+pub fn check_output_values<'a>(
+    outputs: impl IntoIterator<Item = MultiEraOutput<'a>>,
+    min_lovelace: impl Fn(&MultiEraOutput) -> u64,
+) -> ValidationResult {
+    for (index, output) in outputs.into_iter().enumerate() {
+        let coin = Value::from_output(&output).coin();
+
+        if let MultiEraOutput::Byron(_) = output {
+            match coin {
+                0 => return Err(ValidationError::OutputWithoutLovelace(index)),
+                _ => continue,
+            }
+        }
+
+        let required = min_lovelace(&output).max(1);
+
+        if coin < required {
+            return Err(ValidationError::OutputTooSmall(coin, required));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the lovelace of the outputs plus the fee doesn't exceed the
+/// lovelace of the inputs plus the withdrawals and deposit refunds
+///
+/// This is the lovelace side of the preservation of value relaxed to an
+/// inequality, failing early with [ValidationError::OutputsExceedInputs]
+/// before the exact balance is reconciled. Refunds are priced at
+/// `key_deposit`, as otherwise deregistering would look like lovelace out of
+/// thin air. Txs failing phase-2 validation don't spend their inputs, so they
+/// are left out.
+pub fn check_outputs_within_inputs<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    key_deposit: u64,
+) -> ValidationResult {
+    if !metx.is_valid() {
+        return Ok(());
+    }
+
+    let add = |total: Value, coin: u64| {
+        total
+            .checked_add(&Value::from_coin(coin))
+            .ok_or(ValidationError::ValueOverflow)
+    };
+
+    let mut inputs = Value::default();
+
+    for input in metx.inputs() {
+        let output = utxos
+            .resolve(&input.output_ref())
+            .ok_or_else(|| ValidationError::InputNotInUTxO(input.output_ref()))?;
+
+        inputs = add(inputs, Value::from_output(&output).coin())?;
+    }
+
+    for (_, amount) in metx.withdrawals().collect::<Vec<_>>() {
+        inputs = add(inputs, amount)?;
+    }
+
+    for cert in metx.certs() {
+        if let Some(Certificate::StakeDeregistration(_)) = cert.as_alonzo() {
+            inputs = add(inputs, key_deposit)?;
+        }
+    }
+
+    let mut outputs = Value::from_coin(metx.fee().unwrap_or_default());
+
+    for output in metx.outputs() {
+        outputs = add(outputs, Value::from_output(&output).coin())?;
+    }
+
+    match outputs.coin() <= inputs.coin() {
+        true => Ok(()),
+        false => Err(ValidationError::OutputsExceedInputs {
+            inputs: inputs.coin(),
+            outputs: outputs.coin(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_codec::minicbor;
+    use pallas_primitives::{alonzo::StakeCredential, babbage::MintedTransactionOutput};
+    use pallas_traverse::Era;
+
+    fn token(quantity: u64) -> Value {
+        Value::from_coin(0).with_asset(Hash::new([1; 28]), b"token".to_vec(), quantity)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Value::from_coin(10).checked_add(&token(5)).unwrap();
+        let b = Value::from_coin(4).checked_add(&token(5)).unwrap();
+
+        assert_eq!(a.checked_sub(&b), Some(Value::from_coin(6)));
+        assert_eq!(b.checked_sub(&a), None);
+        assert_eq!(a.checked_sub(&token(6)), None);
+        assert_eq!(Value::from_coin(u64::MAX).checked_add(&a), None);
+    }
+
+    #[test]
+    fn outputs_within_inputs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let check = |coin, refunded| {
+            let mut body = alonzo_body(
+                vec![tx_input(1, 0)],
+                vec![alonzo_output(&address, coin)],
+                200_000,
+            );
+
+            if refunded {
+                body.certificates = Some(vec![Certificate::StakeDeregistration(
+                    StakeCredential::AddrKeyhash(key_hash(&secret_key(1))),
+                )]);
+            }
+
+            let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+            let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+            check_outputs_within_inputs(&metx, &utxos, 2_000_000)
+        };
+
+        assert_eq!(check(4_800_000, false), Ok(()));
+        assert_eq!(
+            check(4_800_001, false),
+            Err(ValidationError::OutputsExceedInputs {
+                inputs: 5_000_000,
+                outputs: 5_000_001
+            })
+        );
+
+        assert_eq!(check(6_800_000, true), Ok(()));
+        assert_eq!(
+            check(6_800_001, true),
+            Err(ValidationError::OutputsExceedInputs {
+                inputs: 7_000_000,
+                outputs: 7_000_001
+            })
+        );
+    }
+
+    #[test]
+    fn output_values_across_eras() {
+        let address = key_address(key_hash(&secret_key(1)));
+
+        let byron = |amount| byron_output(&[], amount);
+        let shelley = |coin| alonzo_output(&address, coin);
+        let babbage = |coin| minicbor::to_vec(babbage_output(&address, coin)).unwrap();
+
+        let byron_outputs = [byron(1), byron(0)];
+        let shelley_outputs = [shelley(1), shelley(0)];
+        let babbage_outputs = [babbage(1_000_000), babbage(999_999)];
+        let babbage_outputs: Vec<MintedTransactionOutput> = babbage_outputs
+            .iter()
+            .map(|x| minicbor::decode(x).unwrap())
+            .collect();
+
+        // the minimum only applies from Shelley onwards
+        let cases: [(Vec<MultiEraOutput>, u64, ValidationError); 4] = [
+            (
+                byron_outputs
+                    .iter()
+                    .map(MultiEraOutput::from_byron)
+                    .collect(),
+                0,
+                ValidationError::OutputWithoutLovelace(1),
+            ),
+            (
+                byron_outputs
+                    .iter()
+                    .map(MultiEraOutput::from_byron)
+                    .collect(),
+                1_000_000,
+                ValidationError::OutputWithoutLovelace(1),
+            ),
+            (
+                shelley_outputs
+                    .iter()
+                    .map(MultiEraOutput::from_alonzo_compatible)
+                    .collect(),
+                0,
+                ValidationError::OutputTooSmall(0, 1),
+            ),
+            (
+                babbage_outputs
+                    .iter()
+                    .map(MultiEraOutput::from_babbage)
+                    .collect(),
+                1_000_000,
+                ValidationError::OutputTooSmall(999_999, 1_000_000),
+            ),
+        ];
+
+        for (outputs, min, error) in cases {
+            assert_eq!(
+                check_output_values(outputs[..1].iter().cloned(), |_| min),
+                Ok(())
+            );
+            assert_eq!(check_output_values(outputs, |_| min), Err(error));
+        }
+    }
+}
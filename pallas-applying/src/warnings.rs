@@ -0,0 +1,93 @@
+//! Advisory diagnostics reported alongside the validation result
+
+use pallas_traverse::MultiEraTx;
+
+use crate::{
+    environment::ShelleyProtParams,
+    shelley_ma::{compute_min_fee, validate_shelley_ma_tx},
+    UtxoStore, ValidationResult, ValidationWarning,
+};
+
+/// Fees above this multiple of the minimum are reported as excessive
+const FEE_WARNING_FACTOR: u64 = 2;
+
+/// Validates a transaction, also collecting the warnings it raises
+///
+/// The result is the same one returned by
+/// [validate_shelley_ma_tx](crate::shelley_ma::validate_shelley_ma_tx);
+/// warnings are collected whether the transaction is valid or not.
+pub fn validate_with_warnings<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+    prot_pps: &ShelleyProtParams,
+    slot: Option<u64>,
+) -> (ValidationResult, Vec<ValidationWarning>) {
+    let result = validate_shelley_ma_tx(metx, utxos, prot_pps, slot);
+
+    let mut warnings = Vec::new();
+
+    let fee = metx.fee().unwrap_or_default();
+
+    if let Ok(min_fee) = compute_min_fee(metx, prot_pps) {
+        if fee > min_fee.saturating_mul(FEE_WARNING_FACTOR) {
+            warnings.push(ValidationWarning::FeeFarAboveMin { fee, min_fee });
+        }
+    }
+
+    (result, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::*;
+
+    use pallas_traverse::Era;
+
+    const PROT_PPS: ShelleyProtParams = ShelleyProtParams {
+        min_fee_a: 44,
+        min_fee_b: 155381,
+        max_tx_size: 16384,
+        key_deposit: 2_000_000,
+        pool_deposit: 500_000_000,
+        min_utxo_value: 1_000_000,
+    };
+
+    #[test]
+    fn valid_with_excessive_fee() {
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 4_000_000);
+        let body = alonzo_body(inputs, vec![output], 1_000_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        let (result, warnings) = validate_with_warnings(&metx, &utxos, &PROT_PPS, None);
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            warnings,
+            vec![ValidationWarning::FeeFarAboveMin {
+                fee: 1_000_000,
+                min_fee: compute_min_fee(&metx, &PROT_PPS).unwrap()
+            }]
+        );
+    }
+
+    #[test]
+    fn no_warnings() {
+        let inputs = vec![tx_input(1, 0)];
+        let utxos = alonzo_utxos(&[(inputs[0].clone(), alonzo_output(&[], 5_000_000))]);
+
+        let output = alonzo_output(&key_address(key_hash(&secret_key(2))), 4_800_000);
+        let body = alonzo_body(inputs, vec![output], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            validate_with_warnings(&metx, &utxos, &PROT_PPS, None),
+            (Ok(()), vec![])
+        );
+    }
+}
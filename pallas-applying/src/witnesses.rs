@@ -0,0 +1,461 @@
+//! Verification of the signatures carried in the witness set of a transaction
+
+use std::collections::{BTreeSet, HashSet};
+
+use pallas_addresses::{Address, ShelleyPaymentPart, StakePayload};
+use pallas_codec::minicbor;
+use pallas_crypto::{
+    hash::{Hash, Hasher},
+    key::ed25519::{PublicKey, Signature},
+};
+use pallas_primitives::{
+    alonzo::{Certificate, StakeCredential},
+    byron,
+};
+use pallas_traverse::{ComputeHash, MultiEraTx};
+
+use crate::{
+    byron::{address_root, split_addr_payload, ADDR_TYPE_PUBKEY},
+    native_script::{eval_native_script, NativeScriptContext},
+    UtxoStore, ValidationError, ValidationResult,
+};
+
+/// A backend verifying Ed25519 signatures
+///
+/// Implementations are free to batch, cache or offload the work, as long as
+/// they accept exactly the signatures [Ed25519Verifier] accepts. Malformed
+/// keys or signatures must be rejected, not panicked upon.
+pub trait SignatureVerifier {
+    fn verify(&self, pubkey: &[u8], msg: &[u8], sig: &[u8]) -> bool;
+}
+
+/// The default backend, verifying one signature at a time with `pallas_crypto`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ed25519Verifier;
+
+impl SignatureVerifier for Ed25519Verifier {
+    fn verify(&self, pubkey: &[u8], msg: &[u8], sig: &[u8]) -> bool {
+        match (PublicKey::try_from(pubkey), Signature::try_from(sig)) {
+            (Ok(public_key), Ok(signature)) => public_key.verify(msg, &signature),
+            _ => false,
+        }
+    }
+}
+
+/// Verifies every vkey and bootstrap witness signature over the body hash
+///
+/// Only the signatures themselves are checked, not whether the keys are the
+/// ones required by the inputs, so no UTxO set is needed. The offending
+/// witness is reported by the hash of its public key. Byron witnesses sign
+/// over the protocol magic and are not covered here.
+pub fn verify_tx_witnesses(metx: &MultiEraTx) -> ValidationResult {
+    verify_tx_witnesses_with(metx, &Ed25519Verifier)
+}
+
+/// Same as [verify_tx_witnesses], with the signatures verified by the given
+/// backend
+pub fn verify_tx_witnesses_with(
+    metx: &MultiEraTx,
+    verifier: &impl SignatureVerifier,
+) -> ValidationResult {
+    let body_hash = metx.hash();
+
+    let vkey_witnesses = metx
+        .vkey_witnesses()
+        .iter()
+        .map(|wit| (&wit.vkey, &wit.signature));
+
+    let bootstrap_witnesses = metx
+        .bootstrap_witnesses()
+        .iter()
+        .map(|wit| (&wit.public_key, &wit.signature));
+
+    for (vkey, signature) in vkey_witnesses.chain(bootstrap_witnesses) {
+        if !verifier.verify(vkey, body_hash.as_ref(), signature) {
+            return Err(ValidationError::WrongSignature(Hasher::<224>::hash(vkey)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that each input comes with the kind of witness its resolved output
+/// calls for
+///
+/// An output at a Byron address, be it stored as a Byron output or as one of
+/// a later era, can only be spent with a bootstrap witness whose key and
+/// attributes derive that address. An output at a Shelley key address takes a
+/// vkey witness instead, so having only a bootstrap witness for its key hash
+/// means the wrong verification path. Inputs missing from the UTxO set, or
+/// whose address doesn't parse, are left for other checks.
+pub fn check_input_witness_eras<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> ValidationResult {
+    let vkey_signers: Vec<_> = metx
+        .vkey_witnesses()
+        .iter()
+        .map(|x| Hasher::<224>::hash(&x.vkey))
+        .collect();
+
+    let bootstrap_witnesses = metx.bootstrap_witnesses();
+
+    for input in metx.inputs() {
+        let output_ref = input.output_ref();
+
+        let witnessed = match utxos.resolve(&output_ref).map(|x| x.address()) {
+            Some(Ok(Address::Byron(address))) => {
+                let address: byron::Address = match minicbor::decode(&address.to_vec()) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+
+                let root = match split_addr_payload(&address.payload.0) {
+                    Ok((root, _)) => root.to_vec(),
+                    Err(_) => continue,
+                };
+
+                bootstrap_witnesses.iter().any(|x| {
+                    let xpub = [x.public_key.as_slice(), x.chain_code.as_slice()].concat();
+                    address_root(ADDR_TYPE_PUBKEY, &xpub, &x.attributes).as_ref() == root
+                })
+            }
+            Some(Ok(Address::Shelley(address))) => match address.payment() {
+                ShelleyPaymentPart::Key(hash) => {
+                    vkey_signers.contains(hash)
+                        || !bootstrap_witnesses
+                            .iter()
+                            .any(|x| Hasher::<224>::hash(&x.public_key) == *hash)
+                }
+                ShelleyPaymentPart::Script(_) => true,
+            },
+            _ => true,
+        };
+
+        if !witnessed {
+            return Err(ValidationError::InputEraWitnessMismatch(output_ref));
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the key hashes a transaction requires a vkey witness of but carries
+/// none for, in ascending order
+///
+/// Keys are required by the inputs and collateral inputs at Shelley key
+/// addresses, the withdrawals from key reward accounts, the certificates
+/// deregistering or delegating a key credential, registering a pool (its
+/// operator and owners) or retiring one (its operator), and the required
+/// signers of the body. Inputs at Byron addresses take a bootstrap witness
+/// instead, which is up to [check_input_witness_eras]. Genesis delegation and
+/// MIR certificates aren't covered. Inputs missing from the UTxO set fail with
+/// [ValidationError::InputNotInUTxO].
+pub fn missing_vkey_witnesses<'b>(
+    metx: &MultiEraTx,
+    utxos: &impl UtxoStore<'b>,
+) -> Result<Vec<Hash<28>>, ValidationError> {
+    let mut required = BTreeSet::new();
+
+    for input in metx.inputs().into_iter().chain(metx.collateral()) {
+        let output = utxos
+            .resolve(&input.output_ref())
+            .ok_or_else(|| ValidationError::InputNotInUTxO(input.output_ref()))?;
+
+        if let Ok(Address::Shelley(address)) = output.address() {
+            if let ShelleyPaymentPart::Key(hash) = address.payment() {
+                required.insert(*hash);
+            }
+        }
+    }
+
+    for (account, _) in metx.withdrawals().collect::<Vec<_>>() {
+        if let Ok(Address::Stake(address)) = Address::from_bytes(account) {
+            if let StakePayload::Stake(hash) = address.payload() {
+                required.insert(*hash);
+            }
+        }
+    }
+
+    for cert in metx.certs() {
+        match cert.as_alonzo() {
+            Some(Certificate::StakeDeregistration(StakeCredential::AddrKeyhash(hash)))
+            | Some(Certificate::StakeDelegation(StakeCredential::AddrKeyhash(hash), _))
+            | Some(Certificate::PoolRetirement(hash, _)) => {
+                required.insert(*hash);
+            }
+            Some(Certificate::PoolRegistration {
+                operator,
+                pool_owners,
+                ..
+            }) => {
+                required.insert(*operator);
+                required.extend(pool_owners.iter().copied());
+            }
+            _ => (),
+        }
+    }
+
+    required.extend(
+        metx.required_signers()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .copied(),
+    );
+
+    let signers: HashSet<_> = metx
+        .vkey_witnesses()
+        .iter()
+        .map(|x| Hasher::<224>::hash(&x.vkey))
+        .collect();
+
+    Ok(required
+        .into_iter()
+        .filter(|x| !signers.contains(x))
+        .collect())
+}
+
+/// Checks that the transaction carries a vkey witness for every key it
+/// requires, failing with [ValidationError::MissingVKeyWitness] for the first
+/// of the [missing_vkey_witnesses]
+///
+/// Whether the signatures verify is left for [verify_tx_witnesses].
+pub fn check_vkey_witnesses<'b>(metx: &MultiEraTx, utxos: &impl UtxoStore<'b>) -> ValidationResult {
+    match missing_vkey_witnesses(metx, utxos)?.first() {
+        Some(hash) => Err(ValidationError::MissingVKeyWitness(*hash)),
+        None => Ok(()),
+    }
+}
+
+/// Checks that every withdrawal is authorized by the stake credential of its
+/// reward account
+///
+/// A key credential needs a vkey witness of that key, while a script one needs
+/// a native script with that hash evaluating successfully, or a Plutus script
+/// with that hash (which is left for phase-2 validation).
+pub fn check_withdrawals(metx: &MultiEraTx) -> ValidationResult {
+    let context = NativeScriptContext::from_tx(metx);
+
+    for (account, _) in metx.withdrawals().collect::<Vec<_>>() {
+        let authorized = match Address::from_bytes(account) {
+            Ok(Address::Stake(address)) => match address.payload() {
+                StakePayload::Stake(hash) => context.signers.contains(hash),
+                StakePayload::Script(hash) => is_script_satisfied(metx, hash, &context),
+            },
+            _ => false,
+        };
+
+        if !authorized {
+            return Err(ValidationError::WithdrawalNotAuthorized {
+                account: account.to_vec(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn is_script_satisfied(
+    metx: &MultiEraTx,
+    hash: &Hash<28>,
+    context: &NativeScriptContext,
+) -> bool {
+    let native = metx
+        .native_scripts()
+        .iter()
+        .filter(|x| x.compute_hash() == *hash)
+        .any(|x| eval_native_script(x, context).is_ok());
+
+    let plutus = metx
+        .plutus_v1_scripts()
+        .iter()
+        .map(|x| x.compute_hash())
+        .chain(metx.plutus_v2_scripts().iter().map(|x| x.compute_hash()))
+        .any(|x| x == *hash);
+
+    native || plutus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::*, UTxOs};
+
+    use pallas_codec::{minicbor, utils::KeyValuePairs};
+    use pallas_crypto::key::ed25519::SecretKey;
+    use pallas_primitives::alonzo::{BootstrapWitness, NativeScript, Tx};
+    use pallas_traverse::{Era, OutputRef};
+
+    #[test]
+    fn signatures_over_body() {
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1), secret_key(2)], vec![]);
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(verify_tx_witnesses(&metx), Ok(()));
+    }
+
+    #[test]
+    fn body_changed_after_signing() {
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![]);
+
+        let mut tx: Tx = minicbor::decode(&bytes).unwrap();
+        tx.transaction_body.fee = 100_000;
+        let bytes = minicbor::to_vec(tx).unwrap();
+        let metx = MultiEraTx::decode(Era::Mary, &bytes).unwrap();
+
+        assert_eq!(
+            verify_tx_witnesses(&metx),
+            Err(ValidationError::WrongSignature(key_hash(&secret_key(1))))
+        );
+    }
+
+    #[test]
+    fn withdrawal_authorization() {
+        let key_account = [&[0xe0], key_hash(&secret_key(1)).as_ref()].concat();
+
+        let script = NativeScript::ScriptPubkey(key_hash(&secret_key(2)));
+        let script_account = [&[0xf0], script.compute_hash().as_ref()].concat();
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.withdrawals = Some(KeyValuePairs::from(vec![
+            (key_account.clone().into(), 1_000_000),
+            (script_account.clone().into(), 1_000_000),
+        ]));
+
+        let bytes = alonzo_tx(
+            body.clone(),
+            &[secret_key(1), secret_key(2)],
+            vec![script.clone()],
+        );
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(check_withdrawals(&metx), Ok(()));
+
+        let bytes = alonzo_tx(body.clone(), &[secret_key(2)], vec![script.clone()]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_withdrawals(&metx),
+            Err(ValidationError::WithdrawalNotAuthorized {
+                account: key_account
+            })
+        );
+
+        let bytes = alonzo_tx(body, &[secret_key(1)], vec![script]);
+        let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+
+        assert_eq!(
+            check_withdrawals(&metx),
+            Err(ValidationError::WithdrawalNotAuthorized {
+                account: script_account
+            })
+        );
+    }
+
+    fn with_bootstrap_witness(bytes: &[u8], key: &SecretKey) -> Vec<u8> {
+        let xpub = byron_xpub(key);
+
+        let mut tx: Tx = minicbor::decode(bytes).unwrap();
+        tx.transaction_witness_set.bootstrap_witness = Some(vec![BootstrapWitness {
+            public_key: xpub[..32].to_vec().into(),
+            signature: vec![0; 64].into(),
+            chain_code: xpub[32..].to_vec().into(),
+            attributes: vec![0xa0].into(),
+        }]);
+
+        minicbor::to_vec(&tx).unwrap()
+    }
+
+    #[test]
+    fn input_witness_eras() {
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let signed = alonzo_tx(body, &[secret_key(1)], vec![]);
+        let bootstrapped = with_bootstrap_witness(&signed, &secret_key(1));
+
+        let check = |bytes: &[u8], utxos: &UTxOs| {
+            let metx = MultiEraTx::decode(Era::Shelley, bytes).unwrap();
+            check_input_witness_eras(&metx, utxos)
+        };
+
+        let mismatch = Err(ValidationError::InputEraWitnessMismatch(OutputRef::new(
+            tx_input(1, 0).transaction_id,
+            0,
+        )));
+
+        // a Byron output needs a bootstrap witness deriving its address
+        let byron = byron_utxos(&[(byron_input(1, 0), byron_key_output(&secret_key(1), 1))]);
+
+        assert_eq!(check(&bootstrapped, &byron), Ok(()));
+        assert_eq!(check(&signed, &byron), mismatch);
+
+        // while a Shelley key address needs a vkey witness
+        let address = key_address(key_hash(&secret_key(1)));
+        let shelley = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 1))]);
+
+        assert_eq!(check(&signed, &shelley), Ok(()));
+
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let unsigned = alonzo_tx(body, &[], vec![]);
+        let bootstrapped = with_bootstrap_witness(&unsigned, &secret_key(1));
+
+        assert_eq!(check(&bootstrapped, &shelley), mismatch);
+    }
+
+    #[test]
+    fn unsigned_spend() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        let signed = alonzo_tx(body.clone(), &[secret_key(1)], vec![]);
+        let unsigned = alonzo_tx(body, &[], vec![]);
+
+        let check = |bytes: &[u8]| {
+            let metx = MultiEraTx::decode(Era::Shelley, bytes).unwrap();
+            check_vkey_witnesses(&metx, &utxos)
+        };
+
+        assert_eq!(check(&signed), Ok(()));
+        assert_eq!(
+            check(&unsigned),
+            Err(ValidationError::MissingVKeyWitness(key_hash(&secret_key(
+                1
+            ))))
+        );
+    }
+
+    #[test]
+    fn keys_required_by_withdrawals_and_certs() {
+        let address = key_address(key_hash(&secret_key(1)));
+        let utxos = alonzo_utxos(&[(tx_input(1, 0), alonzo_output(&address, 5_000_000))]);
+
+        let account = [&[0xe0], key_hash(&secret_key(2)).as_ref()].concat();
+        let credential = StakeCredential::AddrKeyhash(key_hash(&secret_key(3)));
+
+        let mut body = alonzo_body(vec![tx_input(1, 0)], vec![], 200_000);
+        body.withdrawals = Some(KeyValuePairs::from(vec![(account.into(), 1_000_000)]));
+        body.certificates = Some(vec![
+            Certificate::StakeRegistration(StakeCredential::AddrKeyhash(key_hash(&secret_key(4)))),
+            Certificate::StakeDeregistration(credential),
+        ]);
+
+        let check = |keys: &[SecretKey]| {
+            let bytes = alonzo_tx(body.clone(), keys, vec![]);
+            let metx = MultiEraTx::decode(Era::Shelley, &bytes).unwrap();
+            missing_vkey_witnesses(&metx, &utxos)
+        };
+
+        // registering a stake credential needs no witness of it
+        assert_eq!(
+            check(&[secret_key(1), secret_key(2), secret_key(3)]),
+            Ok(vec![])
+        );
+
+        let mut expected = vec![key_hash(&secret_key(2)), key_hash(&secret_key(3))];
+        expected.sort();
+
+        assert_eq!(check(&[secret_key(1)]), Ok(expected));
+    }
+}